@@ -0,0 +1,9 @@
+-- Add up migration script here
+
+-- 積算電力量(逆方向)
+CREATE TABLE IF NOT EXISTS reverse_cumlative_amount_epower(
+    id BIGSERIAL PRIMARY KEY,
+    location VARCHAR(255),
+    recorded_at TIMESTAMPTZ NOT NULL,
+    kwh NUMERIC NOT NULL
+);
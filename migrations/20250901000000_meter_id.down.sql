@@ -0,0 +1,9 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS instant_epower_meter_id_recorded_at;
+DROP INDEX IF EXISTS instant_current_meter_id_recorded_at;
+DROP INDEX IF EXISTS cumlative_amount_epower_meter_id_recorded_at;
+DROP INDEX IF EXISTS reverse_cumlative_amount_epower_meter_id_recorded_at;
+ALTER TABLE instant_epower DROP COLUMN meter_id;
+ALTER TABLE instant_current DROP COLUMN meter_id;
+ALTER TABLE cumlative_amount_epower DROP COLUMN meter_id;
+ALTER TABLE reverse_cumlative_amount_epower DROP COLUMN meter_id;
@@ -0,0 +1,19 @@
+-- Add up migration script here
+
+-- 計測したスマートメーター(settings.id)
+ALTER TABLE instant_epower ADD COLUMN meter_id BIGINT;
+ALTER TABLE instant_current ADD COLUMN meter_id BIGINT;
+ALTER TABLE cumlative_amount_epower ADD COLUMN meter_id BIGINT;
+ALTER TABLE reverse_cumlative_amount_epower ADD COLUMN meter_id BIGINT;
+
+-- 以前のレコードは最も新しい設定情報のスマートメーターで計測したものとする
+UPDATE instant_epower SET meter_id = (SELECT MAX(id) FROM settings) WHERE meter_id IS NULL;
+UPDATE instant_current SET meter_id = (SELECT MAX(id) FROM settings) WHERE meter_id IS NULL;
+UPDATE cumlative_amount_epower SET meter_id = (SELECT MAX(id) FROM settings) WHERE meter_id IS NULL;
+UPDATE reverse_cumlative_amount_epower SET meter_id = (SELECT MAX(id) FROM settings) WHERE meter_id IS NULL;
+
+-- スマートメーター毎に期間で絞り込む
+CREATE INDEX IF NOT EXISTS instant_epower_meter_id_recorded_at ON instant_epower (meter_id, recorded_at);
+CREATE INDEX IF NOT EXISTS instant_current_meter_id_recorded_at ON instant_current (meter_id, recorded_at);
+CREATE INDEX IF NOT EXISTS cumlative_amount_epower_meter_id_recorded_at ON cumlative_amount_epower (meter_id, recorded_at);
+CREATE INDEX IF NOT EXISTS reverse_cumlative_amount_epower_meter_id_recorded_at ON reverse_cumlative_amount_epower (meter_id, recorded_at);
@@ -0,0 +1,9 @@
+-- Add up migration script here
+
+-- 受信電波の品質(LQI)
+CREATE TABLE IF NOT EXISTS link_quality(
+    id BIGSERIAL PRIMARY KEY,
+    meter_id BIGINT,
+    recorded_at TIMESTAMPTZ NOT NULL,
+    lqi SMALLINT NOT NULL
+);
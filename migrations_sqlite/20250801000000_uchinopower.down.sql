@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP TABLE instant_epower;
+DROP TABLE instant_current;
+DROP TABLE cumlative_amount_epower;
+DROP TABLE settings;
@@ -0,0 +1,32 @@
+-- Add up migration script here
+
+-- 管理情報
+CREATE TABLE IF NOT EXISTS settings(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    note TEXT NOT NULL
+);
+
+-- 瞬時電力
+CREATE TABLE IF NOT EXISTS instant_epower(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    location VARCHAR(255),
+    recorded_at DATETIME NOT NULL,
+    watt TEXT NOT NULL
+);
+
+-- 瞬時電流
+CREATE TABLE IF NOT EXISTS instant_current(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    location VARCHAR(255),
+    recorded_at DATETIME NOT NULL,
+    r TEXT NOT NULL,
+    t TEXT
+);
+
+-- 積算電力量
+CREATE TABLE IF NOT EXISTS cumlative_amount_epower(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    location VARCHAR(255),
+    recorded_at DATETIME NOT NULL,
+    kwh TEXT NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Add up migration script here
+
+-- 積算電力量(逆方向)
+CREATE TABLE IF NOT EXISTS reverse_cumlative_amount_epower(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    location VARCHAR(255),
+    recorded_at DATETIME NOT NULL,
+    kwh TEXT NOT NULL
+);
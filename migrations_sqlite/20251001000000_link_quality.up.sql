@@ -0,0 +1,9 @@
+-- Add up migration script here
+
+-- 受信電波の品質(LQI)
+CREATE TABLE IF NOT EXISTS link_quality(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    meter_id INTEGER,
+    recorded_at DATETIME NOT NULL,
+    lqi INTEGER NOT NULL
+);
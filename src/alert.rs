@@ -0,0 +1,204 @@
+// 瞬時電力のしきい値通知
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use crate::units::Watt;
+use serde::Serialize;
+use std::fmt;
+
+/// 瞬時電力のしきい値
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PowerThreshold {
+    /// これ以上になったら通知する
+    pub high: Option<Watt>,
+    /// これ以下になったら通知する
+    pub low: Option<Watt>,
+    /// 元に戻ったとみなすまでの幅(しきい値付近でばたつかないようにする)
+    pub hysteresis: Watt,
+}
+
+impl PowerThreshold {
+    /// しきい値の組み合わせを確かめる
+    pub fn validate(&self) -> Result<(), String> {
+        if self.high.is_none() && self.low.is_none() {
+            return Err("high or low threshold must be set".to_string());
+        }
+        if self.hysteresis.0.is_sign_negative() {
+            return Err(format!(
+                "hysteresis {} must not be negative",
+                self.hysteresis
+            ));
+        }
+        if let (Some(high), Some(low)) = (self.high, self.low)
+            && low.0 + self.hysteresis.0 >= high.0 - self.hysteresis.0
+        {
+            return Err(format!(
+                "low {low} and high {high} must be separated by more than twice the hysteresis {}",
+                self.hysteresis
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// 瞬時電力の状態
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerLevel {
+    /// しきい値の間
+    #[default]
+    Normal,
+    /// 上のしきい値を超えた
+    High,
+    /// 下のしきい値を下回った
+    Low,
+}
+
+impl fmt::Display for PowerLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PowerLevel::Normal => write!(f, "normal"),
+            PowerLevel::High => write!(f, "high"),
+            PowerLevel::Low => write!(f, "low"),
+        }
+    }
+}
+
+/// 状態が変わったことの通知
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct PowerAlertEvent {
+    /// 変わった後の状態
+    pub level: PowerLevel,
+    /// 変わる前の状態
+    pub previous: PowerLevel,
+    /// 状態を変えた瞬時電力
+    pub watt: Watt,
+}
+
+/// 瞬時電力の状態を追いかける
+#[derive(Debug, Clone)]
+pub struct PowerAlert {
+    threshold: PowerThreshold,
+    level: PowerLevel,
+}
+
+impl PowerAlert {
+    pub fn new(threshold: PowerThreshold) -> Self {
+        Self {
+            threshold,
+            level: PowerLevel::Normal,
+        }
+    }
+
+    /// 今の状態
+    pub fn level(&self) -> PowerLevel {
+        self.level
+    }
+
+    /// 瞬時電力を受け取って, 状態が変われば通知を返す
+    /// しきい値を超えたら High(Low) にして, しきい値からヒステリシス分戻るまで Normal に戻さない
+    pub fn update(&mut self, watt: Watt) -> Option<PowerAlertEvent> {
+        let PowerThreshold {
+            high,
+            low,
+            hysteresis,
+        } = self.threshold;
+        let is_high = high.is_some_and(|high| watt >= high);
+        let is_low = low.is_some_and(|low| watt <= low);
+        let next = match self.level {
+            _ if is_high => PowerLevel::High,
+            _ if is_low => PowerLevel::Low,
+            PowerLevel::High if high.is_some_and(|high| watt.0 > high.0 - hysteresis.0) => {
+                PowerLevel::High
+            }
+            PowerLevel::Low if low.is_some_and(|low| watt.0 < low.0 + hysteresis.0) => {
+                PowerLevel::Low
+            }
+            _ => PowerLevel::Normal,
+        };
+        if next == self.level {
+            return None;
+        }
+        let previous = std::mem::replace(&mut self.level, next);
+        Some(PowerAlertEvent {
+            level: next,
+            previous,
+            watt,
+        })
+    }
+}
+
+#[test]
+fn test1() {
+    use rust_decimal::Decimal;
+    let w = |n: i64| Watt(Decimal::from(n));
+    let mut alert = PowerAlert::new(PowerThreshold {
+        high: Some(w(3000)),
+        low: Some(w(100)),
+        hysteresis: w(200),
+    });
+    assert_eq!(alert.update(w(1000)), None);
+    // 上のしきい値を超えた
+    assert_eq!(
+        alert.update(w(3000)),
+        Some(PowerAlertEvent {
+            level: PowerLevel::High,
+            previous: PowerLevel::Normal,
+            watt: w(3000),
+        })
+    );
+    // ヒステリシスの範囲内では戻らない
+    assert_eq!(alert.update(w(2900)), None);
+    assert_eq!(alert.update(w(3100)), None);
+    assert_eq!(alert.update(w(2801)), None);
+    assert_eq!(alert.level(), PowerLevel::High);
+    // ヒステリシスの幅を越えて戻った
+    assert_eq!(
+        alert.update(w(2800)),
+        Some(PowerAlertEvent {
+            level: PowerLevel::Normal,
+            previous: PowerLevel::High,
+            watt: w(2800),
+        })
+    );
+    // 下のしきい値を下回った
+    assert_eq!(alert.update(w(100)).map(|e| e.level), Some(PowerLevel::Low));
+    assert_eq!(alert.update(w(250)), None);
+    assert_eq!(
+        alert.update(w(300)).map(|e| e.level),
+        Some(PowerLevel::Normal)
+    );
+    // 下から一気に上のしきい値を超えた
+    assert_eq!(alert.update(w(-50)).map(|e| e.level), Some(PowerLevel::Low));
+    assert_eq!(
+        alert.update(w(5000)).map(|e| e.level),
+        Some(PowerLevel::High)
+    );
+}
+
+#[test]
+fn test2() {
+    use rust_decimal::Decimal;
+    let w = |n: i64| Watt(Decimal::from(n));
+    let threshold = |high: Option<i64>, low: Option<i64>, hysteresis: i64| PowerThreshold {
+        high: high.map(w),
+        low: low.map(w),
+        hysteresis: w(hysteresis),
+    };
+    assert!(threshold(Some(3000), None, 100).validate().is_ok());
+    assert!(threshold(None, Some(100), 0).validate().is_ok());
+    assert!(threshold(Some(3000), Some(100), 100).validate().is_ok());
+    assert!(threshold(None, None, 100).validate().is_err());
+    assert!(threshold(Some(3000), None, -1).validate().is_err());
+    assert!(threshold(Some(300), Some(100), 100).validate().is_err());
+    // 通知は JSON で公開する
+    let event = PowerAlertEvent {
+        level: PowerLevel::High,
+        previous: PowerLevel::Normal,
+        watt: w(3000),
+    };
+    assert_eq!(
+        serde_json::to_string(&event).unwrap(),
+        r#"{"level":"high","previous":"normal","watt":"3000"}"#
+    );
+}
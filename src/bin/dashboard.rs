@@ -13,9 +13,11 @@
 //! [examples]: https://github.com/ratatui/ratatui/blob/main/examples
 //! [examples readme]: https://github.com/ratatui/ratatui/blob/main/examples/README.md
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use clap::Parser;
 use color_eyre::{Result, eyre::Context};
-use futures::StreamExt;
+use futures::{StreamExt, TryStreamExt};
 use hsv;
 use ratatui::widgets::Block;
 use ratatui::{
@@ -24,21 +26,54 @@ use ratatui::{
     layout::{Constraint, Layout},
     style::{Color, Style, Stylize},
     text::Line,
-    widgets::{Bar, BarChart, BarGroup},
+    widgets::{Bar, BarChart, BarGroup, Paragraph},
 };
 use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
 use sqlx::{self, postgres::PgPool};
 use std::env;
 use std::time::Duration;
+use uchinoepower::connection_settings::ConnectionSettings;
+use uchinoepower::echonetlite::smart_electric_energy_meter as SM;
+use uchinoepower::store::{self, Database, ReadRange};
+use uchinoepower::units::Watt;
+
+/// 測定値をターミナルに表示する
+#[derive(Parser, Debug)]
+#[command(name = "dashboard")]
+#[command(version, about, long_about = None)]
+struct Cli {
+    /// データーベースから再取得する間隔(秒)
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    refresh_secs: u64,
+    /// 表示する瞬時電力、瞬時電流のレコード数(積算電力量はその半分)
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..))]
+    samples: u32,
+    /// 日の区切りに使うタイムゾーン
+    #[arg(long, env = "TIMEZONE", default_value_t = uchinoepower::DEFAULT_TIMEZONE, value_parser = uchinoepower::parse_timezone)]
+    timezone: Tz,
+    /// 表示するスマートメーター(settings.id) 指定しなければすべてのスマートメーター
+    /// (積算電力量は最も新しい使用中のスマートメーター)
+    #[arg(long, visible_alias = "settings-id")]
+    meter_id: Option<i64>,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv::dotenv().ok();
+    // コマンドライン引数
+    let cli = Cli::parse();
     let database_url = env::var("DATABASE_URL").wrap_err("Must be set to DATABASE_URL")?;
+    // 測定値の読み出しは PostgreSQL のみ
+    if store::is_sqlite_url(&database_url) {
+        color_eyre::eyre::bail!(
+            "dashboard は SQLite に対応していません(PostgreSQL の DATABASE_URL を指定してください)"
+        );
+    }
     let pool = PgPool::connect(&database_url).await?;
     color_eyre::install()?;
     let terminal = ratatui::init();
-    let app = App::new(pool).await;
+    let app = App::new(pool, &cli).await;
     let app_result = app.run(terminal).await;
     ratatui::restore();
     app_result
@@ -46,10 +81,10 @@ async fn main() -> Result<()> {
 
 struct InstantWatt {
     pub recorded_at: DateTime<Utc>,
-    pub watt: Decimal,
+    /// 符号付き(マイナスは逆潮流)
+    pub watt: Watt,
 }
 
-#[allow(dead_code)]
 struct InstantCurrent {
     pub recorded_at: DateTime<Utc>,
     pub r: Decimal,
@@ -64,50 +99,115 @@ struct CumlativeKiloWattHour {
 
 struct App {
     pool: PgPool,
+    fetch_period: Duration,
+    samples: i64,
+    timezone: Tz,
+    meter_id: Option<i64>,
+    /// 積算電力量を表示するスマートメーター(settings.id)
+    /// 別々のスマートメーターの積算電力量の差分には意味が無いので
+    /// --meter-id を指定しなければ最も新しい使用中のスマートメーター
+    kwh_meter_id: Option<i64>,
     should_quit: bool,
+    /// 積算電力量を30分毎の使用量で表示する
+    show_deltas: bool,
+    /// 瞬時電力を移動平均で表示する
+    show_moving_average: bool,
+    /// 表示するページ(0 が最新, 1 つ増える毎にレコード数だけ遡る)
+    page: i64,
+    /// 積算電力量が一巡する値
+    wrap_kwh: Option<Decimal>,
     instant_watt: Vec<InstantWatt>,
     instant_current: Vec<InstantCurrent>,
     cumlative_amount_epower: Vec<CumlativeKiloWattHour>,
+    reverse_cumlative_amount_epower: Vec<CumlativeKiloWattHour>,
+    today_kwh: Option<Decimal>,
+    /// 最近の受信電波の品質(LQI)の平均
+    link_quality: Option<Decimal>,
 }
 
 impl App {
     const FRAMES_PER_SECOND: f32 = 60.0;
 
-    async fn new(pool: PgPool) -> Self {
-        let instant_watt = read_instant_epower(&pool).await.unwrap_or_default();
-        let instant_current = read_instant_current(&pool).await.unwrap_or_default();
-        let cumlative_amount_epower = read_cumlative_amount_epower(&pool)
+    async fn new(pool: PgPool, cli: &Cli) -> Self {
+        let samples = cli.samples as i64;
+        let meter_id = cli.meter_id;
+        let kwh_meter_id = match meter_id {
+            Some(id) => Some(id),
+            None => Database::Postgres(pool.clone())
+                .read_newest_meter_id()
+                .await
+                .ok(),
+        };
+        let instant_watt = read_instant_epower(&pool, meter_id, samples, 0)
+            .await
+            .unwrap_or_default();
+        let instant_current = read_instant_current(&pool, meter_id, samples, 0)
+            .await
+            .unwrap_or_default();
+        let cumlative_amount_epower = read_cumlative_amount_epower(&pool, kwh_meter_id, samples, 0)
+            .await
+            .unwrap_or_default();
+        let reverse_cumlative_amount_epower =
+            read_reverse_cumlative_amount_epower(&pool, kwh_meter_id, samples, 0)
+                .await
+                .unwrap_or_default();
+        let wrap_kwh = read_settings(&pool, kwh_meter_id)
+            .await
+            .and_then(|settings| settings.wrap_kwh());
+        let today_kwh = read_today_kwh(&pool, kwh_meter_id, wrap_kwh, &cli.timezone)
+            .await
+            .unwrap_or_default();
+        let link_quality = read_link_quality(&pool, meter_id, samples)
             .await
             .unwrap_or_default();
 
         Self {
             pool: pool,
+            fetch_period: Duration::from_secs(cli.refresh_secs),
+            samples,
+            timezone: cli.timezone,
+            meter_id: cli.meter_id,
+            kwh_meter_id,
             should_quit: false,
+            show_deltas: false,
+            show_moving_average: false,
+            page: 0,
+            wrap_kwh,
             instant_watt,
             instant_current,
             cumlative_amount_epower,
+            reverse_cumlative_amount_epower,
+            today_kwh,
+            link_quality,
         }
     }
 
     async fn run(mut self, mut terminal: DefaultTerminal) -> Result<()> {
         let period = Duration::from_secs_f32(1.0 / Self::FRAMES_PER_SECOND);
         let mut interval = tokio::time::interval(period);
-        let mut fetch_interval = tokio::time::interval(Duration::from_secs(10));
+        let mut fetch_interval = tokio::time::interval(self.fetch_period);
         let mut events = EventStream::new();
 
         while !self.should_quit {
             tokio::select! {
                 _ = interval.tick() => { terminal.draw(|frame| self.draw(frame))?; },
                 _ = fetch_interval.tick() => self.fetch_data().await.unwrap_or_default(),
-                Some(Ok(event)) = events.next() => self.handle_event(&event),
+                Some(Ok(event)) = events.next() => {
+                    // 表示するページが変わったらすぐに再取得する
+                    if self.handle_event(&event) {
+                        self.fetch_data().await.unwrap_or_default();
+                    }
+                },
             }
         }
         Ok(())
     }
 
     fn draw(&self, frame: &mut Frame) {
-        let [title, upper, lower] = Layout::vertical([
+        let [title, today, upper, middle, lower] = Layout::vertical([
             Constraint::Length(1),
+            Constraint::Length(3),
+            Constraint::Fill(1),
             Constraint::Fill(1),
             Constraint::Fill(1),
         ])
@@ -116,90 +216,295 @@ impl App {
         let now = Utc::now();
 
         frame.render_widget(
-            "DASHBOARD (press q key to exit.)"
-                .bold()
-                .into_centered_line(),
+            format!(
+                "DASHBOARD {} (press q key to exit, d key to toggle usage per 30 minutes, s key to toggle moving average, left/right keys to scroll.)",
+                self.time_range()
+            )
+            .bold()
+            .into_centered_line(),
             title,
         );
-        frame.render_widget(
-            cumlative_amount_epower_chart(now, &self.cumlative_amount_epower),
-            upper,
-        );
-        frame.render_widget(instantious_watt_chart(now, &self.instant_watt), lower);
+        frame.render_widget(today_kwh_panel(self.today_kwh, self.link_quality), today);
+        let (forward, reverse) = if self.show_deltas {
+            (
+                kwh_deltas(&self.cumlative_amount_epower, self.wrap_kwh),
+                kwh_deltas(&self.reverse_cumlative_amount_epower, self.wrap_kwh),
+            )
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        let forward_chart = if self.show_deltas {
+            kwh_chart(
+                now,
+                &forward,
+                180.0,
+                "electric power usage per 30 minutes (kWh)",
+            )
+        } else {
+            cumlative_amount_epower_chart(now, &self.cumlative_amount_epower)
+        };
+        if self.reverse_cumlative_amount_epower.is_empty() {
+            frame.render_widget(forward_chart, upper);
+        } else {
+            // 逆方向計測値があれば正方向と並べて表示する
+            let [left, right] =
+                Layout::horizontal([Constraint::Fill(1), Constraint::Fill(1)]).areas(upper);
+            let reverse_chart = if self.show_deltas {
+                kwh_chart(
+                    now,
+                    &reverse,
+                    300.0,
+                    "reverse electric power usage per 30 minutes (kWh)",
+                )
+            } else {
+                reverse_cumlative_amount_epower_chart(now, &self.reverse_cumlative_amount_epower)
+            };
+            frame.render_widget(forward_chart, left);
+            frame.render_widget(reverse_chart, right);
+        }
+        if self.show_moving_average {
+            let averaged = moving_average(&self.instant_watt, MOVING_AVERAGE_POINTS);
+            frame.render_widget(instantious_watt_chart(now, &averaged), middle);
+        } else {
+            frame.render_widget(instantious_watt_chart(now, &self.instant_watt), middle);
+        }
+        frame.render_widget(instantious_current_chart(now, &self.instant_current), lower);
     }
 
-    fn handle_event(&mut self, event: &Event) {
+    /// 表示するページが変わったら true を返す
+    fn handle_event(&mut self, event: &Event) -> bool {
         if let Event::Key(key) = event {
-            if key.kind == KeyEventKind::Press && key.code == KeyCode::Char('q') {
-                self.should_quit = true;
+            if key.kind == KeyEventKind::Press {
+                match key.code {
+                    KeyCode::Char('q') => self.should_quit = true,
+                    KeyCode::Char('d') => self.show_deltas = !self.show_deltas,
+                    KeyCode::Char('s') => self.show_moving_average = !self.show_moving_average,
+                    // 過去に遡る
+                    KeyCode::Left => {
+                        self.page += 1;
+                        return true;
+                    }
+                    // 最新に近づく
+                    KeyCode::Right if self.page > 0 => {
+                        self.page -= 1;
+                        return true;
+                    }
+                    _ => {}
+                }
             }
         }
+        false
+    }
+
+    /// 表示している瞬時電力の時間範囲
+    fn time_range(&self) -> String {
+        let local = |a: &InstantWatt| a.recorded_at.with_timezone(&self.timezone);
+        match (self.instant_watt.first(), self.instant_watt.last()) {
+            (Some(first), Some(last)) => format!(
+                "{} - {}",
+                local(first).format("%Y-%m-%d %H:%M"),
+                local(last).format("%Y-%m-%d %H:%M")
+            ),
+            _ => "--".to_string(),
+        }
     }
 
     async fn fetch_data(&mut self) -> Result<()> {
-        self.instant_watt = read_instant_epower(&self.pool).await?;
-        self.instant_current = read_instant_current(&self.pool).await?;
-        self.cumlative_amount_epower = read_cumlative_amount_epower(&self.pool).await?;
+        let page = self.page;
+        let meter_id = self.meter_id;
+        let kwh_meter_id = self.kwh_meter_id;
+        self.instant_watt = read_instant_epower(&self.pool, meter_id, self.samples, page).await?;
+        self.instant_current =
+            read_instant_current(&self.pool, meter_id, self.samples, page).await?;
+        self.cumlative_amount_epower =
+            read_cumlative_amount_epower(&self.pool, kwh_meter_id, self.samples, page).await?;
+        self.reverse_cumlative_amount_epower =
+            read_reverse_cumlative_amount_epower(&self.pool, kwh_meter_id, self.samples, page)
+                .await?;
+        self.today_kwh =
+            read_today_kwh(&self.pool, kwh_meter_id, self.wrap_kwh, &self.timezone).await?;
+        self.link_quality = read_link_quality(&self.pool, meter_id, self.samples).await?;
         Ok(())
     }
 }
 
+/// スマートメーターの設定情報をデーターベースから得る
+/// 指定しなければ最も新しいもの
+async fn read_settings(pool: &PgPool, meter_id: Option<i64>) -> Option<ConnectionSettings> {
+    let db = Database::Postgres(pool.clone());
+    match meter_id {
+        Some(id) => db.read_settings_by_id(id).await.ok(),
+        None => db.read_settings().await.ok(),
+    }
+}
+
+/// 最新から page ページ遡った samples 件
+fn page_range(samples: i64, page: i64) -> ReadRange {
+    ReadRange::Latest {
+        limit: samples,
+        offset: samples * page,
+    }
+}
+
 /// 瞬時電力をデーターベースから得る
-async fn read_instant_epower(pool: &PgPool) -> Result<Vec<InstantWatt>> {
-    let mut recs = sqlx::query!(
-        "SELECT recorded_at, watt FROM instant_epower ORDER BY recorded_at DESC LIMIT $1",
-        20
+/// page は最新から遡るページ数
+async fn read_instant_epower(
+    pool: &PgPool,
+    meter_id: Option<i64>,
+    samples: i64,
+    page: i64,
+) -> Result<Vec<InstantWatt>> {
+    Ok(
+        store::read_instant_power(pool, meter_id, page_range(samples, page))
+            .map_ok(|(recorded_at, watt)| InstantWatt {
+                recorded_at,
+                watt: Watt(watt),
+            })
+            .try_collect()
+            .await?,
     )
-    .fetch_all(pool)
-    .await?;
-
-    recs.reverse();
-    Ok(recs
-        .iter()
-        .map(|a| InstantWatt {
-            recorded_at: a.recorded_at,
-            watt: a.watt,
-        })
-        .collect())
 }
 
 /// 瞬時電流をデーターベースから得る
-async fn read_instant_current(pool: &PgPool) -> Result<Vec<InstantCurrent>> {
-    let mut recs = sqlx::query!(
-        "SELECT recorded_at, r, t FROM instant_current ORDER BY recorded_at DESC LIMIT $1",
-        20
+/// page は最新から遡るページ数
+async fn read_instant_current(
+    pool: &PgPool,
+    meter_id: Option<i64>,
+    samples: i64,
+    page: i64,
+) -> Result<Vec<InstantCurrent>> {
+    Ok(
+        store::read_current(pool, meter_id, page_range(samples, page))
+            .map_ok(|(recorded_at, r, t)| InstantCurrent { recorded_at, r, t })
+            .try_collect()
+            .await?,
     )
-    .fetch_all(pool)
-    .await?;
-
-    recs.reverse();
-    Ok(recs
-        .iter()
-        .map(|a| InstantCurrent {
-            recorded_at: a.recorded_at,
-            r: a.r,
-            t: a.t,
-        })
-        .collect())
 }
 
 /// 定時積算電力量計測値(正方向計測値)をデーターベースから得る
-async fn read_cumlative_amount_epower(pool: &PgPool) -> Result<Vec<CumlativeKiloWattHour>> {
-    let mut recs = sqlx::query!(
-        "SELECT recorded_at, kwh FROM cumlative_amount_epower ORDER BY recorded_at DESC LIMIT $1",
-        10
+/// page は最新から遡るページ数(30分毎なので瞬時値の半分の件数)
+async fn read_cumlative_amount_epower(
+    pool: &PgPool,
+    meter_id: Option<i64>,
+    samples: i64,
+    page: i64,
+) -> Result<Vec<CumlativeKiloWattHour>> {
+    Ok(
+        store::read_cumulative(pool, meter_id, page_range((samples + 1) / 2, page))
+            .map_ok(|(recorded_at, kwh)| CumlativeKiloWattHour { recorded_at, kwh })
+            .try_collect()
+            .await?,
+    )
+}
+
+/// 定時積算電力量計測値(逆方向計測値)をデーターベースから得る
+/// page は最新から遡るページ数(30分毎なので瞬時値の半分の件数)
+async fn read_reverse_cumlative_amount_epower(
+    pool: &PgPool,
+    meter_id: Option<i64>,
+    samples: i64,
+    page: i64,
+) -> Result<Vec<CumlativeKiloWattHour>> {
+    Ok(
+        store::read_reverse_cumulative(pool, meter_id, page_range((samples + 1) / 2, page))
+            .map_ok(|(recorded_at, kwh)| CumlativeKiloWattHour { recorded_at, kwh })
+            .try_collect()
+            .await?,
+    )
+}
+
+/// 今日(午前０時から)の積算電力量(正方向計測値)の差分をデーターベースから得る
+/// 30分毎の使用量の合計なので積算電力量が一巡しても負にならない
+/// 別々のスマートメーターの積算電力量は混ぜられないので meter_id が無ければ None
+async fn read_today_kwh(
+    pool: &PgPool,
+    meter_id: Option<i64>,
+    wrap_kwh: Option<Decimal>,
+    tz: &Tz,
+) -> Result<Option<Decimal>> {
+    let Some(meter_id) = meter_id else {
+        return Ok(None);
+    };
+    let now = Utc::now().with_timezone(tz);
+    let Some(midnight) = tz
+        .from_local_datetime(&now.date_naive().and_time(chrono::NaiveTime::MIN))
+        .earliest()
+    else {
+        return Ok(None);
+    };
+    let range = ReadRange::Between(midnight.to_utc(), now.to_utc());
+    let readings = store::read_cumulative(pool, Some(meter_id), range)
+        .try_collect::<Vec<_>>()
+        .await?;
+    if readings.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(
+        SM::consecutive_deltas(&readings, wrap_kwh)
+            .into_iter()
+            .map(|(_, kwh)| kwh)
+            .sum(),
+    ))
+}
+
+/// 最近の受信電波の品質(LQI)の平均をデーターベースから得る
+/// LQIを返さないドングルなら None
+async fn read_link_quality(
+    pool: &PgPool,
+    meter_id: Option<i64>,
+    samples: i64,
+) -> Result<Option<Decimal>> {
+    let avg = sqlx::query_scalar!(
+        "SELECT AVG(lqi) FROM (SELECT lqi FROM link_quality WHERE ($2::BIGINT IS NULL OR meter_id = $2) ORDER BY recorded_at DESC LIMIT $1) AS recent",
+        samples,
+        meter_id
     )
-    .fetch_all(pool)
+    .fetch_one(pool)
     .await?;
 
-    recs.reverse();
-    Ok(recs
-        .iter()
-        .map(|a| CumlativeKiloWattHour {
-            recorded_at: a.recorded_at,
-            kwh: a.kwh,
+    Ok(avg.map(|a| a.round()))
+}
+
+/// これより低いLQIは電波が弱くなっていて受信に失敗しやすい
+const WEAK_LINK_QUALITY: u8 = 64;
+
+fn today_kwh_panel<'a>(today_kwh: Option<Decimal>, link_quality: Option<Decimal>) -> Paragraph<'a> {
+    let value = today_kwh.map_or("--".to_string(), |kwh| format!("{kwh}"));
+    let link = match link_quality {
+        Some(lqi) if lqi < Decimal::from(WEAK_LINK_QUALITY) => Line::from(format!(
+            "weak radio link (LQI {lqi}), readings may be missed"
+        ))
+        .red()
+        .centered(),
+        Some(lqi) => Line::from(format!("LQI {lqi}")).dim().centered(),
+        None => Line::default(),
+    };
+    Paragraph::new(vec![
+        Line::from("today's consumption").centered(),
+        Line::from(format!("{value} kWh"))
+            .bold()
+            .yellow()
+            .centered(),
+        link,
+    ])
+}
+
+/// 瞬時電力の移動平均をとる点数
+const MOVING_AVERAGE_POINTS: usize = 5;
+
+/// 瞬時電力の移動平均(その時刻までの最大n点の平均)を求める
+fn moving_average(iw: &[InstantWatt], n: usize) -> Vec<InstantWatt> {
+    iw.iter()
+        .enumerate()
+        .map(|(i, a)| {
+            let window = &iw[(i + 1).saturating_sub(n)..=i];
+            let sum = window.iter().map(|a| a.watt.0).sum::<Decimal>();
+            InstantWatt {
+                recorded_at: a.recorded_at,
+                watt: Watt((sum / Decimal::from(window.len())).round()),
+            }
         })
-        .collect())
+        .collect()
 }
 
 fn instantious_watt_chart<'a>(now: DateTime<Utc>, iw: &'a [InstantWatt]) -> BarChart<'a> {
@@ -207,19 +512,21 @@ fn instantious_watt_chart<'a>(now: DateTime<Utc>, iw: &'a [InstantWatt]) -> BarC
         .iter()
         .map(|a| {
             let diff_minutes = (now - a.recorded_at).num_seconds() as f64 / 60.0;
-            let value: u32 = a.watt.try_into().unwrap();
-            let (r, g, b) = hsv::hsv_to_rgb(60.0, 1.0, 1.0 - f64::from(value) / 5000.0);
+            // マイナスの値(逆潮流)は棒の長さを絶対値にして色を変える
+            let value = a.watt.magnitude();
+            let hue = if a.watt.is_export() { 120.0 } else { 60.0 };
+            let (r, g, b) = hsv::hsv_to_rgb(hue, 1.0, 1.0 - (value as f64 / 5000.0).min(1.0));
             let style = Style::new().fg(Color::Rgb(r, g, b));
             //
             Bar::default()
-                .value(value as u64)
+                .value(value)
                 .label(Line::from(format!("{}m", 0.0 - diff_minutes.floor())))
-                .text_value(format!("{value:>3}"))
+                .text_value(format!("{:>3}", a.watt))
                 .style(style)
                 .value_style(style.reversed())
         })
         .collect();
-    let title = Line::from("instantious electric power (W)").centered();
+    let title = Line::from("instantious electric power (W, green is export)").centered();
     BarChart::default()
         .data(BarGroup::default().bars(&bars))
         .block(Block::new().title(title))
@@ -227,30 +534,139 @@ fn instantious_watt_chart<'a>(now: DateTime<Utc>, iw: &'a [InstantWatt]) -> BarC
         .bar_gap(1)
 }
 
+fn instantious_current_chart<'a>(now: DateTime<Utc>, ic: &'a [InstantCurrent]) -> BarChart<'a> {
+    // 0.1A単位の棒にする
+    let bar = |ampere: Decimal, hue: f64| {
+        let value = (ampere * Decimal::from(10)).to_u64().unwrap_or(0);
+        let (r, g, b) = hsv::hsv_to_rgb(hue, 1.0, 1.0 - (value as f64 / 600.0).min(1.0));
+        let style = Style::new().fg(Color::Rgb(r, g, b));
+        Bar::default()
+            .value(value)
+            .text_value(format!("{ampere}"))
+            .style(style)
+            .value_style(style.reversed())
+    };
+    let mut chart = BarChart::default();
+    for a in ic.iter() {
+        let diff_minutes = (now - a.recorded_at).num_seconds() as f64 / 60.0;
+        // 単相2線式(T相なし)ならR相のみ
+        let bars: Vec<Bar> = std::iter::once(bar(a.r, 0.0))
+            .chain(a.t.map(|t| bar(t, 240.0)))
+            .collect();
+        chart = chart.data(
+            BarGroup::default()
+                .label(Line::from(format!("{}m", 0.0 - diff_minutes.floor())))
+                .bars(&bars),
+        );
+    }
+    let title = Line::from("instantious current R / T (A)").centered();
+    chart
+        .block(Block::new().title(title))
+        .bar_width(4)
+        .bar_gap(0)
+        .group_gap(1)
+}
+
+/// 積算電力量から30分毎の使用量を求める
+fn kwh_deltas(
+    kwh: &[CumlativeKiloWattHour],
+    wrap_kwh: Option<Decimal>,
+) -> Vec<CumlativeKiloWattHour> {
+    let readings: Vec<(DateTime<Utc>, Decimal)> =
+        kwh.iter().map(|a| (a.recorded_at, a.kwh)).collect();
+    SM::consecutive_deltas(&readings, wrap_kwh)
+        .into_iter()
+        .map(|(recorded_at, kwh)| CumlativeKiloWattHour { recorded_at, kwh })
+        .collect()
+}
+
 fn cumlative_amount_epower_chart<'a>(
     now: DateTime<Utc>,
     kwh: &'a [CumlativeKiloWattHour],
 ) -> BarChart<'a> {
+    kwh_chart(now, kwh, 180.0, "cumlative amount electric power (kWh)")
+}
+
+fn reverse_cumlative_amount_epower_chart<'a>(
+    now: DateTime<Utc>,
+    kwh: &'a [CumlativeKiloWattHour],
+) -> BarChart<'a> {
+    kwh_chart(
+        now,
+        kwh,
+        300.0,
+        "reverse cumlative amount electric power (kWh)",
+    )
+}
+
+fn kwh_chart<'a>(
+    now: DateTime<Utc>,
+    kwh: &'a [CumlativeKiloWattHour],
+    hue: f64,
+    title: &'a str,
+) -> BarChart<'a> {
+    // 色の濃さは表示するデーターの最小値から最大値の範囲で決める
+    let min = kwh.iter().map(|a| a.kwh).min().unwrap_or_default();
+    let max = kwh.iter().map(|a| a.kwh).max().unwrap_or_default();
+    let span = (max - min).to_f64().filter(|a| *a > 0.0).unwrap_or(1.0);
     let bars: Vec<Bar> = kwh
         .iter()
         .map(|a| {
             let diff_minutes = (now - a.recorded_at).num_seconds() as f64 / 60.0;
-            let value: f64 = a.kwh.try_into().unwrap();
-            let (r, g, b) = hsv::hsv_to_rgb(180.0, 1.0, 1.0 - value / 99999.0);
+            let ratio = ((a.kwh - min).to_f64().unwrap_or(0.0) / span).clamp(0.0, 1.0);
+            let (r, g, b) = hsv::hsv_to_rgb(hue, 1.0, 1.0 - ratio / 2.0);
             let style = Style::new().fg(Color::Rgb(r, g, b));
+            // 0.01kWh単位の棒にする(マイナスの値は0にする)
+            let value = a
+                .kwh
+                .checked_mul(Decimal::from(100))
+                .unwrap_or(Decimal::MAX)
+                .max(Decimal::ZERO)
+                .to_u64()
+                .unwrap_or(u64::MAX);
             //
             Bar::default()
-                .value((a.kwh * Decimal::from(100)).try_into().unwrap())
+                .value(value)
                 .label(Line::from(format!("{}m", 0.0 - diff_minutes.floor())))
-                .text_value(format!("{value}"))
+                .text_value(format!("{}", a.kwh))
                 .style(style)
                 .value_style(style.reversed())
         })
         .collect();
-    let title = Line::from("cumlative amount electric power (kWh)").centered();
+    let title = Line::from(title).centered();
     BarChart::default()
         .data(BarGroup::default().bars(&bars))
         .block(Block::new().title(title))
         .bar_width(10)
         .bar_gap(2)
 }
+
+#[test]
+fn test1() {
+    // 逆潮流(マイナスの瞬時電力)を含めて描画できる
+    use ratatui::{buffer::Buffer, layout::Rect, widgets::Widget};
+    let now = DateTime::parse_from_rfc3339("2025-07-13T12:05:00Z")
+        .unwrap()
+        .to_utc();
+    let iw = [-500, 1200, 0, -3]
+        .into_iter()
+        .enumerate()
+        .map(|(i, w)| InstantWatt {
+            recorded_at: now - chrono::TimeDelta::minutes(4 - i as i64),
+            watt: Watt(Decimal::from(w)),
+        })
+        .collect::<Vec<InstantWatt>>();
+    let averaged = moving_average(&iw, MOVING_AVERAGE_POINTS);
+    assert_eq!(averaged[1].watt, Watt(Decimal::from(350)));
+    let area = Rect::new(0, 0, 40, 12);
+    for data in [&iw, &averaged] {
+        let mut buffer = Buffer::empty(area);
+        instantious_watt_chart(now, data).render(area, &mut buffer);
+        let text = buffer
+            .content()
+            .iter()
+            .map(|c| c.symbol())
+            .collect::<String>();
+        assert!(text.contains("-500"));
+    }
+}
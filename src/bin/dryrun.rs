@@ -3,22 +3,28 @@
 // SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
 //
 use anyhow::{Context, anyhow, bail};
+use chrono::{TimeDelta, Utc};
+use chrono_tz::Tz;
 use clap::{Args, Parser, Subcommand};
-use core::time;
-use serialport::{DataBits, SerialPort, StopBits};
+use serialport::SerialPort;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
 use std::fs::File;
 use std::io::{self, BufReader, Write};
 use std::net::Ipv6Addr;
 use std::str::FromStr;
-use std::sync::{LazyLock, mpsc, mpsc::TryRecvError};
+use std::sync::{
+    LazyLock, mpsc,
+    mpsc::{RecvTimeoutError, TryRecvError},
+};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tracing_subscriber::FmtSubscriber;
 use uchinoepower::echonetlite::{
-    self, EchonetliteEdata, EchonetliteFrame, smart_electric_energy_meter,
+    self, EchonetliteEdata, EchonetliteFrame, Eoj, smart_electric_energy_meter,
 };
-use uchinoepower::skstack::{self, Erxudp, authn};
+use uchinoepower::skstack::{self, authn};
 use uchinoepower::{self, ConnectionSettings, pairing};
 
 /// スマートメーターBルートから情報を取得する。
@@ -34,6 +40,18 @@ struct Cli {
     #[arg(short = 'D', long, default_value = "/dev/ttyUSB0")]
     device: String,
 
+    /// シリアルポートのボーレート
+    #[arg(long, default_value_t = skstack::DEFAULT_BAUD_RATE, value_parser = skstack::parse_baud_rate)]
+    baud: u32,
+
+    /// シリアルポートの読み込みタイムアウト(ミリ秒)
+    #[arg(long, default_value_t = skstack::DEFAULT_TIMEOUT_MS, value_parser = clap::value_parser!(u64).range(1..))]
+    timeout_ms: u64,
+
+    /// スマートメーターの時計のタイムゾーン
+    #[arg(long, env = "TIMEZONE", default_value_t = uchinoepower::DEFAULT_TIMEZONE, value_parser = uchinoepower::parse_timezone)]
+    timezone: Tz,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -43,7 +61,28 @@ enum Commands {
     /// ペアリングして情報を設定ファイルに保存する
     Pairing(PairingArgs),
     /// スマートメータから電力消費量を得る
-    DryRun,
+    DryRun(DryRunArgs),
+    /// 標準入力から読み込んだSKSTACKコマンドを送信して応答を表示する
+    Raw,
+    /// Getプロパティマップにある全てのプロパティ値を読み出して表示する
+    Probe,
+    /// スマートメーターの時計をこのコンピューターの時計に合わせる
+    SetClock,
+    /// 16進文字列のEchonetliteフレームを解析して表示する(スマートメーターに接続しない)
+    Decode(DecodeArgs),
+}
+
+#[derive(Debug, Args)]
+struct DryRunArgs {
+    /// 瞬時電力と瞬時電流を1回だけ読み出して表示したら終了する
+    #[arg(long)]
+    once: bool,
+}
+
+#[derive(Debug, Args)]
+struct DecodeArgs {
+    /// ERXUDPのデーター部(16進文字列)
+    hex: String,
 }
 
 #[derive(Debug, Args)]
@@ -77,15 +116,40 @@ static SMARTMETER_PROPS: LazyLock<Vec<EchonetliteEdata>> = LazyLock::new(|| {
     ]
 });
 
+/// スマートメーターの時計を取得するechonet lite電文
+static METER_CLOCK: LazyLock<EchonetliteFrame> = LazyLock::new(|| {
+    EchonetliteFrame {
+        ehd: 0x1081, // 0x1081 = echonet lite
+        tid: 1,      // tid
+        seoj: Eoj::HOME_CONTROLLER,
+        deoj: Eoj::SMART_METER,
+        esv: 0x62, // get要求
+        opc: 2,    // 2つ
+        edata: vec![
+            EchonetliteEdata {
+                epc: echonetlite::superclass::CurrentDateSetting::EPC, // 現在年月日設定
+                ..Default::default()
+            },
+            EchonetliteEdata {
+                epc: echonetlite::superclass::CurrentTimeSetting::EPC, // 現在時刻設定
+                ..Default::default()
+            },
+        ],
+    }
+});
+
+/// スマートメーターの時計とこのコンピューターの時計のずれの許容範囲
+const ALLOWED_METER_CLOCK_SKEW: TimeDelta = TimeDelta::minutes(5);
+
 /// 定時積算電力量計測値を取得するechonet lite電文
 static LATEST_CWH: LazyLock<EchonetliteFrame> = LazyLock::new(|| {
     EchonetliteFrame {
-        ehd: 0x1081,              // 0x1081 = echonet lite
-        tid: 1,                   // tid
-        seoj: [0x05, 0xff, 0x01], // home controller
-        deoj: [0x02, 0x88, 0x01], // smartmeter
-        esv: 0x62,                // get要求
-        opc: 1,                   // 1つ
+        ehd: 0x1081, // 0x1081 = echonet lite
+        tid: 1,      // tid
+        seoj: Eoj::HOME_CONTROLLER,
+        deoj: Eoj::SMART_METER,
+        esv: 0x62, // get要求
+        opc: 1,    // 1つ
         edata: vec![EchonetliteEdata {
             epc: 0xea, // 定時積算電力量計測値(正方向計測値)
             ..Default::default()
@@ -96,12 +160,12 @@ static LATEST_CWH: LazyLock<EchonetliteFrame> = LazyLock::new(|| {
 /// 今日の積算電力量履歴を取得するechonet lite電文
 static CWH_HISTORIES: LazyLock<EchonetliteFrame> = LazyLock::new(|| {
     EchonetliteFrame {
-        ehd: 0x1081,              // 0x1081 = echonet lite
-        tid: 1,                   // tid
-        seoj: [0x05, 0xff, 0x01], // home controller
-        deoj: [0x02, 0x88, 0x01], // smartmeter
-        esv: 0x62,                // get要求
-        opc: 1,                   // 1つ
+        ehd: 0x1081, // 0x1081 = echonet lite
+        tid: 1,      // tid
+        seoj: Eoj::HOME_CONTROLLER,
+        deoj: Eoj::SMART_METER,
+        esv: 0x62, // get要求
+        opc: 1,    // 1つ
         edata: vec![EchonetliteEdata {
             epc: 0xe2, // 積算電力量計測値履歴1
             ..Default::default()
@@ -112,12 +176,12 @@ static CWH_HISTORIES: LazyLock<EchonetliteFrame> = LazyLock::new(|| {
 /// 積算電力量計測値を取得するechonet lite電文
 static CUMLATIVE_WATT_HOUR: LazyLock<EchonetliteFrame> = LazyLock::new(|| {
     EchonetliteFrame {
-        ehd: 0x1081,              // 0x1081 = echonet lite
-        tid: 1,                   // tid
-        seoj: [0x05, 0xff, 0x01], // home controller
-        deoj: [0x02, 0x88, 0x01], // smartmeter
-        esv: 0x62,                // get要求
-        opc: 1,                   // 1つ
+        ehd: 0x1081, // 0x1081 = echonet lite
+        tid: 1,      // tid
+        seoj: Eoj::HOME_CONTROLLER,
+        deoj: Eoj::SMART_METER,
+        esv: 0x62, // get要求
+        opc: 1,    // 1つ
         edata: vec![EchonetliteEdata {
             epc: 0xe0, // 積算電力量計測値(正方向計測値)
             ..Default::default()
@@ -128,12 +192,12 @@ static CUMLATIVE_WATT_HOUR: LazyLock<EchonetliteFrame> = LazyLock::new(|| {
 /// 瞬時電力と瞬時電流計測値を取得するechonet lite電文
 static INSTANT_WATT_AMPERE: LazyLock<EchonetliteFrame> = LazyLock::new(|| {
     EchonetliteFrame {
-        ehd: 0x1081,              // 0x1081 = echonet lite
-        tid: 1,                   // tid
-        seoj: [0x05, 0xff, 0x01], // home controller
-        deoj: [0x02, 0x88, 0x01], // smartmeter
-        esv: 0x62,                // get要求
-        opc: 2,                   // 2つ
+        ehd: 0x1081, // 0x1081 = echonet lite
+        tid: 1,      // tid
+        seoj: Eoj::HOME_CONTROLLER,
+        deoj: Eoj::SMART_METER,
+        esv: 0x62, // get要求
+        opc: 2,    // 2つ
         edata: vec![
             EchonetliteEdata {
                 epc: 0xe7, // 瞬時電力計測値
@@ -147,18 +211,6 @@ static INSTANT_WATT_AMPERE: LazyLock<EchonetliteFrame> = LazyLock::new(|| {
     }
 });
 
-/// シリアルポートを開く
-fn open_port(port_name: &str) -> anyhow::Result<Box<dyn SerialPort>> {
-    let builder = serialport::new(port_name, 115200)
-        .stop_bits(StopBits::One)
-        .data_bits(DataBits::Eight)
-        .timeout(Duration::from_secs(1));
-
-    builder
-        .open()
-        .with_context(move || format!("Failed to open \"{}\".", port_name))
-}
-
 /// 接続するスマートメーターをアクティブスキャンで探す
 fn exec_pairing(cli: &Cli, args: &PairingArgs) -> anyhow::Result<()> {
     let credentials = authn::Credentials {
@@ -167,7 +219,9 @@ fn exec_pairing(cli: &Cli, args: &PairingArgs) -> anyhow::Result<()> {
     };
 
     // シリアルポートを開く
-    let mut port = open_port(&cli.device)?;
+    let mut port =
+        skstack::open_serial(&cli.device, cli.baud, Duration::from_millis(cli.timeout_ms))
+            .with_context(|| format!("Failed to open \"{}\".", cli.device))?;
 
     // シリアルポート読み込みはバッファリングする
     let mut reader = port
@@ -196,10 +250,22 @@ fn exec_pairing(cli: &Cli, args: &PairingArgs) -> anyhow::Result<()> {
     }
 }
 
-fn exec_dryrun(cli: &Cli) -> anyhow::Result<()> {
+/// 接続したスマートメーター
+struct Connection {
+    settings: ConnectionSettings,
+    sender: Ipv6Addr,
+    serial_port: Box<dyn SerialPort>,
+    serial_port_reader: BufReader<Box<dyn SerialPort>>,
+}
+
+/// 設定ファイルの情報でスマートメーターと接続する
+fn connect_smartmeter(cli: &Cli) -> anyhow::Result<Connection> {
     // 設定ファイルからスマートメーターの情報を得る
     let file = fs::read_to_string(&cli.config_file).context("setting file read error.")?;
     let settings = toml::from_str::<ConnectionSettings>(&file)?;
+    settings
+        .validate()
+        .map_err(|problems| anyhow!("setting file error. {}", problems.join(", ")))?;
     let credentials = authn::Credentials {
         id: authn::Id::from_str(&settings.RouteBId).map_err(|s| anyhow!(s))?,
         password: authn::Password::from_str(&settings.RouteBPassword).map_err(|s| anyhow!(s))?,
@@ -215,7 +281,9 @@ fn exec_dryrun(cli: &Cli) -> anyhow::Result<()> {
     );
 
     // シリアルポートを開く
-    let mut serial_port = open_port(&cli.device)?;
+    let mut serial_port =
+        skstack::open_serial(&cli.device, cli.baud, Duration::from_millis(cli.timeout_ms))
+            .with_context(|| format!("Failed to open \"{}\".", cli.device))?;
 
     // シリアルポート読み込みはバッファリングする
     let mut serial_port_reader = serial_port
@@ -233,47 +301,172 @@ fn exec_dryrun(cli: &Cli) -> anyhow::Result<()> {
         settings.PanId,
     )?;
 
+    Ok(Connection {
+        settings,
+        sender,
+        serial_port,
+        serial_port_reader,
+    })
+}
+
+/// 要求したプロパティ値の応答を待つ時間
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 要求したプロパティ値の応答状況
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Answer {
+    /// 応答があった
+    Answered,
+    /// 読み出し不可応答(Get_SNA)があった
+    NotAvailable,
+    /// 時間内に応答がなかった
+    NotAnswered,
+    /// 要求に失敗した
+    Failed(String),
+}
+
+impl fmt::Display for Answer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Answer::Answered => write!(f, "answered"),
+            Answer::NotAvailable => write!(f, "not available (Get_SNA)"),
+            Answer::NotAnswered => write!(f, "NOT answered within {}s", RESPONSE_TIMEOUT.as_secs()),
+            Answer::Failed(e) => write!(f, "failed, {}", e),
+        }
+    }
+}
+
+/// 要求したプロパティ値の応答状況を表示する
+fn print_answers(answers: &BTreeMap<u8, Answer>) {
+    println!("Property response summary:");
+    for (epc, answer) in answers.iter() {
+        println!(
+            "  0x{:02X} {}: {}",
+            epc,
+            smart_electric_energy_meter::property_name(*epc).unwrap_or("unknown"),
+            answer
+        );
+    }
+    let unanswered = answers
+        .iter()
+        .filter(|(_, answer)| **answer != Answer::Answered)
+        .map(|(epc, _)| format!("0x{:02X}", epc))
+        .collect::<Vec<String>>();
+    if unanswered.is_empty() {
+        println!("All requested properties were answered.");
+    } else {
+        println!("The smart meter did not answer: {}", unanswered.join(", "));
+    }
+}
+
+fn exec_dryrun(cli: &Cli, args: &DryRunArgs) -> anyhow::Result<()> {
+    if args.once {
+        return exec_once(cli);
+    }
+    let Connection {
+        settings,
+        sender,
+        mut serial_port,
+        mut serial_port_reader,
+    } = connect_smartmeter(cli)?;
+
+    // 要求したプロパティ値の応答状況
+    let mut answers = BTreeMap::<u8, Answer>::new();
+
+    // スマートメーターの属性値を取得する
+    for edata in SMARTMETER_PROPS.iter() {
+        match skstack::request_property(
+            &mut serial_port_reader,
+            &mut serial_port,
+            &sender,
+            edata.epc,
+        ) {
+            Ok(value) => {
+                tracing::info!(
+                    "{}",
+                    value.as_edata().show(Some(&settings.effective_unit()))
+                );
+                answers.insert(edata.epc, Answer::Answered);
+            }
+            Err(e) => {
+                tracing::warn!("{}", e);
+                answers.insert(edata.epc, Answer::Failed(e.to_string()));
+            }
+        }
+    }
+
     thread::scope(|s| {
         let (tx_cancel, rx_cancel) = mpsc::channel::<()>();
+        // 受信したフレームの(ESV, EPC)
+        let (tx_answer, rx_answer) = mpsc::channel::<(u8, u8)>();
 
         // イベント受信用スレッドを起動する
         let handle = s.spawn(move || -> anyhow::Result<()> {
-            while let Err(TryRecvError::Empty) = rx_cancel.try_recv() {
-                if let Some(erxudp) = take_erxudp(&mut serial_port_reader)? {
-                    let config = bincode::config::standard()
-                        .with_big_endian()
-                        .with_fixed_int_encoding();
-                    let (frame, _len): (EchonetliteFrame, usize) =
-                        bincode::borrow_decode_from_slice(&erxudp.data, config)?;
-                    let mut s = Vec::<String>::new();
-                    s.push(frame.show());
-                    for v in frame.edata.iter() {
-                        s.push(v.show(Some(&settings.Unit)));
+            for received in skstack::responses(&mut serial_port_reader) {
+                // 停止の合図があれば終わる
+                if !matches!(rx_cancel.try_recv(), Err(TryRecvError::Empty)) {
+                    break;
+                }
+                let erxudp = match received {
+                    Ok(skstack::SkRxD::Erxudp(v)) => v,
+                    Ok(skstack::SkRxD::Void) => continue,
+                    Ok(r) => {
+                        tracing::trace!("{:?}", r);
+                        continue;
+                    }
+                    // タイムアウトエラーは無視する
+                    Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                    // 次の受信で同期を取り直す
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                        tracing::warn!("{}", e);
+                        continue;
                     }
-                    tracing::info!("{}", s.join(" "));
+                    Err(e) => return Err(e).context("serial port read failed!"),
+                };
+                let dump =
+                    |xs: &Vec<u8>| xs.iter().map(|b| format!("{:02X}", b)).collect::<String>();
+                // Echonetliteメッセージだけを扱う
+                if erxudp.destination_port != skstack::ECHONETLITE_PORT {
+                    tracing::warn!(
+                        r#"rport {} message "{}" is IGNORED"#,
+                        erxudp.destination_port,
+                        dump(&erxudp.data)
+                    );
+                    continue;
+                }
+                let config = bincode::config::standard()
+                    .with_big_endian()
+                    .with_fixed_int_encoding();
+                let decoded: Result<(EchonetliteFrame, usize), _> =
+                    bincode::borrow_decode_from_slice(&erxudp.data, config);
+                match decoded {
+                    Ok((frame, _len)) => {
+                        let mut s = Vec::<String>::new();
+                        s.push(frame.show());
+                        for v in frame.edata.iter() {
+                            s.push(v.show(Some(&settings.effective_unit())));
+                        }
+                        tracing::info!("{}", s.join(" "));
+                        check_meter_clock(&cli.timezone, &frame);
+                        for v in frame.edata.iter() {
+                            // 送信スレッドが終わっていても受信は続ける
+                            let _ = tx_answer.send((frame.esv, v.epc));
+                        }
+                    }
+                    // 解析できなくても受信を続ける
+                    Err(e) => tracing::error!(
+                        r#"Echonetlite message "{}" parse error, reason:{}"#,
+                        dump(&erxudp.data),
+                        e
+                    ),
                 }
             }
             Ok(())
         });
 
-        // スマートメーターの属性値を取得する
-        for edata in SMARTMETER_PROPS.iter() {
-            let frame = EchonetliteFrame {
-                ehd: 0x1081,              // 0x1081 = echonet lite
-                tid: 1,                   // tid
-                seoj: [0x05, 0xff, 0x01], // home controller
-                deoj: [0x02, 0x88, 0x01], // smartmeter
-                esv: 0x62,                // get要求
-                opc: 1,                   // 1つ
-                edata: vec![edata.clone()],
-            };
-            let command = skstack::command_from_echonetliteframe(&sender, &frame)?;
-            skstack::send(&mut serial_port, &command)?;
-            thread::sleep(time::Duration::from_secs(5));
-        }
-
         // Echonetliteメッセージ
-        let elmessages: [&EchonetliteFrame; 4] = [
+        let elmessages: [&EchonetliteFrame; 5] = [
+            &METER_CLOCK,
             &LATEST_CWH,
             &CWH_HISTORIES,
             &CUMLATIVE_WATT_HOUR,
@@ -281,10 +474,39 @@ fn exec_dryrun(cli: &Cli) -> anyhow::Result<()> {
         ];
 
         // Echonetliteメッセージ送信
-        for &msg in elmessages.iter() {
+        for (n, &msg) in elmessages.iter().enumerate() {
+            if n > 0 {
+                thread::sleep(PROBE_INTERVAL);
+            }
             let command = skstack::command_from_echonetliteframe(&sender, msg)?;
             skstack::send(&mut serial_port, &command)?;
-            thread::sleep(time::Duration::from_secs(10));
+            for edata in msg.edata.iter() {
+                answers.insert(edata.epc, Answer::NotAnswered);
+            }
+            // 要求した全てのプロパティ値の応答を待つ
+            let deadline = Instant::now() + RESPONSE_TIMEOUT;
+            while msg
+                .edata
+                .iter()
+                .any(|edata| answers.get(&edata.epc) == Some(&Answer::NotAnswered))
+            {
+                let Some(timeout) = deadline.checked_duration_since(Instant::now()) else {
+                    break;
+                };
+                match rx_answer.recv_timeout(timeout) {
+                    Ok((esv, epc)) if msg.edata.iter().any(|edata| edata.epc == epc) => {
+                        let answer = match esv {
+                            0x52 => Answer::NotAvailable, // Get_SNA プロパティ値読み出し不可応答
+                            _ => Answer::Answered,
+                        };
+                        answers.insert(epc, answer);
+                    }
+                    Ok(_) => {}
+                    Err(RecvTimeoutError::Timeout) => break,
+                    // 受信用スレッドが終了している
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
         }
 
         // イベント受信用スレッドを停止する
@@ -293,37 +515,237 @@ fn exec_dryrun(cli: &Cli) -> anyhow::Result<()> {
             .context("Can't send cancellation signal.")?;
 
         // 受信用スレッドの処理結果
-        match handle.join().map_err(|e| anyhow!("{:?}", e))? {
+        let result = handle.join().map_err(|e| anyhow!("{:?}", e))?;
+        print_answers(&answers);
+        match result {
             Ok(()) => Ok(println!("Good Bye!")),
             Err(e) => bail!(e),
         }
     })
 }
 
-/// イベント受信
-fn take_erxudp(serial_port_reader: &mut BufReader<dyn io::Read>) -> anyhow::Result<Option<Erxudp>> {
-    match skstack::receive(serial_port_reader) {
-        Ok(skstack::SkRxD::Void) => {}
-        Ok(r @ skstack::SkRxD::Ok) => {
-            tracing::trace!("{:?}", r);
+/// 瞬時電力と瞬時電流を1回だけ読み出して表示する
+/// (cron などから定期的に実行して確かめる用途)
+fn exec_once(cli: &Cli) -> anyhow::Result<()> {
+    let Connection {
+        settings,
+        sender,
+        mut serial_port,
+        mut serial_port_reader,
+    } = connect_smartmeter(cli)?;
+    let unit = settings.effective_unit();
+
+    let epcs = INSTANT_WATT_AMPERE
+        .edata
+        .iter()
+        .map(|edata| edata.epc)
+        .collect::<Vec<u8>>();
+    let result =
+        skstack::request_properties(&mut serial_port_reader, &mut serial_port, &sender, &epcs);
+    if let Ok(values) = &result {
+        for value in values.iter() {
+            println!("{}", value.as_edata().show(Some(&unit)));
         }
-        Ok(r @ skstack::SkRxD::Fail(_)) => {
-            tracing::trace!("{:?}", r);
+    }
+
+    // PANAセッションを終了する
+    skstack::send_line(&mut serial_port, "SKTERM")?;
+    match result {
+        Ok(values) if values.is_empty() => bail!("The smart meter did not answer."),
+        Ok(_) => Ok(()),
+        Err(e) => Err(e).context("instant power request failed"),
+    }
+}
+
+/// スマートメーターの時計が大きくずれていたら警告する
+fn check_meter_clock(tz: &Tz, frame: &EchonetliteFrame) {
+    use echonetlite::superclass::Properties;
+
+    let mut date = None;
+    let mut time = None;
+    for edata in frame.edata.iter() {
+        match Properties::try_from(edata) {
+            Ok(Properties::CurrentDateSetting(a)) => date = Some(a.0),
+            Ok(Properties::CurrentTimeSetting(a)) => time = Some(a.0),
+            _ => {}
+        }
+    }
+    if let Some((date, time)) = date.zip(time) {
+        // スマートメーターの時計は現地時刻
+        let now = Utc::now().with_timezone(tz).naive_local();
+        let skew = (date.and_time(time) - now).abs();
+        if skew > ALLOWED_METER_CLOCK_SKEW {
+            tracing::warn!(
+                "Meter clock {} {} is {}s away from this host ({}).",
+                date,
+                time.format("%H:%M"),
+                skew.num_seconds(),
+                now.format("%Y-%m-%d %H:%M:%S")
+            );
+        } else {
+            tracing::info!("Meter clock skew is {}s.", skew.num_seconds());
+        }
+    }
+}
+
+/// プロパティ値を要求する間隔(ARIB STD-T108 の送信時間制限を超えないように間をあける)
+const PROBE_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Getプロパティマップにある全てのプロパティ値を読み出して表示する
+fn exec_probe(cli: &Cli) -> anyhow::Result<()> {
+    let Connection {
+        settings,
+        sender,
+        mut serial_port,
+        mut serial_port_reader,
+    } = connect_smartmeter(cli)?;
+    let unit = settings.effective_unit();
+
+    // Getプロパティマップ
+    let value = skstack::request_property(
+        &mut serial_port_reader,
+        &mut serial_port,
+        &sender,
+        echonetlite::superclass::GetPropertyMap::EPC,
+    )
+    .context("Get property map request failed")?;
+    let map = echonetlite::superclass::GetPropertyMap::try_from(&value.as_edata())
+        .map_err(|s| anyhow!(s))?;
+    println!("{}", map);
+
+    for &epc in map.epcs() {
+        thread::sleep(PROBE_INTERVAL);
+        match skstack::request_property(&mut serial_port_reader, &mut serial_port, &sender, epc) {
+            Ok(value) => println!("0x{:02X}: {}", epc, value.as_edata().show(Some(&unit))),
+            Err(e) => println!("0x{:02X}: {}", epc, e),
         }
-        Ok(r @ skstack::SkRxD::Event(_)) => {
-            tracing::trace!("{:?}", r);
+    }
+
+    // PANAセッションを終了する
+    skstack::send_line(&mut serial_port, "SKTERM")?;
+    Ok(())
+}
+
+/// スマートメーターの時計をこのコンピューターの時計に合わせる
+fn exec_set_clock(cli: &Cli) -> anyhow::Result<()> {
+    use echonetlite::superclass::{CurrentDateSetting, CurrentTimeSetting};
+
+    let Connection {
+        sender,
+        mut serial_port,
+        mut serial_port_reader,
+        ..
+    } = connect_smartmeter(cli)?;
+
+    // 書き込む前の時計
+    let epcs = [CurrentDateSetting::EPC, CurrentTimeSetting::EPC];
+    match skstack::request_properties(&mut serial_port_reader, &mut serial_port, &sender, &epcs) {
+        Ok(values) => {
+            let clock = values
+                .iter()
+                .map(|value| value.as_edata().show(None))
+                .collect::<Vec<String>>();
+            println!("meter clock: {}", clock.join(" "));
         }
-        Ok(r @ skstack::SkRxD::Epandesc(_)) => {
-            tracing::trace!("{:?}", r);
+        Err(e) => println!("meter clock: {}", e),
+    }
+    thread::sleep(PROBE_INTERVAL);
+
+    // スマートメーターの時計は現地時刻
+    let now = Utc::now().with_timezone(&cli.timezone).naive_local();
+    let result =
+        match skstack::set_meter_clock(&mut serial_port_reader, &mut serial_port, &sender, &now) {
+            Ok(()) => {
+                println!("meter clock is set to {}.", now.format("%Y-%m-%d %H:%M"));
+                Ok(())
+            }
+            // Bルートでは時計の書き込みを受け付けないスマートメーターが多い
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                println!("This smart meter does not accept clock writes over Route B. ({e})");
+                Ok(())
+            }
+            Err(e) => Err(e),
+        };
+
+    // PANAセッションを終了する
+    skstack::send_line(&mut serial_port, "SKTERM")?;
+    result.context("set clock request failed")
+}
+
+/// 標準入力から読み込んだSKSTACKコマンドを送信して応答を表示する
+fn exec_raw(cli: &Cli) -> anyhow::Result<()> {
+    // シリアルポートを開く
+    let mut serial_port =
+        skstack::open_serial(&cli.device, cli.baud, Duration::from_millis(cli.timeout_ms))
+            .with_context(|| format!("Failed to open \"{}\".", cli.device))?;
+
+    // シリアルポート読み込みはバッファリングする
+    let mut serial_port_reader = serial_port
+        .try_clone()
+        .map(BufReader::new)
+        .context("Failed to clone")?;
+
+    for line in io::stdin().lines() {
+        let line = line?;
+        let command = line.trim();
+        if !command.is_empty() {
+            match skstack::send_line(&mut serial_port, command) {
+                Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                    println!("{}", e);
+                    continue;
+                }
+                r => r?,
+            }
         }
-        Ok(skstack::SkRxD::Erxudp(v)) => {
-            return Ok(Some(v));
+        // タイムアウトするまで受信した結果を表示する
+        for received in skstack::responses(&mut serial_port_reader) {
+            match received {
+                Ok(skstack::SkRxD::Void) => {}
+                Ok(r) => println!("{:?}", r),
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => break,
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => println!("{}", e),
+                Err(e) => return Err(e).context("serial port read failed!"),
+            }
         }
-        Err(e) if e.kind() == io::ErrorKind::TimedOut => {} // タイムアウトエラーは無視する
-        Err(e) => return Err(e).context("serial port read failed!"),
     }
-    Ok(None)
+    Ok(())
+}
+
+/// 16進文字列をバイト列にする(空白は無視する)
+fn parse_hex(s: &str) -> anyhow::Result<Vec<u8>> {
+    let digits = s
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<Vec<char>>();
+    if digits.len() % 2 != 0 {
+        bail!("odd number of hex digits ({})", digits.len());
+    }
+    digits
+        .chunks(2)
+        .enumerate()
+        .map(|(n, pair)| {
+            let hex = pair.iter().collect::<String>();
+            u8::from_str_radix(&hex, 16)
+                .with_context(|| format!(r#"invalid hex "{}" at byte {}"#, hex, n))
+        })
+        .collect()
+}
+
+/// 16進文字列のEchonetliteフレームを解析して表示する
+fn exec_decode(args: &DecodeArgs) -> anyhow::Result<()> {
+    let data = parse_hex(&args.hex)?;
+    let config = bincode::config::standard()
+        .with_big_endian()
+        .with_fixed_int_encoding();
+    let (frame, _len): (EchonetliteFrame, usize) = bincode::borrow_decode_from_slice(&data, config)
+        .context("echonet lite frame decode failed")?;
+    println!("{}", frame.show());
+    for v in frame.edata.iter() {
+        println!("{}", v.show(None));
+    }
+    Ok(())
 }
+
 fn main() -> anyhow::Result<()> {
     let subscriber = FmtSubscriber::builder()
         .with_max_level(tracing::Level::TRACE)
@@ -339,6 +761,10 @@ fn main() -> anyhow::Result<()> {
 
     match &cli.command {
         Commands::Pairing(args) => exec_pairing(&cli, args),
-        Commands::DryRun => exec_dryrun(&cli),
+        Commands::DryRun(args) => exec_dryrun(&cli, args),
+        Commands::Raw => exec_raw(&cli),
+        Commands::Probe => exec_probe(&cli),
+        Commands::SetClock => exec_set_clock(&cli),
+        Commands::Decode(args) => exec_decode(args),
     }
 }
@@ -3,13 +3,20 @@
 // SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
 //
 use anyhow::Context;
-use chrono::{DateTime, Utc};
-use chrono_tz::Asia;
-use clap::{Args, Parser, Subcommand};
-use futures_util::TryStreamExt;
+use chrono::{DateTime, FixedOffset, Utc};
+use chrono_tz::Tz;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use futures_util::stream::BoxStream;
+use futures_util::{StreamExt, TryStreamExt};
 use rust_decimal::Decimal;
 use sqlx::{self, postgres::PgPool};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
 use std::result;
+use uchinoepower::connection_settings::ConnectionSettings;
+use uchinoepower::echonetlite::smart_electric_energy_meter as SM;
+use uchinoepower::store::{self, Database, ReadRange, Reading};
 
 /// 測定値データーベースをいじる
 #[derive(Parser, Debug)]
@@ -20,6 +27,14 @@ struct Cli {
     #[arg(long, env = "DATABASE_URL")]
     database_url: String,
 
+    /// 時刻を表示するタイムゾーン
+    #[arg(long, env = "TIMEZONE", default_value_t = uchinoepower::DEFAULT_TIMEZONE, value_parser = uchinoepower::parse_timezone)]
+    timezone: Tz,
+
+    /// 対象のスマートメーター(settings.id) 指定しなければすべてのスマートメーター
+    #[arg(long, visible_alias = "settings-id", global = true)]
+    meter_id: Option<i64>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -32,6 +47,11 @@ enum Commands {
     /// 測定値の重複を整理する
     #[clap(alias = "unique-record")]
     Unique(UniqueArgs),
+    /// 期間内の測定値を削除する
+    #[clap(alias = "delete-record")]
+    Delete(DeleteArgs),
+    /// 測定値をCSV形式で書き出す
+    Export(ExportArgs),
 }
 
 #[derive(Debug, Args)]
@@ -39,14 +59,124 @@ struct GetArgs {
     /// レコード数
     #[arg(short = 'C', long, default_value_t = 10)]
     count: u32,
+    /// 期間の開始時刻(RFC3339) 指定するとレコード数は無視する
+    #[arg(long, requires = "to")]
+    from: Option<DateTime<FixedOffset>>,
+    /// 期間の終了時刻(RFC3339)
+    #[arg(long, requires = "from")]
+    to: Option<DateTime<FixedOffset>>,
+    /// 積算電力量を30分毎の使用量にする
+    /// --meter-id を指定しなければ最も新しい使用中のスマートメーター
+    #[arg(long, action)]
+    deltas: bool,
+}
+
+impl GetArgs {
+    /// 期間を指定した場合はその期間のすべて、そうでなければ最新の count 件
+    fn range(&self) -> ReadRange {
+        ReadRange::new(
+            self.count as i64,
+            self.from
+                .zip(self.to)
+                .map(|(from, to)| (from.to_utc(), to.to_utc())),
+        )
+    }
 }
 
 #[derive(Debug, Args)]
 struct UniqueArgs {
+    /// 対象のテーブル 指定しなければすべてのテーブル
+    #[arg(long, value_enum)]
+    table: Option<Table>,
     #[arg(long, action)]
     dryrun: bool,
 }
 
+#[derive(Debug, Args)]
+struct DeleteArgs {
+    /// 対象のテーブル
+    #[arg(long, value_enum)]
+    table: Table,
+    /// 期間の開始時刻(RFC3339)
+    #[arg(long)]
+    from: DateTime<FixedOffset>,
+    /// 期間の終了時刻(RFC3339)
+    #[arg(long)]
+    to: DateTime<FixedOffset>,
+    #[arg(long, action)]
+    dryrun: bool,
+}
+
+#[derive(Debug, Args)]
+struct ExportArgs {
+    /// 対象のテーブル
+    #[arg(long, value_enum)]
+    table: Table,
+    /// 期間の開始時刻(RFC3339) 指定しなければすべて
+    #[arg(long, requires = "to")]
+    from: Option<DateTime<FixedOffset>>,
+    /// 期間の終了時刻(RFC3339)
+    #[arg(long, requires = "from")]
+    to: Option<DateTime<FixedOffset>>,
+    /// 出力ファイル名 指定しなければ標準出力
+    #[arg(short = 'o', long)]
+    out: Option<PathBuf>,
+}
+
+/// 測定値のテーブル
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Table {
+    /// 瞬時電力
+    InstantEpower,
+    /// 瞬時電流
+    InstantCurrent,
+    /// 積算電力量(正方向計測値)
+    CumlativeAmountEpower,
+    /// 積算電力量(逆方向計測値)
+    ReverseCumlativeAmountEpower,
+}
+
+impl Table {
+    const ALL: [Table; 4] = [
+        Table::InstantEpower,
+        Table::InstantCurrent,
+        Table::CumlativeAmountEpower,
+        Table::ReverseCumlativeAmountEpower,
+    ];
+
+    /// テーブル名
+    fn name(&self) -> &'static str {
+        match self {
+            Table::InstantEpower => "instant_epower",
+            Table::InstantCurrent => "instant_current",
+            Table::CumlativeAmountEpower => "cumlative_amount_epower",
+            Table::ReverseCumlativeAmountEpower => "reverse_cumlative_amount_epower",
+        }
+    }
+
+    /// CSVのヘッダー行
+    fn csv_header(&self) -> &'static [&'static str] {
+        match self {
+            Table::InstantEpower => &["recorded_at", "watt"],
+            Table::InstantCurrent => &["recorded_at", "r", "t"],
+            Table::CumlativeAmountEpower | Table::ReverseCumlativeAmountEpower => {
+                &["recorded_at", "kwh"]
+            }
+        }
+    }
+
+    /// 測定値の列(a, b の2列にそろえる)
+    fn value_columns(&self) -> &'static str {
+        match self {
+            Table::InstantEpower => "watt AS a, NULL::NUMERIC AS b",
+            Table::InstantCurrent => "r AS a, t AS b",
+            Table::CumlativeAmountEpower | Table::ReverseCumlativeAmountEpower => {
+                "kwh AS a, NULL::NUMERIC AS b"
+            }
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenv::dotenv().ok();
@@ -54,157 +184,460 @@ async fn main() -> anyhow::Result<()> {
     // コマンドライン引数
     let cli = Cli::parse();
 
+    // 測定値の読み出しは PostgreSQL のみ
+    anyhow::ensure!(
+        !store::is_sqlite_url(&cli.database_url),
+        "manipulate_db は SQLite に対応していません(PostgreSQL の DATABASE_URL を指定してください)"
+    );
     let pool = PgPool::connect(&cli.database_url)
         .await
         .context("データベースとの接続失敗")?;
 
+    let meter_id = cli.meter_id;
     match &cli.command {
-        Commands::Get(args) => exec_get_record(&pool, args).await,
-        Commands::Unique(args) => exec_unique_record(&pool, args).await,
+        Commands::Get(args) => exec_get_record(&pool, &cli.timezone, meter_id, args).await,
+        Commands::Unique(args) => exec_unique_record(&pool, &cli.timezone, meter_id, args).await,
+        Commands::Delete(args) => exec_delete_record(&pool, &cli.timezone, meter_id, args).await,
+        Commands::Export(args) => exec_export(&pool, &cli.timezone, meter_id, args).await,
     }
 }
 
 /// 測定値の重複を整理する
-async fn exec_unique_record(pool: &PgPool, args: &UniqueArgs) -> anyhow::Result<()> {
-    #[derive(sqlx::FromRow, Eq, PartialEq, Default)]
-    struct Measure {
-        id: i64,
-        recorded_at: DateTime<Utc>,
-        kwh: Decimal,
+async fn exec_unique_record(
+    pool: &PgPool,
+    tz: &Tz,
+    meter_id: Option<i64>,
+    args: &UniqueArgs,
+) -> anyhow::Result<()> {
+    let tables = match args.table {
+        Some(table) => vec![table],
+        None => Table::ALL.to_vec(),
+    };
+    for table in tables {
+        unique_record(pool, tz, meter_id, table, args.dryrun).await?;
     }
+    Ok(())
+}
+
+/// 重複を整理する測定値の1行
+#[derive(sqlx::FromRow, Eq, PartialEq, Default)]
+struct Measure {
+    id: i64,
+    meter_id: Option<i64>,
+    recorded_at: DateTime<Utc>,
+    a: Decimal,
+    b: Option<Decimal>,
+}
+
+impl Measure {
+    /// 同じスマートメーターの同じ計測時刻の同じ値(id は問わない)
+    fn is_same(&self, other: &Measure) -> bool {
+        self.meter_id == other.meter_id
+            && self.recorded_at == other.recorded_at
+            && self.a == other.a
+            && self.b == other.b
+    }
+}
+
+/// 指定のテーブルの測定値の重複を整理する
+async fn unique_record(
+    pool: &PgPool,
+    tz: &Tz,
+    meter_id: Option<i64>,
+    table: Table,
+    dryrun: bool,
+) -> anyhow::Result<()> {
+    println!("table: {}", table.name());
 
     let mut delete_id = Vec::<i64>::new();
 
-    let mut rows = sqlx::query_as::<_, Measure>(
-        "SELECT id, recorded_at, kwh FROM cumlative_amount_epower ORDER BY recorded_at",
-    )
-    .fetch(pool);
+    // 削除する行の id が要るので store::read_* ではなくここで読み出す
+    // 重複した行が隣り合うように値でも並べる
+    // (同じ計測時刻に違う値の行が挟まっていても重複を見つけられる)
+    let sql = format!(
+        "SELECT id, meter_id, recorded_at, {} FROM {} WHERE {} ORDER BY meter_id, recorded_at, a, b, id",
+        table.value_columns(),
+        table.name(),
+        METER_ID_FILTER
+    );
+    let mut rows = sqlx::query_as::<_, Measure>(&sql)
+        .bind(meter_id)
+        .fetch(pool);
 
     let mut unique_record: Measure = Default::default();
     while let Some(row) = rows.try_next().await? {
         print!(
-            "{}, {}, {}",
+            "{}, {}, {}{}",
             row.id,
-            row.recorded_at.with_timezone(&Asia::Tokyo).to_rfc3339(),
-            row.kwh
+            row.recorded_at.with_timezone(tz).to_rfc3339(),
+            row.a,
+            row.b.map(|v| format!(", {v}")).unwrap_or_default()
         );
-        if unique_record.recorded_at == row.recorded_at && unique_record.kwh == row.kwh {
+        if unique_record.is_same(&row) {
             print!(" **This record is same as id {}**", unique_record.id);
             delete_id.push(row.id);
         }
-        println!("");
+        println!();
         unique_record = row;
     }
 
-    if !args.dryrun {
-        //
-        let transaction = pool.begin().await.context("transaction error")?;
-        //
-        let do_delete = async || -> anyhow::Result<i32> {
-            let mut counter = 0;
-            for id in delete_id {
-                sqlx::query("DELETE FROM cumlative_amount_epower WHERE id = $1")
-                    .bind(id)
-                    .execute(pool)
-                    .await?;
-                println!("id: {} has been deleted.", id);
-                counter = counter + 1;
-            }
-            Ok(counter)
-        };
-        match do_delete().await {
-            Ok(counter) => {
+    if !dryrun {
+        let counter = delete_records(pool, table, &delete_id).await?;
+        println!("Total {} records deleted.", counter);
+    }
+    println!();
+
+    Ok(())
+}
+
+/// 指定のテーブルから id のレコードを1つのトランザクションで削除する
+/// 途中で失敗したら何も削除しない
+async fn delete_records<DB>(
+    pool: &sqlx::Pool<DB>,
+    table: Table,
+    delete_id: &[i64],
+) -> anyhow::Result<u64>
+where
+    DB: sqlx::Database,
+    for<'c> &'c mut DB::Connection: sqlx::Executor<'c, Database = DB>,
+    for<'q> DB::Arguments<'q>: sqlx::IntoArguments<'q, DB>,
+    for<'q> i64: sqlx::Encode<'q, DB> + sqlx::Type<DB>,
+{
+    let mut transaction = pool.begin().await.context("transaction error")?;
+    let sql = format!("DELETE FROM {} WHERE id = $1", table.name());
+    let mut counter = 0;
+    for id in delete_id {
+        if let Err(e) = sqlx::query::<DB>(&sql)
+            .bind(*id)
+            .execute(&mut *transaction)
+            .await
+        {
+            transaction.rollback().await.context("rollback failure")?;
+            println!("All deletions have been rolled back.");
+            return Err(e).context("delete failure");
+        }
+        println!("id: {} has been deleted.", id);
+        counter += 1;
+    }
+    transaction.commit().await.context("commit failure")?;
+    Ok(counter)
+}
+
+/// 期間内の測定値を削除する
+async fn exec_delete_record(
+    pool: &PgPool,
+    tz: &Tz,
+    meter_id: Option<i64>,
+    args: &DeleteArgs,
+) -> anyhow::Result<()> {
+    let from = args.from.to_utc();
+    let to = args.to.to_utc();
+    anyhow::ensure!(from <= to, "--from must not be after --to");
+
+    println!(
+        "table: {}, from: {}, to: {}",
+        args.table.name(),
+        from.with_timezone(tz).to_rfc3339(),
+        to.with_timezone(tz).to_rfc3339()
+    );
+
+    if args.dryrun {
+        // 削除する測定値を表示する(get --from --to と同じ読み出し)
+        let mut rows = read_fields(pool, tz, meter_id, args.table, ReadRange::Between(from, to));
+        let mut count = 0;
+        while let Some(fields) = rows.try_next().await? {
+            println!("{}", fields.join(", "));
+            count += 1;
+        }
+        println!("{} records will be deleted.", count);
+    } else {
+        let mut transaction = pool.begin().await.context("transaction error")?;
+        let sql = format!(
+            "DELETE FROM {} WHERE recorded_at BETWEEN $2 AND $3 AND {}",
+            args.table.name(),
+            METER_ID_FILTER
+        );
+        match sqlx::query(&sql)
+            .bind(meter_id)
+            .bind(from)
+            .bind(to)
+            .execute(&mut *transaction)
+            .await
+        {
+            Ok(result) => {
                 transaction.commit().await.context("commit failure")?;
-                println!("Total {} records deleted.", counter);
+                println!("Total {} records deleted.", result.rows_affected());
             }
             Err(e) => {
                 transaction.rollback().await.context("rollback failure")?;
-                println!("Error: {}", e);
+                println!("All deletions have been rolled back.");
+                return Err(e).context("delete failure");
             }
         }
-        //
     }
 
     Ok(())
 }
 
+/// --meter-id で絞り込む条件(1番目のバインドパラメーターが meter_id)
+/// 指定しなければすべてのスマートメーター
+const METER_ID_FILTER: &str = "($1::BIGINT IS NULL OR meter_id = $1)";
+
+/// スマートメーターの設定情報をデーターベースから得る
+/// 指定しなければ最も新しいもの
+async fn read_settings(pool: &PgPool, meter_id: Option<i64>) -> Option<ConnectionSettings> {
+    let db = Database::Postgres(pool.clone());
+    match meter_id {
+        Some(id) => db.read_settings_by_id(id).await.ok(),
+        None => db.read_settings().await.ok(),
+    }
+}
+
+/// CSVの1行(RFC4180)
+fn csv_record<S: AsRef<str>>(fields: &[S]) -> String {
+    let fields = fields
+        .iter()
+        .map(|field| {
+            let field = field.as_ref();
+            if field.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<String>>();
+    format!("{}\r\n", fields.join(","))
+}
+
+/// 指定のテーブルの測定値を文字列の列にして時刻の古い順に1行ずつ返す
+/// (計測時刻, 値..) 計測時刻は tz の RFC3339
+fn read_fields<'a>(
+    pool: &'a PgPool,
+    tz: &'a Tz,
+    meter_id: Option<i64>,
+    table: Table,
+    range: ReadRange,
+) -> BoxStream<'a, result::Result<Vec<String>, sqlx::Error>> {
+    let local = |at: DateTime<Utc>| at.with_timezone(tz).to_rfc3339();
+    match table {
+        Table::InstantEpower => store::read_instant_power(pool, meter_id, range)
+            .map_ok(move |(at, watt)| vec![local(at), watt.to_string()])
+            .boxed(),
+        Table::InstantCurrent => store::read_current(pool, meter_id, range)
+            .map_ok(move |(at, r, t)| {
+                vec![
+                    local(at),
+                    r.to_string(),
+                    t.map(|v| v.to_string()).unwrap_or_default(),
+                ]
+            })
+            .boxed(),
+        Table::CumlativeAmountEpower => store::read_cumulative(pool, meter_id, range)
+            .map_ok(move |(at, kwh)| vec![local(at), kwh.to_string()])
+            .boxed(),
+        Table::ReverseCumlativeAmountEpower => {
+            store::read_reverse_cumulative(pool, meter_id, range)
+                .map_ok(move |(at, kwh)| vec![local(at), kwh.to_string()])
+                .boxed()
+        }
+    }
+}
+
+/// 測定値をCSV形式で書き出す
+async fn exec_export(
+    pool: &PgPool,
+    tz: &Tz,
+    meter_id: Option<i64>,
+    args: &ExportArgs,
+) -> anyhow::Result<()> {
+    let mut out: Box<dyn Write> = match &args.out {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).with_context(|| format!("{} create failed", path.display()))?,
+        )),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    out.write_all(csv_record(args.table.csv_header()).as_bytes())?;
+
+    // 期間を指定しなければ今までのすべて
+    let range = match args.from.zip(args.to) {
+        Some((from, to)) => ReadRange::Between(from.to_utc(), to.to_utc()),
+        None => ReadRange::Between(DateTime::UNIX_EPOCH, Utc::now()),
+    };
+    // 大きなテーブルでもメモリを使い果たさないように1行ずつ書き出す
+    let mut rows = read_fields(pool, tz, meter_id, args.table, range);
+    while let Some(fields) = rows.try_next().await? {
+        out.write_all(csv_record(&fields).as_bytes())?;
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
 /// 測定値を得る
-async fn exec_get_record(pool: &PgPool, args: &GetArgs) -> anyhow::Result<()> {
-    //
-    let xs = read_instant_epower(&pool, args.count as i64).await?;
+async fn exec_get_record(
+    pool: &PgPool,
+    tz: &Tz,
+    meter_id: Option<i64>,
+    args: &GetArgs,
+) -> anyhow::Result<()> {
+    // 受け取った行から順に表示する
+    let mut rows = store::read_instant_power(pool, meter_id, args.range());
     println!("time, instantious electric power(W)");
-    for (at, power) in xs.iter() {
-        let t = at.with_timezone(&Asia::Tokyo).to_rfc3339();
+    while let Some((at, power)) = rows.try_next().await? {
+        let t = at.with_timezone(tz).to_rfc3339();
         println!("{t}, {power}");
     }
-    println!("");
+    println!();
 
-    let xs = read_instant_current(&pool, args.count as i64).await?;
+    let mut rows = store::read_current(pool, meter_id, args.range());
     println!("time, instantious current R(A), T(A)");
-    for (at, ir, it) in xs.iter() {
-        let t = at.with_timezone(&Asia::Tokyo).to_rfc3339();
+    while let Some((at, ir, it)) = rows.try_next().await? {
+        let t = at.with_timezone(tz).to_rfc3339();
         println!(
             "{t}, {ir}{}",
             it.map(|v| format!(", {v}")).unwrap_or_default()
         );
     }
-    println!("");
+    println!();
 
-    let xs = read_cumlative_amount_epower(&pool, args.count as i64).await?;
-    println!("time, cumlative amounts of power(kWh)");
-    for (at, power) in xs.iter() {
-        let t = at.with_timezone(&Asia::Tokyo).to_rfc3339();
-        println!("{t}, {power}");
+    // 別々のスマートメーターの積算電力量の差分には意味が無いので
+    // 30分毎の使用量はひとつのスマートメーターに限る
+    let kwh_meter_id = match meter_id {
+        None if args.deltas => Some(
+            Database::Postgres(pool.clone())
+                .read_newest_meter_id()
+                .await
+                .context("smart meter settings not found")?,
+        ),
+        _ => meter_id,
+    };
+
+    // 積算電力量が一巡する値
+    let wrap_kwh = if args.deltas {
+        read_settings(pool, kwh_meter_id)
+            .await
+            .and_then(|settings| settings.wrap_kwh())
+    } else {
+        None
+    };
+
+    let rows = store::read_cumulative(pool, kwh_meter_id, args.range());
+    if args.deltas {
+        println!("time, electric power usage per 30 minutes(kWh)");
+        print_kwh_deltas(tz, rows, wrap_kwh).await?;
+    } else {
+        println!("time, cumlative amounts of power(kWh)");
+        print_kwh(tz, rows).await?;
+    }
+    println!();
+
+    let rows = store::read_reverse_cumulative(pool, kwh_meter_id, args.range());
+    if args.deltas {
+        println!("time, reverse electric power usage per 30 minutes(kWh)");
+        print_kwh_deltas(tz, rows, wrap_kwh).await?;
+    } else {
+        println!("time, reverse cumlative amounts of power(kWh)");
+        print_kwh(tz, rows).await?;
     }
-    println!("");
+    println!();
 
     Ok(())
 }
 
-/// 瞬時電力をデーターベースから得る
-async fn read_instant_epower(
-    pool: &PgPool,
-    count: i64,
-) -> result::Result<Vec<(DateTime<Utc>, Decimal)>, sqlx::Error> {
-    let mut recs = sqlx::query!(
-        "SELECT recorded_at, watt FROM instant_epower ORDER BY recorded_at DESC LIMIT $1",
-        count
-    )
-    .fetch_all(pool)
-    .await?;
-
-    recs.reverse();
-    Ok(recs.iter().map(|a| (a.recorded_at, a.watt)).collect())
+/// 積算電力量を受け取った行から順に表示する
+async fn print_kwh(
+    tz: &Tz,
+    mut rows: BoxStream<'_, result::Result<Reading, sqlx::Error>>,
+) -> anyhow::Result<()> {
+    while let Some((at, kwh)) = rows.try_next().await? {
+        let t = at.with_timezone(tz).to_rfc3339();
+        println!("{t}, {kwh}");
+    }
+    Ok(())
 }
 
-/// 瞬時電流をデーターベースから得る
-async fn read_instant_current(
-    pool: &PgPool,
-    count: i64,
-) -> result::Result<Vec<(DateTime<Utc>, Decimal, Option<Decimal>)>, sqlx::Error> {
-    let mut recs = sqlx::query!(
-        "SELECT recorded_at, r, t FROM instant_current ORDER BY recorded_at DESC LIMIT $1",
-        count
-    )
-    .fetch_all(pool)
-    .await?;
+/// 積算電力量から30分毎の使用量を求めて表示する
+async fn print_kwh_deltas(
+    tz: &Tz,
+    rows: BoxStream<'_, result::Result<Reading, sqlx::Error>>,
+    wrap_kwh: Option<Decimal>,
+) -> anyhow::Result<()> {
+    let readings = rows.try_collect::<Vec<_>>().await?;
+    for (at, kwh) in SM::consecutive_deltas(&readings, wrap_kwh) {
+        let t = at.with_timezone(tz).to_rfc3339();
+        println!("{t}, {kwh}");
+    }
+    Ok(())
+}
 
-    recs.reverse();
-    Ok(recs.iter().map(|a| (a.recorded_at, a.r, a.t)).collect())
+#[test]
+fn test1() {
+    let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().to_utc();
+    let measure = |id: i64, meter_id: i64, recorded_at: &str, a: i64| Measure {
+        id,
+        meter_id: Some(meter_id),
+        recorded_at: at(recorded_at),
+        a: Decimal::from(a),
+        b: None,
+    };
+    // 隣り合う行を比べて重複を見つける(SQLで meter_id, recorded_at, 値, id の順に並べている)
+    let duplicate_ids = |rows: &[Measure]| {
+        rows.windows(2)
+            .filter(|pair| pair[0].is_same(&pair[1]))
+            .map(|pair| pair[1].id)
+            .collect::<Vec<i64>>()
+    };
+    let rows = [
+        measure(1, 1, "2025-07-13T12:00:00Z", 100),
+        // id 順なら間に同じ計測時刻の違う値(id 3)が挟まっていても重複になる
+        measure(4, 1, "2025-07-13T12:00:00Z", 100),
+        measure(3, 1, "2025-07-13T12:00:00Z", 200),
+        // 別のスマートメーターの同じ計測時刻の同じ値は重複ではない
+        measure(2, 2, "2025-07-13T12:00:00Z", 100),
+        measure(5, 2, "2025-07-13T12:01:00Z", 100),
+        measure(6, 2, "2025-07-13T12:01:00Z", 100),
+        measure(7, 2, "2025-07-13T12:01:00Z", 100),
+    ];
+    assert_eq!(duplicate_ids(&rows), vec![4, 6, 7]);
+    assert_eq!(duplicate_ids(&[]), Vec::<i64>::new());
 }
 
-/// 定時積算電力量計測値(正方向計測値)をデーターベースから得る
-async fn read_cumlative_amount_epower(
-    pool: &PgPool,
-    count: i64,
-) -> result::Result<Vec<(DateTime<Utc>, Decimal)>, sqlx::Error> {
-    let mut recs = sqlx::query!(
-        "SELECT recorded_at, kwh FROM cumlative_amount_epower ORDER BY recorded_at DESC LIMIT $1",
-        count
+#[tokio::test]
+async fn test2() {
+    // 途中で削除に失敗したら何も削除しない
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    let Database::Sqlite(pool) = &db else {
+        unreachable!()
+    };
+    sqlx::query(
+        "INSERT INTO instant_epower (meter_id, recorded_at, watt) VALUES (1, '2025-07-13T12:00:00Z', '100'), (1, '2025-07-13T12:00:00Z', '100'), (1, '2025-07-13T12:00:00Z', '100')",
     )
-    .fetch_all(pool)
-    .await?;
-
-    recs.reverse();
-    Ok(recs.iter().map(|a| (a.recorded_at, a.kwh)).collect())
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "CREATE TRIGGER fail_delete BEFORE DELETE ON instant_epower WHEN OLD.id = 3 BEGIN SELECT RAISE(ABORT, 'fail'); END",
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    let count = || async {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM instant_epower")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    };
+    assert!(
+        delete_records(pool, Table::InstantEpower, &[1, 2, 3])
+            .await
+            .is_err()
+    );
+    assert_eq!(count().await, 3);
+    assert_eq!(
+        delete_records(pool, Table::InstantEpower, &[1, 2])
+            .await
+            .unwrap(),
+        2
+    );
+    assert_eq!(count().await, 1);
 }
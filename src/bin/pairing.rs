@@ -3,16 +3,18 @@
 // SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
 //
 use anyhow::{Context, anyhow};
+use chrono::Utc;
 use clap::Parser;
-use serialport::{DataBits, SerialPort, StopBits};
-use sqlx::PgPool;
 use std::env;
 use std::io::BufReader;
 use std::str::FromStr;
 use std::time::Duration;
 use tracing_subscriber::FmtSubscriber;
+use uchinoepower::connection_settings::ConnectionSettings;
+use uchinoepower::echonetlite::smart_electric_energy_meter as SM;
 use uchinoepower::pairing;
-use uchinoepower::skstack::authn;
+use uchinoepower::skstack::{self, authn};
+use uchinoepower::store::Database;
 
 /// 接続対象のスマートメーターを探す
 #[derive(Parser, Debug)]
@@ -27,27 +29,90 @@ struct Cli {
     #[arg(short = 'D', long, default_value = "/dev/ttyUSB0")]
     device: String,
 
+    /// シリアルポートのボーレート
+    #[arg(long, default_value_t = skstack::DEFAULT_BAUD_RATE, value_parser = skstack::parse_baud_rate)]
+    baud: u32,
+
+    /// シリアルポートの読み込みタイムアウト(ミリ秒)
+    #[arg(long, default_value_t = skstack::DEFAULT_TIMEOUT_MS, value_parser = clap::value_parser!(u64).range(1..))]
+    timeout_ms: u64,
+
     /// アクティブスキャン時間(1～14)
     #[arg(short = 'T', long, default_value_t = 6)]
     activescan: usize,
 
+    /// データーベースに蓄積した接続情報からスマートメーターが対応するプロパティを表示する
+    #[arg(long)]
+    show_capabilities: bool,
+
+    /// データーベースに蓄積した接続情報をすべて表示する
+    #[arg(long)]
+    list: bool,
+
+    /// 指定した id の接続情報を使う(最も新しい接続情報にする)
+    #[arg(long, value_name = "ID")]
+    activate: Option<i64>,
+
     /// ルートBID(32文字)
-    id: String,
+    #[arg(required_unless_present_any = ["show_capabilities", "list", "activate"])]
+    id: Option<String>,
 
     /// ルートBパスワード(12文字)
-    password: String,
+    #[arg(required_unless_present_any = ["show_capabilities", "list", "activate"])]
+    password: Option<String>,
 }
 
-/// シリアルポートを開く
-fn open_port(port_name: &str) -> anyhow::Result<Box<dyn SerialPort>> {
-    let builder = serialport::new(port_name, 115200)
-        .stop_bits(StopBits::One)
-        .data_bits(DataBits::Eight)
-        .timeout(Duration::from_secs(1));
+/// ルートBIDの末尾4文字以外をマスクする
+fn mask_route_b_id(id: &str) -> String {
+    let len = id.chars().count();
+    id.chars()
+        .enumerate()
+        .map(|(n, c)| if n + 4 < len { '#' } else { c })
+        .collect()
+}
 
-    builder
-        .open()
-        .with_context(move || format!("Failed to open \"{}\".", port_name))
+/// 接続情報をすべて表示する
+/// active_ids は uchino_daqd が使う接続情報の id
+fn show_settings_list(rows: &[(i64, ConnectionSettings)], active_ids: &[i64]) {
+    println!(
+        "  {:>4} {:>7} {:>6} {:<16} {:<32} {:<14} activated at",
+        "id", "channel", "PAN ID", "MAC address", "Route-B ID", "serial device"
+    );
+    for (id, settings) in rows {
+        println!(
+            "{} {:>4} {:>7} {:>6} {:<16} {:<32} {:<14} {}",
+            if active_ids.contains(id) { '*' } else { ' ' },
+            id,
+            format!("0x{:02X}", settings.Channel),
+            format!("{:04X}", settings.PanId),
+            settings.MacAddress,
+            mask_route_b_id(&settings.RouteBId),
+            settings.SerialDevice.as_deref().unwrap_or("-"),
+            settings
+                .ActivatedAt
+                .map_or("-".to_string(), |a| a.to_rfc3339())
+        );
+    }
+    println!("(* は uchino_daqd が使う接続情報)");
+}
+
+/// スマートメーターが対応するプロパティを表示する
+fn show_capabilities(settings: &ConnectionSettings) {
+    println!("MAC address: {}", settings.MacAddress);
+    let Some(epcs) = settings.GetPropertyMap.as_ref() else {
+        println!("Get property map is not stored, run pairing again to capture it.");
+        return;
+    };
+    let mut epcs = epcs.clone();
+    epcs.sort();
+    println!("Get property map ({} properties):", epcs.len());
+    for epc in epcs {
+        println!(
+            "  0x{:02X} {}",
+            epc,
+            SM::property_name(epc).unwrap_or("(unknown)")
+        );
+    }
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -67,20 +132,69 @@ async fn main() -> anyhow::Result<()> {
     // コマンドライン引数
     let cli = Cli::parse();
 
-    let credentials = authn::Credentials {
-        id: authn::Id::from_str(&cli.id).map_err(|s| anyhow!(s))?,
-        password: authn::Password::from_str(&cli.password).map_err(|s| anyhow!(s))?,
-    };
-
     if let Some(database_url) = cli
         .database_url
         .or(env::var("DATABASE_URL").map(|a| a.to_string()).ok())
     {
         // データーベースプール
-        let pool = PgPool::connect(&database_url).await?;
+        let db = Database::connect(&database_url).await?;
+
+        // 接続し直さずにデーターベースの接続情報から表示する
+        if cli.show_capabilities {
+            let settings = db.read_settings().await.map_err(|e| match e {
+                sqlx::Error::RowNotFound => anyhow!("no settings in database, run pairing first"),
+                e => anyhow!(e),
+            })?;
+            show_capabilities(&settings);
+            return Ok(());
+        }
+
+        // 接続情報を切り替える
+        if let Some(id) = cli.activate {
+            let settings = db.activate_settings(id).await.map_err(|e| match e {
+                sqlx::Error::RowNotFound => anyhow!("settings id={id} is not found"),
+                e => anyhow!(e),
+            })?;
+            println!(
+                "settings id={} (MAC address {}) is activated.",
+                id, settings.MacAddress
+            );
+        }
+
+        // 接続情報をすべて表示する
+        if cli.list || cli.activate.is_some() {
+            let rows = db.read_all_settings().await?;
+            if rows.is_empty() {
+                println!("no settings in database, run pairing first");
+                return Ok(());
+            }
+            let active_ids = db
+                .read_active_settings()
+                .await?
+                .iter()
+                .map(|(id, _)| *id)
+                .collect::<Vec<i64>>();
+            show_settings_list(&rows, &active_ids);
+            if let Some(id) = cli.activate
+                && !active_ids.contains(&id)
+            {
+                // シリアルデバイス名のある接続情報があるとシリアルデバイス名の無い以前の接続情報は使わない
+                println!("settings id={id} has no serial device, run pairing again to use it.");
+            }
+            return Ok(());
+        }
+
+        let credentials = authn::Credentials {
+            id: authn::Id::from_str(cli.id.as_deref().unwrap_or_default())
+                .map_err(|s| anyhow!(s))?,
+            password: authn::Password::from_str(cli.password.as_deref().unwrap_or_default())
+                .map_err(|s| anyhow!(s))?,
+        };
 
         // シリアルポートを開く
-        let mut port = open_port(&cli.device)?;
+        let mut port =
+            skstack::open_serial(&cli.device, cli.baud, Duration::from_millis(cli.timeout_ms))
+                .with_context(|| format!("Failed to open \"{}\".", cli.device))?;
 
         // シリアルポート読み込みはバッファリングする
         let mut reader = port
@@ -90,15 +204,14 @@ async fn main() -> anyhow::Result<()> {
 
         // 接続するスマートメーターをアクティブスキャンで探して設定ファイルに情報を保存する
         match pairing(&mut reader, &mut port, cli.activescan, &credentials)? {
-            Some(settings) => {
+            Some(mut settings) => {
+                // このシリアルデバイスでスマートメーターと通信する
+                settings.SerialDevice = Some(cli.device.clone());
+                // 最も新しい接続情報として使い始める
+                settings.ActivatedAt = Some(Utc::now());
                 // データーベースに蓄積する
-                let rec = sqlx::query!(
-                    "INSERT INTO settings ( note ) VALUES ( $1 ) RETURNING id",
-                    sqlx::types::Json(settings) as _
-                )
-                .fetch_one(&pool)
-                .await?;
-                Ok(println!("successfully finished, id={}", rec.id))
+                let id = db.insert_settings(&settings).await?;
+                Ok(println!("successfully finished, id={}", id))
             }
             None => Ok(println!("Could not find smart meter.")),
         }
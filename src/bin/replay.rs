@@ -0,0 +1,132 @@
+// JSONに書き出した受信値をデーターベースに蓄積しなおす
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use anyhow::{Context, anyhow};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use clap::Parser;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+use uchinoepower::commit;
+use uchinoepower::echonetlite::smart_electric_energy_meter as SM;
+use uchinoepower::store::Database;
+
+/// JSONに書き出した受信値をデーターベースに蓄積しなおす
+#[derive(Parser, Debug)]
+#[command(name = "replay")]
+#[command(version, about, long_about = None)]
+struct Cli {
+    /// データベースURL("sqlite:" で始まるならSQLite)
+    #[arg(long, env = "DATABASE_URL")]
+    database_url: String,
+
+    /// 測定値の meter_id (settings.id)
+    #[arg(long, default_value_t = 1)]
+    meter_id: i64,
+
+    /// 積算電力量単位(例 "0.1 kwh")
+    /// JSONに積算電力量単位(unit_for_cumlative_amounts_power)があればそれを使う
+    #[arg(long, value_parser = parse_unit)]
+    unit: Option<SM::UnitForCumlativeAmountsPower>,
+
+    /// スマートメーターの時計のタイムゾーン
+    #[arg(long, env = "TIMEZONE", default_value_t = uchinoepower::DEFAULT_TIMEZONE, value_parser = uchinoepower::parse_timezone)]
+    timezone: Tz,
+
+    /// 受信値のJSONファイル
+    /// [{"recorded_at":"2025-07-13T03:00:00Z","type":"instant_power","watt":1234}, ...]
+    file: PathBuf,
+}
+
+/// 積算電力量単位を設定ファイルと同じ書式で読む
+fn parse_unit(s: &str) -> Result<SM::UnitForCumlativeAmountsPower, String> {
+    serde_json::from_value(serde_json::Value::String(s.to_string())).map_err(|e| e.to_string())
+}
+
+/// 受信値
+#[derive(Debug, Deserialize)]
+struct Record {
+    /// 受信時刻
+    recorded_at: DateTime<Utc>,
+    /// プロパティ値
+    #[serde(flatten)]
+    property: SM::Properties,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenv::dotenv().ok();
+
+    // コマンドライン引数
+    let cli = Cli::parse();
+
+    let json = fs::read_to_string(&cli.file)
+        .with_context(|| format!("Failed to read \"{}\".", cli.file.display()))?;
+    let records = serde_json::from_str::<Vec<Record>>(&json).context("JSON parse error")?;
+
+    let db = Database::connect(&cli.database_url)
+        .await
+        .context("データベースとの接続失敗")?;
+
+    // 積算電力量単位と係数はJSONの途中で変わってもよい
+    let mut unit = cli.unit.clone();
+    let mut coefficient = SM::Coefficient(1);
+    let mut committed = 0usize;
+    let mut skipped = 0usize;
+    // まとめて1つのトランザクションで蓄積する
+    let mut tx = db.begin().await?;
+    for record in records.iter() {
+        match &record.property {
+            SM::Properties::UnitForCumlativeAmountsPower(a) => unit = Some(a.clone()),
+            SM::Properties::Coefficient(a) => coefficient = a.clone(),
+            _ => {}
+        }
+        let effective_unit = match (unit.as_ref(), is_cumulative(&record.property)) {
+            (Some(a), _) => a.with_coefficient(&coefficient),
+            (None, true) => {
+                return Err(anyhow!(
+                    "unit is unknown at {}, use --unit option",
+                    record.recorded_at
+                ));
+            }
+            // 積算電力量単位を使わないプロパティ
+            (None, false) => SM::UnitForCumlativeAmountsPower(Decimal::ONE),
+        };
+        if commit::commit_property(
+            &mut tx,
+            cli.meter_id,
+            &cli.timezone,
+            &effective_unit,
+            &record.recorded_at,
+            &record.recorded_at,
+            &record.property,
+        )
+        .await?
+        {
+            committed += 1;
+        } else {
+            skipped += 1;
+        }
+    }
+    tx.commit().await?;
+    db.close().await;
+
+    println!(
+        "{} records committed, {} records skipped.",
+        committed, skipped
+    );
+    Ok(())
+}
+
+/// 積算電力量単位を使うプロパティか
+fn is_cumulative(property: &SM::Properties) -> bool {
+    matches!(
+        property,
+        SM::Properties::HistoricalCumlativeAmount(_)
+            | SM::Properties::CumlativeAmountsOfPowerAtFixedTime(_)
+            | SM::Properties::ReverseCumlativeAmountsOfPowerAtFixedTime(_)
+    )
+}
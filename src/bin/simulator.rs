@@ -0,0 +1,385 @@
+// スマートメーターとWiSUNモジュールのふりをするシミュレーター
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use anyhow::{Context, bail};
+use chrono::{Datelike, Timelike, Utc};
+use chrono_tz::Tz;
+use clap::Parser;
+use serialport::{SerialPort, TTYPort};
+use std::f64::consts::PI;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::Ipv6Addr;
+use std::os::unix::fs::symlink;
+use std::path::PathBuf;
+use std::time::Instant;
+use tracing_subscriber::FmtSubscriber;
+use uchinoepower::echonetlite::{EchonetliteEdata, EchonetliteFrame};
+use uchinoepower::skstack::{self, redact};
+
+/// スマートメーターのふりをして、擬似端末でSKSTACKコマンドに応答する。
+#[derive(Parser, Debug)]
+#[command(name = "simulator")]
+#[command(version, about, long_about = None)]
+struct Cli {
+    /// 擬似端末へのシンボリックリンクを作る
+    #[arg(short = 'L', long)]
+    link: Option<PathBuf>,
+
+    /// スマートメーターのMACアドレス(16進数)
+    #[arg(long, default_value = "123456789ABCDEF0")]
+    mac_address: String,
+
+    /// スマートメーターの時計のタイムゾーン
+    #[arg(long, env = "TIMEZONE", default_value_t = uchinoepower::DEFAULT_TIMEZONE, value_parser = uchinoepower::parse_timezone)]
+    timezone: Tz,
+}
+
+/// チャンネル
+const CHANNEL: u8 = 0x21;
+
+/// PAN ID
+const PAN_ID: u16 = 0x1234;
+
+/// 積算電力量単位(0.1kWh)
+const UNIT_0_1_KWH: u8 = 0x01;
+
+/// Getプロパティマップ
+const GET_PROPERTY_MAP: [u8; 13] = [
+    0x80, 0x8a, 0x97, 0x98, 0x9f, 0xd3, 0xd7, 0xe0, 0xe1, 0xe2, 0xe7, 0xe8, 0xea,
+];
+
+/// 模擬スマートメーター
+struct Meter {
+    mac_address: u64,
+    timezone: Tz,
+    started_at: Instant,
+    /// 起動時の積算電力量(0.1kWh単位)
+    initial_cumlative: u32,
+}
+
+impl Meter {
+    /// IPv6リンクローカルアドレス
+    fn address(&self) -> String {
+        let address = Ipv6Addr::from_bits(
+            0xFE80_0000_0000_0000u128 << 64
+                | (self.mac_address as u128 ^ 0x0200_0000_0000_0000u128),
+        );
+        address.segments().map(|n| format!("{:04X}", n)).join(":")
+    }
+
+    /// 瞬時電力(W) 1分周期で 200W から 1800W を行き来する
+    fn instant_watt(&self) -> i32 {
+        let t = self.started_at.elapsed().as_secs_f64();
+        (1000.0 + 800.0 * (2.0 * PI * t / 60.0).sin()) as i32
+    }
+
+    /// 積算電力量(0.1kWh単位) 平均1kWで増える
+    fn cumlative(&self) -> u32 {
+        let hours = self.started_at.elapsed().as_secs_f64() / 3600.0;
+        self.initial_cumlative + (hours * 10.0) as u32
+    }
+
+    /// プロパティ値(EDT)を返す、読み出せないプロパティは None
+    fn property(&self, epc: u8) -> Option<Vec<u8>> {
+        let now = Utc::now().with_timezone(&self.timezone);
+        match epc {
+            // 動作状態
+            0x80 => Some(vec![0x30]),
+            // メーカーコード
+            0x8a => Some(vec![0xff, 0xff, 0xff]),
+            // 現在時刻設定
+            0x97 => Some(vec![now.hour() as u8, now.minute() as u8]),
+            // 現在年月日設定
+            0x98 => {
+                let year = (now.year() as u16).to_be_bytes();
+                Some(vec![year[0], year[1], now.month() as u8, now.day() as u8])
+            }
+            // Getプロパティマップ
+            0x9f => Some([&[GET_PROPERTY_MAP.len() as u8], &GET_PROPERTY_MAP[..]].concat()),
+            // 係数
+            0xd3 => Some(vec![1]),
+            // 積算電力量有効桁数
+            0xd7 => Some(vec![6]),
+            // 積算電力量計測値(正方向計測値)
+            0xe0 => Some(self.cumlative().to_be_bytes().to_vec()),
+            // 積算電力量単位
+            0xe1 => Some(vec![UNIT_0_1_KWH]),
+            // 積算電力量計測値履歴1 (今日の午前０時から現在まで)
+            0xe2 => {
+                let current = now.hour() * 2 + now.minute() / 30;
+                let cumlative = self.cumlative();
+                let mut edt = 0u16.to_be_bytes().to_vec();
+                for n in 0..48u32 {
+                    let value = if n <= current {
+                        cumlative.saturating_sub(5 * (current - n))
+                    } else {
+                        0xffff_fffe // 計測値なし
+                    };
+                    edt.extend_from_slice(&value.to_be_bytes());
+                }
+                Some(edt)
+            }
+            // 瞬時電力計測値
+            0xe7 => Some(self.instant_watt().to_be_bytes().to_vec()),
+            // 瞬時電流計測値 (0.1A単位) 100V単相3線式としてR相とT相に振り分ける
+            0xe8 => {
+                let deciampere = (self.instant_watt() / 20) as i16;
+                let r = (deciampere * 6 / 10).to_be_bytes();
+                let t = (deciampere * 4 / 10).to_be_bytes();
+                Some(vec![r[0], r[1], t[0], t[1]])
+            }
+            // 定時積算電力量計測値(正方向計測値)
+            0xea => {
+                let minute = if now.minute() < 30 { 0 } else { 30 };
+                let year = (now.year() as u16).to_be_bytes();
+                let mut edt = vec![
+                    year[0],
+                    year[1],
+                    now.month() as u8,
+                    now.day() as u8,
+                    now.hour() as u8,
+                    minute,
+                    0,
+                ];
+                edt.extend_from_slice(&self.cumlative().to_be_bytes());
+                Some(edt)
+            }
+            _ => None,
+        }
+    }
+
+    /// Get要求に応答する
+    fn get_response(&self, request: &EchonetliteFrame) -> anyhow::Result<Vec<u8>> {
+        let values = request
+            .edata
+            .iter()
+            .map(|v| (v.epc, self.property(v.epc)))
+            .collect::<Vec<(u8, Option<Vec<u8>>)>>();
+        // 1つでも読み出せなければ Get_SNA
+        let esv = if values.iter().all(|(_, edt)| edt.is_some()) {
+            0x72
+        } else {
+            0x52
+        };
+        let frame = EchonetliteFrame {
+            ehd: 0x1081,
+            tid: request.tid,
+            seoj: request.deoj,
+            deoj: request.seoj,
+            esv,
+            opc: values.len() as u8,
+            edata: values
+                .iter()
+                .map(|(epc, edt)| {
+                    let edt = edt.as_deref().unwrap_or_default();
+                    EchonetliteEdata {
+                        epc: *epc,
+                        pdc: edt.len() as u8,
+                        edt,
+                    }
+                })
+                .collect(),
+        };
+        let config = bincode::config::standard()
+            .with_big_endian()
+            .with_fixed_int_encoding();
+        Ok(bincode::encode_to_vec(&frame, config)?)
+    }
+
+    /// Echonetliteメッセージを受信したことを知らせるERXUDPイベント
+    fn erxudp(&self, data: &[u8]) -> String {
+        format!(
+            "ERXUDP {0} {0} {1:04X} {1:04X} {2:016X} 1 {3:04X} {4}\r\n",
+            self.address(),
+            skstack::ECHONETLITE_PORT,
+            self.mac_address,
+            data.len(),
+            data.iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<String>()
+        )
+    }
+
+    /// SetC要求に書き込み不可応答(Set_SNA)を返す
+    /// 書き込めなかったプロパティは要求のEDTをそのまま返す
+    fn setc_response(&self, request: &EchonetliteFrame) -> anyhow::Result<Vec<u8>> {
+        let frame = EchonetliteFrame {
+            ehd: 0x1081,
+            tid: request.tid,
+            seoj: request.deoj,
+            deoj: request.seoj,
+            esv: 0x51,
+            opc: request.opc,
+            edata: request.edata.clone(),
+        };
+        let config = bincode::config::standard()
+            .with_big_endian()
+            .with_fixed_int_encoding();
+        Ok(bincode::encode_to_vec(&frame, config)?)
+    }
+
+    /// SKSENDTOに応答する
+    fn sksendto(&self, payload: &[u8]) -> anyhow::Result<String> {
+        let config = bincode::config::standard()
+            .with_big_endian()
+            .with_fixed_int_encoding();
+        let (request, _len): (EchonetliteFrame, usize) =
+            bincode::borrow_decode_from_slice(payload, config)?;
+        let address = self.address();
+        // UDPの送信に成功
+        let mut response = format!("EVENT 21 {} 00\r\nOK\r\n", address);
+        match request.esv {
+            // Get要求
+            0x62 => {
+                let data = self.get_response(&request)?;
+                response.push_str(&self.erxudp(&data));
+            }
+            // SetC要求(Bルートでは書き込みを受け付けないのでSet_SNA)
+            0x61 => {
+                let data = self.setc_response(&request)?;
+                response.push_str(&self.erxudp(&data));
+            }
+            esv => tracing::warn!("ESV 0x{:02X} is not supported", esv),
+        }
+        Ok(response)
+    }
+
+    /// コマンドに応答する
+    fn respond(&self, command: &[u8]) -> anyhow::Result<String> {
+        let address = self.address();
+        if let Some(payload) = sksendto_payload(command) {
+            return self.sksendto(payload);
+        }
+        let line = String::from_utf8_lossy(command);
+        let line = line.trim_end();
+        let name = line.split(' ').next().unwrap_or_default();
+        let response = match name {
+            "SKRESET" | "SKSETPWD" | "SKSETRBID" | "SKSREG" => "OK\r\n".to_string(),
+            "SKVER" => "EVER 1.2.10\r\nOK\r\n".to_string(),
+            "SKSCAN" => format!(
+                concat!(
+                    "OK\r\n",
+                    "EVENT 20 {0}\r\n",
+                    "EPANDESC\r\n",
+                    "  Channel:{1:02X}\r\n",
+                    "  Channel Page:09\r\n",
+                    "  Pan ID:{2:04X}\r\n",
+                    "  Addr:{3:016X}\r\n",
+                    "  LQI:E1\r\n",
+                    "  PairID:01234567\r\n",
+                    "EVENT 22 {0}\r\n"
+                ),
+                address, CHANNEL, PAN_ID, self.mac_address
+            ),
+            "SKJOIN" | "SKREJOIN" => format!("OK\r\nEVENT 25 {}\r\n", address),
+            "SKTERM" => format!("OK\r\nEVENT 27 {}\r\n", address),
+            "SKTABLE" => format!(
+                "ENEIGHBOR\r\n{} {:016X} FFFF\r\nOK\r\n",
+                address, self.mac_address
+            ),
+            "" => String::new(),
+            _ => {
+                tracing::warn!(r#"command "{}" is not supported"#, line.escape_debug());
+                "FAIL ER04\r\n".to_string()
+            }
+        };
+        Ok(response)
+    }
+}
+
+/// SKSENDTO のヘッダ部分の区切り数
+/// SKSENDTO <HANDLE> <IPADDR> <PORT> <SEC> <DATALEN> <DATA>
+const SKSENDTO_FIELDS: usize = 6;
+
+/// SKSENDTO コマンドのデーター部を取り出す
+fn sksendto_payload(command: &[u8]) -> Option<&[u8]> {
+    let (header_len, datalen) = sksendto_header(command)?;
+    command.get(header_len..header_len + datalen)
+}
+
+/// SKSENDTO コマンドのヘッダの長さとデーターの長さ
+fn sksendto_header(buf: &[u8]) -> Option<(usize, usize)> {
+    if !buf.starts_with(b"SKSENDTO ") {
+        return None;
+    }
+    let spaces = buf
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| **b == b' ')
+        .map(|(i, _)| i)
+        .take(SKSENDTO_FIELDS)
+        .collect::<Vec<usize>>();
+    if spaces.len() < SKSENDTO_FIELDS {
+        return None;
+    }
+    let datalen = std::str::from_utf8(&buf[spaces[4] + 1..spaces[5]]).ok()?;
+    let datalen = usize::from_str_radix(datalen, 16).ok()?;
+    Some((spaces[5] + 1, datalen))
+}
+
+/// 受信バッファから1つのコマンドを取り出す
+/// SKSENDTO はデーター部がバイナリなので長さで区切る
+fn take_command(buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let end = match sksendto_header(buf) {
+        Some((header_len, datalen)) if buf.len() >= header_len + datalen => header_len + datalen,
+        Some(_) => return None,
+        None if buf.starts_with(b"SKSENDTO ") => return None,
+        None => buf.windows(2).position(|w| w == b"\r\n")? + 2,
+    };
+    Some(buf.drain(..end).collect())
+}
+
+fn main() -> anyhow::Result<()> {
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_timer(tracing_subscriber::fmt::time::LocalTime::rfc_3339())
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)
+        .context("setting default subscriber failed")?;
+
+    let cli = Cli::parse();
+    let meter = Meter {
+        mac_address: u64::from_str_radix(&cli.mac_address, 16).context("invalid mac address")?,
+        timezone: cli.timezone,
+        started_at: Instant::now(),
+        initial_cumlative: 123456,
+    };
+
+    // 擬似端末を作る(slave側をdryrun, uchino_daqdが開く)
+    let (mut master, slave) = TTYPort::pair().context("couldn't create pseudo terminal")?;
+    let Some(slave_name) = slave.name() else {
+        bail!("couldn't get pseudo terminal name");
+    };
+    if let Some(link) = &cli.link {
+        let _ = fs::remove_file(link);
+        symlink(&slave_name, link).context("couldn't create symbolic link")?;
+    }
+    tracing::info!(
+        "simulator is listening on {} (mac address {:016X})",
+        cli.link
+            .as_ref()
+            .map_or(slave_name.clone(), |p| p.display().to_string()),
+        meter.mac_address
+    );
+
+    let mut buf = Vec::<u8>::new();
+    let mut chunk = [0u8; 1024];
+    loop {
+        match master.read(&mut chunk) {
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+            Err(e) => return Err(e).context("pseudo terminal read failed"),
+        }
+        while let Some(command) = take_command(&mut buf) {
+            let mut line = command.escape_ascii().to_string();
+            redact::mask_credentials(&mut line);
+            tracing::debug!(target:"Rx->","{}", line);
+            let response = meter.respond(&command)?;
+            tracing::debug!(target:"<-Tx","{}", response.escape_debug());
+            master.write_all(response.as_bytes())?;
+        }
+    }
+}
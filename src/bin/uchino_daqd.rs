@@ -2,23 +2,33 @@
 // SPDX-License-Identifier: MPL-2.0
 // SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
 //
-use chrono::{DateTime, Datelike, Days, TimeDelta, TimeZone, Timelike, Utc};
-use chrono_tz::Asia;
+use chrono::{DateTime, Datelike, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use clap::Parser;
 use cron::Schedule;
+use futures::{Stream, StreamExt};
 use rust_decimal::Decimal;
-use serialport::{DataBits, StopBits};
-use sqlx::{self, QueryBuilder, postgres::PgPool};
+use rust_decimal::prelude::ToPrimitive;
+use serde::Deserialize;
 use std::env;
+use std::fs;
 use std::io::{self, BufReader};
-use std::net::Ipv6Addr;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::result;
 use std::str::FromStr;
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 use std::thread;
 use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio;
+use tokio::io::AsyncWrite;
+use tokio::net::UdpSocket;
+use tokio::signal;
+use tokio::sync::{Mutex, mpsc, watch};
+use tokio_serial::SerialStream;
+use tokio_util::sync::CancellationToken;
 use tracing::{Event, Subscriber};
 use tracing_subscriber::{
     fmt::{self, FormatEvent, FormatFields},
@@ -26,11 +36,19 @@ use tracing_subscriber::{
     registry::LookupSpan,
     util::SubscriberInitExt,
 };
+use uchinoepower::alert::{PowerAlert, PowerThreshold};
+use uchinoepower::commit;
 use uchinoepower::connection_settings::ConnectionSettings;
 use uchinoepower::echonetlite::{
-    EchonetliteEdata, EchonetliteFrame, smart_electric_energy_meter as SM,
+    EchonetliteEdata, EchonetliteFrame, Eoj, Esv, smart_electric_energy_meter as SM, superclass,
 };
-use uchinoepower::skstack::{self, Erxudp, authn};
+use uchinoepower::influxdb;
+use uchinoepower::metrics::{self, METRICS};
+use uchinoepower::mqtt;
+use uchinoepower::skstack::{self, Erxudp, UdpRoute, UdpRoutes, authn, redact};
+use uchinoepower::store::{Buffered, Database, InstantBuffer, MeasurementStore};
+use uchinoepower::units::Watt;
+use uchinoepower::webhook::{self, WebhookEvent};
 
 mod built_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
@@ -56,6 +74,12 @@ pub enum DaqDaemonError {
     #[error(r#"database "{0}""#)]
     Database(#[from] sqlx::Error),
 
+    #[error(r#"config file "{0}""#)]
+    ConfigFile(#[from] toml::de::Error),
+
+    #[error(r#"task join "{0}""#)]
+    TaskJoin(#[from] tokio::task::JoinError),
+
     #[error(r#"invalid id "{0}""#)]
     InvalidId(String),
 
@@ -71,29 +95,283 @@ pub enum DaqDaemonError {
     #[error("PANA session disconnected")]
     PanaSessionDisconnected,
 
+    #[error("no settings in database, run the pairing program first")]
+    NoSettings,
+
+    #[error("invalid settings: {}", .0.join(", "))]
+    InvalidSettings(Vec<String>),
+
     #[error("{0}")]
     Other(&'static str),
 }
 
+impl From<commit::CommitError> for DaqDaemonError {
+    fn from(err: commit::CommitError) -> DaqDaemonError {
+        match err {
+            commit::CommitError::Database(e) => DaqDaemonError::Database(e),
+            commit::CommitError::Other(s) => DaqDaemonError::Other(s),
+        }
+    }
+}
+
 impl From<authn::Error> for DaqDaemonError {
     fn from(err: authn::Error) -> DaqDaemonError {
         match err {
             authn::Error::Fail(code) => DaqDaemonError::CommandFail(code),
             authn::Error::Io(e) => DaqDaemonError::Io(e),
             authn::Error::PanaSessionDisconnected => DaqDaemonError::PanaSessionDisconnected,
+            authn::Error::Cancelled => DaqDaemonError::Other("connect cancelled"),
         }
     }
 }
 
+/// 瞬時電力と瞬時電流計測値の取得スケジュール(既定値は1分毎)
+const DEFAULT_POLL_SCHEDULE: &str = "00 */1 * * * *";
+
+/// PANA セッションライフタイム値の既定値(秒)
+const DEFAULT_SESSION_LIFETIME_OF_SECOND: u32 = 900;
+
+/// PANA セッション再認証間隔(ライフタイム値に対する割合)の既定値
+const DEFAULT_REJOIN_FACTOR: f32 = 0.7;
+
+/// 瞬時電力のしきい値から元に戻ったとみなすまでの幅の既定値(W)
+const DEFAULT_POWER_ALERT_HYSTERESIS_WATTS: i64 = 100;
+
+#[derive(Parser, Debug)]
+#[command(name = "uchino_daqd")]
+#[command(version, about, long_about = None)]
+struct Cli {
+    /// Home Assistant MQTT discovery 設定を公開する(MQTT_HOST が必要)
+    #[arg(long, env = "HA_DISCOVERY")]
+    ha_discovery: bool,
+
+    /// 設定ファイル名(環境変数の設定が優先する)
+    #[arg(short = 'c', long)]
+    config: Option<PathBuf>,
+
+    /// データーベースとシリアルポートの接続を確かめて終了する(データー収集はしない)
+    #[arg(long)]
+    check: bool,
+}
+
+/// 設定ファイル
+/// 環境変数と同じ設定を書く, 両方にあれば環境変数の設定を使う
+/// (Bルートの接続情報はデーターベースの settings にある)
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    /// SERIAL_DEVICE
+    serial_device: Option<String>,
+    /// SERIAL_BAUD
+    serial_baud: Option<u32>,
+    /// SERIAL_TIMEOUT_MS
+    serial_timeout_ms: Option<u64>,
+    /// DATABASE_URL
+    database_url: Option<String>,
+    /// POLL_SCHEDULE
+    poll_schedule: Option<String>,
+    /// HISTORY_SCHEDULE
+    history_schedule: Option<String>,
+    /// PANA_SESSION_LIFETIME
+    pana_session_lifetime: Option<u32>,
+    /// PANA_REJOIN_FACTOR
+    pana_rejoin_factor: Option<f32>,
+    /// TIMEZONE
+    timezone: Option<String>,
+    /// INSTANT_FLUSH_MINUTES
+    instant_flush_minutes: Option<u32>,
+    /// POWER_ALERT_HIGH_WATTS
+    power_alert_high_watts: Option<i64>,
+    /// POWER_ALERT_LOW_WATTS
+    power_alert_low_watts: Option<i64>,
+    /// POWER_ALERT_HYSTERESIS_WATTS
+    power_alert_hysteresis_watts: Option<i64>,
+}
+
+impl ConfigFile {
+    /// 設定ファイルを読む
+    fn load(path: &Path) -> result::Result<Self, DaqDaemonError> {
+        let s = fs::read_to_string(path)?;
+        Ok(toml::from_str::<ConfigFile>(&s)?)
+    }
+}
+
+/// 環境変数の設定, 無ければ設定ファイルの設定
+fn setting<T: ToString>(name: &str, from_file: &Option<T>) -> Option<String> {
+    env::var(name)
+        .ok()
+        .or(from_file.as_ref().map(ToString::to_string))
+}
+
+/// サービスの設定
+struct ServiceConfig {
+    serial_device: String,
+    serial_baud: u32,
+    serial_timeout: Duration,
+    database_url: String,
+    poll_schedule: Schedule,
+    /// 今日の積算電力量履歴の取得スケジュール(None なら接続時だけ取得する)
+    history_schedule: Option<Schedule>,
+    session_lifetime: u32,
+    rejoin_factor: f32,
+    timezone: Tz,
+    /// ERXUDPの送信先UDPポート番号ごとの扱い
+    udp_routes: UdpRoutes,
+    /// Home Assistant MQTT discovery 設定を公開する
+    ha_discovery: bool,
+    /// 瞬時電力と瞬時電流をまとめて蓄積する間隔(None ならすぐに蓄積する)
+    instant_flush_interval: Option<Duration>,
+    /// 瞬時電力のしきい値(None なら通知しない)
+    power_alert: Option<PowerThreshold>,
+}
+
+impl ServiceConfig {
+    /// 環境変数と設定ファイルから設定を得る
+    fn from_env(file: &ConfigFile) -> result::Result<Self, DaqDaemonError> {
+        let serial_device = setting("SERIAL_DEVICE", &file.serial_device).ok_or(
+            DaqDaemonError::Other(r#"Must be set to "SERIAL_DEVICE" environment."#),
+        )?;
+        // シリアルポートのボーレート
+        let serial_baud = match setting("SERIAL_BAUD", &file.serial_baud) {
+            Some(s) => skstack::parse_baud_rate(&s).or(Err(DaqDaemonError::Other(
+                r#""SERIAL_BAUD" must be one of 2400, 4800, 9600, 19200, 38400, 57600, 115200."#,
+            )))?,
+            None => skstack::DEFAULT_BAUD_RATE,
+        };
+        // シリアルポートの読み込みタイムアウト
+        let serial_timeout = match setting("SERIAL_TIMEOUT_MS", &file.serial_timeout_ms) {
+            Some(s) => s
+                .parse::<u64>()
+                .ok()
+                .filter(|n| *n > 0)
+                .map(Duration::from_millis)
+                .ok_or(DaqDaemonError::Other(
+                    r#""SERIAL_TIMEOUT_MS" must be a positive integer (milliseconds)."#,
+                ))?,
+            None => Duration::from_millis(skstack::DEFAULT_TIMEOUT_MS),
+        };
+        let database_url = setting("DATABASE_URL", &file.database_url).ok_or(
+            DaqDaemonError::Other(r#"Must be set to "DATABASE_URL" environment."#),
+        )?;
+        // 瞬時電力と瞬時電流計測値の取得スケジュール(cron式)
+        let poll_schedule = setting("POLL_SCHEDULE", &file.poll_schedule)
+            .map_or(Schedule::from_str(DEFAULT_POLL_SCHEDULE), |s| {
+                Schedule::from_str(&s)
+            })?;
+        // 今日の積算電力量履歴の取得スケジュール(cron式)
+        let history_schedule = setting("HISTORY_SCHEDULE", &file.history_schedule)
+            .map(|s| Schedule::from_str(&s))
+            .transpose()?;
+        // PANA セッションライフタイム値
+        let session_lifetime = match setting("PANA_SESSION_LIFETIME", &file.pana_session_lifetime) {
+            Some(s) => s
+                .parse::<u32>()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or(DaqDaemonError::Other(
+                    r#""PANA_SESSION_LIFETIME" must be a positive integer (seconds)."#,
+                ))?,
+            None => DEFAULT_SESSION_LIFETIME_OF_SECOND,
+        };
+        // PANA セッション再認証間隔
+        let rejoin_factor = match setting("PANA_REJOIN_FACTOR", &file.pana_rejoin_factor) {
+            Some(s) => s
+                .parse::<f32>()
+                .ok()
+                .filter(|n| 0.0 < *n && *n < 1.0)
+                .ok_or(DaqDaemonError::Other(
+                    r#""PANA_REJOIN_FACTOR" must be greater than 0 and less than 1."#,
+                ))?,
+            None => DEFAULT_REJOIN_FACTOR,
+        };
+        // 日の区切りと計測時刻のタイムゾーン
+        let timezone = match setting(uchinoepower::TIMEZONE_ENV, &file.timezone) {
+            Some(s) => uchinoepower::parse_timezone(&s).or(Err(DaqDaemonError::Other(
+                r#""TIMEZONE" must be a timezone name such as "Asia/Tokyo"."#,
+            )))?,
+            None => uchinoepower::DEFAULT_TIMEZONE,
+        };
+        // 瞬時電力と瞬時電流をまとめて蓄積する間隔(0 ならすぐに蓄積する)
+        let instant_flush_interval =
+            match setting("INSTANT_FLUSH_MINUTES", &file.instant_flush_minutes) {
+                Some(s) => s
+                    .parse::<u64>()
+                    .ok()
+                    .map(|n| (n > 0).then(|| Duration::from_secs(n * 60)))
+                    .ok_or(DaqDaemonError::Other(
+                        r#""INSTANT_FLUSH_MINUTES" must be a non-negative integer (minutes)."#,
+                    ))?,
+                None => None,
+            };
+        // 瞬時電力のしきい値
+        let watts = |name: &str, from_file: &Option<i64>| {
+            setting(name, from_file)
+                .map(|s| s.parse::<i64>().map(|n| Watt(Decimal::from(n))))
+                .transpose()
+                .map_err(|e| {
+                    tracing::error!("{name}: {e}");
+                    DaqDaemonError::Other("power alert thresholds must be integers (watts).")
+                })
+        };
+        let high = watts("POWER_ALERT_HIGH_WATTS", &file.power_alert_high_watts)?;
+        let low = watts("POWER_ALERT_LOW_WATTS", &file.power_alert_low_watts)?;
+        let hysteresis = watts(
+            "POWER_ALERT_HYSTERESIS_WATTS",
+            &file.power_alert_hysteresis_watts,
+        )?
+        .unwrap_or(Watt(Decimal::from(DEFAULT_POWER_ALERT_HYSTERESIS_WATTS)));
+        let power_alert = match (high, low) {
+            (None, None) => None,
+            _ => {
+                let threshold = PowerThreshold {
+                    high,
+                    low,
+                    hysteresis,
+                };
+                threshold.validate().map_err(|e| {
+                    tracing::error!("power alert: {e}");
+                    DaqDaemonError::Other("invalid power alert thresholds.")
+                })?;
+                Some(threshold)
+            }
+        };
+        // Echonetlite と PANA 以外のUDPポート番号で受信したデーターの転送先
+        let udp_routes = match env::var("ERXUDP_FORWARD") {
+            Ok(s) => UdpRoutes::default().with_forwards(&s).map_err(|e| {
+                tracing::error!("ERXUDP_FORWARD: {e}");
+                DaqDaemonError::Other(
+                    r#""ERXUDP_FORWARD" must be "port=address:port" separated by commas."#,
+                )
+            })?,
+            Err(_) => UdpRoutes::default(),
+        };
+        Ok(Self {
+            serial_device,
+            serial_baud,
+            serial_timeout,
+            database_url,
+            poll_schedule,
+            history_schedule,
+            session_lifetime,
+            rejoin_factor,
+            timezone,
+            udp_routes,
+            ha_discovery: false,
+            instant_flush_interval,
+            power_alert,
+        })
+    }
+}
+
 /// 今日の積算電力量履歴を取得するechonet lite電文
 static TODAY_CWH: LazyLock<EchonetliteFrame> = LazyLock::new(|| {
     EchonetliteFrame {
-        ehd: 0x1081,              // 0x1081 = echonet lite
-        tid: 1,                   // tid
-        seoj: [0x05, 0xff, 0x01], // home controller
-        deoj: [0x02, 0x88, 0x01], // smartmeter
-        esv: 0x62,                // get要求
-        opc: 1,                   // 1つ
+        ehd: 0x1081, // 0x1081 = echonet lite
+        tid: 1,      // tid
+        seoj: Eoj::HOME_CONTROLLER,
+        deoj: Eoj::SMART_METER,
+        esv: 0x62, // get要求
+        opc: 1,    // 1つ
         edata: vec![EchonetliteEdata {
             epc: 0xe2, // 積算電力量計測値履歴1
             pdc: 0,    // 今日
@@ -105,12 +383,12 @@ static TODAY_CWH: LazyLock<EchonetliteFrame> = LazyLock::new(|| {
 /// 瞬時電力と瞬時電流計測値を取得するechonet lite電文
 static INSTANT_WATT_AMPERE: LazyLock<EchonetliteFrame> = LazyLock::new(|| {
     EchonetliteFrame {
-        ehd: 0x1081,              // 0x1081 = echonet lite
-        tid: 1,                   // tid
-        seoj: [0x05, 0xff, 0x01], // home controller
-        deoj: [0x02, 0x88, 0x01], // smartmeter
-        esv: 0x62,                // get要求
-        opc: 2,                   // 2つ
+        ehd: 0x1081, // 0x1081 = echonet lite
+        tid: 1,      // tid
+        seoj: Eoj::HOME_CONTROLLER,
+        deoj: Eoj::SMART_METER,
+        esv: 0x62, // get要求
+        opc: 2,    // 2つ
         edata: vec![
             EchonetliteEdata {
                 epc: 0xe7, // 瞬時電力計測値
@@ -125,54 +403,377 @@ static INSTANT_WATT_AMPERE: LazyLock<EchonetliteFrame> = LazyLock::new(|| {
 });
 
 /// 受信値をデーターベースに蓄積する
+/// バッファがあれば瞬時電力と瞬時電流はそこに溜める
+/// スマートメーター以外(SEOJ)からのフレームは蓄積しない(受信電波の品質(LQI)も)
+#[allow(clippy::too_many_arguments)]
 async fn commit_to_database<'a>(
-    pool: &PgPool,
+    db: &Database,
+    tz: &Tz,
+    meter: &Meter,
     unit: &SM::UnitForCumlativeAmountsPower,
     recorded_at: &DateTime<Utc>,
+    history_requested_at: &DateTime<Utc>,
+    lqi: Option<u8>,
     frame: &EchonetliteFrame<'a>,
 ) -> result::Result<(), DaqDaemonError> {
+    // 近所のスマートメーターなど他の機器からのフレームを蓄積しないようにする
+    if !frame.seoj.is_same_class(&Eoj::SMART_METER) {
+        tracing::debug!(
+            seoj = frame.seoj.to_string(),
+            "This frame is not from a smart meter, not committed to the database"
+        );
+        return Ok(());
+    }
+    // フレーム内のデーターはまとめて1つのトランザクションで蓄積する
+    let mut tx = db.begin().await?;
+    // 受信電波の品質(LQI)
+    if let Some(lqi) = lqi {
+        tracing::trace!(meter_id = meter.id, "LQI {lqi}");
+        tx.insert_link_quality(meter.id, recorded_at, lqi).await?;
+    }
+    let mut guard = match &meter.instant_buffer {
+        Some(buffer) => Some(buffer.lock().await),
+        None => None,
+    };
     for edata in frame.edata.iter() {
         match SM::Properties::try_from(edata) {
-            // 0xe2 積算電力量計測値履歴1 (正方向計測値)
-            Ok(SM::Properties::HistoricalCumlativeAmount(hist)) => {
-                commit_historical_cumlative_amount(&pool, unit, &hist).await?;
+            // 0xd5 インスタンスリスト通知はINFの処理でログに出す
+            Ok(SM::Properties::Superclass(superclass::Properties::NotifyInstances(_))) => {}
+            Ok(prop) => {
+                let committed = match guard.as_deref_mut() {
+                    Some(buffer) => {
+                        let mut store = Buffered {
+                            store: &mut tx,
+                            buffer,
+                        };
+                        commit::commit_property(
+                            &mut store,
+                            meter.id,
+                            tz,
+                            unit,
+                            recorded_at,
+                            history_requested_at,
+                            &prop,
+                        )
+                        .await?
+                    }
+                    None => {
+                        commit::commit_property(
+                            &mut tx,
+                            meter.id,
+                            tz,
+                            unit,
+                            recorded_at,
+                            history_requested_at,
+                            &prop,
+                        )
+                        .await?
+                    }
+                };
+                if !committed {
+                    tracing::warn!(r#"This data "{prop}" is not committed to the database"#);
+                }
             }
+            Err(e) => tracing::error!("{e}"),
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// 受信値を InfluxDB に書き出す
+/// 書き出しが有効でなければ何もしない
+/// 書き出せなくてもデーター収集は続ける
+async fn export_to_influxdb<'a>(
+    tz: &Tz,
+    meter_id: i64,
+    unit: &SM::UnitForCumlativeAmountsPower,
+    recorded_at: &DateTime<Utc>,
+    history_requested_at: &DateTime<Utc>,
+    frame: &EchonetliteFrame<'a>,
+) {
+    if !influxdb::is_enabled() {
+        return;
+    }
+    let mut lines = influxdb::Lines::default();
+    for edata in frame.edata.iter() {
+        // 書き出さないプロパティは無視する
+        let Ok(prop) = SM::Properties::try_from(edata) else {
+            continue;
+        };
+        if let Err(e) = commit::commit_property(
+            &mut lines,
+            meter_id,
+            tz,
+            unit,
+            recorded_at,
+            history_requested_at,
+            &prop,
+        )
+        .await
+        {
+            tracing::warn!(
+                "epc 0x{:02x} is not exported to influxdb, reason: {e}",
+                edata.epc
+            );
+        }
+    }
+    influxdb::export(lines);
+}
+
+/// 受信値を MQTT で公開する
+/// 公開が有効でなければ何もしない
+fn publish_to_mqtt(unit: &SM::UnitForCumlativeAmountsPower, frame: &EchonetliteFrame) {
+    if !mqtt::is_enabled() {
+        return;
+    }
+    for edata in frame.edata.iter() {
+        match SM::Properties::try_from(edata) {
             // 0xe7 瞬時電力計測値
             Ok(SM::Properties::InstantiousPower(epower)) => {
-                commit_instant_epower(&pool, recorded_at, &epower).await?;
+                mqtt::publish_state("power", &epower.watts().0);
             }
             // 0xe8 瞬時電流計測値
             Ok(SM::Properties::InstantiousCurrent(current)) => {
-                commit_instant_current(&pool, recorded_at, &current).await?;
+                mqtt::publish_state("current_r", &current.r.0);
+                if let Some(t) = current.t {
+                    mqtt::publish_state("current_t", &t.0);
+                }
             }
             // 0xea 定時積算電力量計測値(正方向計測値)
-            Ok(SM::Properties::CumlativeAmountsOfPowerAtFixedTime(epower)) => {
-                commit_cumlative_amount_epower(&pool, unit, &epower).await?;
+            Ok(SM::Properties::CumlativeAmountsOfPowerAtFixedTime(a)) => {
+                let kwh = unit.kwh(a.cumlative_amounts_power);
+                mqtt::publish_state("energy", &kwh.0);
             }
-            //
-            Ok(v) => tracing::warn!(r#"This data "{v}" is not committed to the database"#),
-            Err(e) => tracing::error!("{e}"),
+            _ => {}
+        }
+    }
+}
+
+/// 受信値をメトリクスに反映する
+fn update_metrics(frame: &EchonetliteFrame) {
+    for edata in frame.edata.iter() {
+        match SM::Properties::try_from(edata) {
+            // 0xe7 瞬時電力計測値
+            Ok(SM::Properties::InstantiousPower(epower)) => {
+                METRICS
+                    .instant_power
+                    .set(epower.watts().0.to_f64().unwrap_or(f64::NAN));
+            }
+            // 0xe8 瞬時電流計測値
+            Ok(SM::Properties::InstantiousCurrent(current)) => {
+                METRICS
+                    .instant_current
+                    .with_label_values(&["r"])
+                    .set(current.r.0.to_f64().unwrap_or(f64::NAN));
+                if let Some(t) = current.t {
+                    METRICS
+                        .instant_current
+                        .with_label_values(&["t"])
+                        .set(t.0.to_f64().unwrap_or(f64::NAN));
+                }
+            }
+            _ => {}
         }
     }
-    Ok(())
+}
+
+/// 受信したEchonetliteフレームを構造化ログに出す
+/// プロパティ毎に型付きのフィールドを持つイベントにする
+fn log_frame(unit: &SM::UnitForCumlativeAmountsPower, frame: &EchonetliteFrame) {
+    let tid = frame.tid;
+    let esv = frame.esv;
+    tracing::info!(tid, esv, opc = frame.opc, "{}", frame.show());
+    for edata in frame.edata.iter() {
+        let epc = edata.epc;
+        let message = edata.show(Some(unit));
+        match SM::Properties::try_from(edata) {
+            // 0xe0 積算電力量計測値(正方向計測値)
+            Ok(SM::Properties::CumlativeAmountsPower(a)) => {
+                let cumlative_kwh = a.kwh(unit);
+                tracing::info!(tid, epc, %cumlative_kwh, "{message}");
+            }
+            // 0xe3 積算電力量計測値(逆方向計測値)
+            Ok(SM::Properties::ReverseCumlativeAmountsPower(a)) => {
+                let reverse_cumlative_kwh = a.kwh(unit);
+                tracing::info!(tid, epc, %reverse_cumlative_kwh, "{message}");
+            }
+            // 0xe7 瞬時電力計測値
+            Ok(SM::Properties::InstantiousPower(a)) => {
+                let instant_power_w = a.watts();
+                tracing::info!(tid, epc, %instant_power_w, "{message}");
+            }
+            // 0xe8 瞬時電流計測値
+            Ok(SM::Properties::InstantiousCurrent(a)) => {
+                let r_current_a = a.r;
+                let t_current_a = a.t.map(|t| t.to_string());
+                tracing::info!(tid, epc, %r_current_a, t_current_a, "{message}");
+            }
+            // 0xea 定時積算電力量計測値(正方向計測値)
+            Ok(SM::Properties::CumlativeAmountsOfPowerAtFixedTime(a)) => {
+                let time_point = a.time_point.to_string();
+                let cumlative_kwh = unit.kwh(a.cumlative_amounts_power);
+                tracing::info!(tid, epc, time_point, %cumlative_kwh, "{message}");
+            }
+            // 0xeb 定時積算電力量計測値(逆方向計測値)
+            Ok(SM::Properties::ReverseCumlativeAmountsOfPowerAtFixedTime(a)) => {
+                let time_point = a.time_point.to_string();
+                let reverse_cumlative_kwh = unit.kwh(a.cumlative_amounts_power);
+                tracing::info!(tid, epc, time_point, %reverse_cumlative_kwh, "{message}");
+            }
+            // 0xe2 積算電力量計測値履歴1 (正方向計測値)
+            Ok(SM::Properties::HistoricalCumlativeAmount(a)) => {
+                let n_days_ago = a.n_days_ago;
+                tracing::info!(tid, epc, n_days_ago, "{message}");
+            }
+            _ => {
+                tracing::info!(tid, epc, "{message}");
+            }
+        }
+    }
+}
+
+/// INF(プロパティ値通知)を受信した
+fn rx_inf(frame: &EchonetliteFrame) {
+    for edata in frame.edata.iter() {
+        match SM::Properties::try_from(edata) {
+            // 0xd5 インスタンスリスト通知
+            Ok(SM::Properties::Superclass(superclass::Properties::NotifyInstances(notify))) => {
+                let instances = notify
+                    .instances()
+                    .iter()
+                    .map(|eoj| Eoj::from(*eoj).to_string())
+                    .collect::<Vec<String>>()
+                    .join(",");
+                tracing::info!(
+                    seoj = frame.seoj.to_string(),
+                    count = notify.count(),
+                    instances,
+                    "instance list notification received"
+                );
+            }
+            Ok(v) => tracing::debug!(r#"INF "{v}""#),
+            Err(e) => tracing::error!("INF {e}"),
+        }
+    }
+}
+
+/// 異常発生状態(0x88)が変わったら通知する
+/// (スマートメーターは状態変化時にINFで知らせてくる)
+fn check_fault_status(meter: &Meter, frame: &EchonetliteFrame) {
+    for edata in frame.edata.iter().filter(|edata| edata.epc == 0x88) {
+        // 0x41 = 異常発生有, 0x42 = 異常発生無
+        let fault = match edata.edt {
+            [0x41] => true,
+            [0x42] => false,
+            _ => {
+                tracing::warn!("unknown fault status {:02X?}", edata.edt);
+                continue;
+            }
+        };
+        let previous = meter
+            .fault_status
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace(fault);
+        if previous == Some(fault) {
+            continue;
+        }
+        if fault {
+            tracing::warn!(meter_id = meter.id, "smart meter reports a fault");
+        } else {
+            tracing::info!(meter_id = meter.id, "smart meter reports no fault");
+        }
+        // 起動後はじめての「異常発生無」は知らせない
+        if fault || previous.is_some() {
+            webhook::notify(WebhookEvent::FaultStatus {
+                meter_id: meter.id,
+                fault,
+            });
+        }
+    }
+}
+
+/// 瞬時電力がしきい値を越えたら通知する
+/// (MQTT が有効なら power_alert トピックにも公開する)
+fn check_power_alert(meter: &Meter, frame: &EchonetliteFrame) {
+    let Some(power_alert) = &meter.power_alert else {
+        return;
+    };
+    for edata in frame.edata.iter() {
+        // 0xe7 瞬時電力計測値
+        if let Ok(SM::Properties::InstantiousPower(epower)) = SM::Properties::try_from(edata) {
+            let event = power_alert
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .update(epower.watts());
+            if let Some(event) = event {
+                tracing::warn!(
+                    meter_id = meter.id,
+                    level = %event.level,
+                    previous = %event.previous,
+                    watt = %event.watt,
+                    "instant power alert: {} -> {}",
+                    event.previous,
+                    event.level
+                );
+                if meter.primary {
+                    match serde_json::to_string(&event) {
+                        Ok(payload) => mqtt::publish_event("power_alert", payload),
+                        Err(e) => tracing::error!("{e}"),
+                    }
+                }
+                webhook::notify(WebhookEvent::PowerAlert {
+                    meter_id: meter.id,
+                    event,
+                });
+            }
+        }
+    }
+}
+
+/// Get_SNA(プロパティ値読み出し不可応答)のログを出す間隔
+const NOT_READY_LOG_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Get_SNA(プロパティ値読み出し不可応答)を受信した
+/// 接続直後のスマートメーターは準備ができるまでこれを返すので
+/// プロパティ毎の警告にしないで, 間隔を空けて1行だけログに出す
+fn rx_get_sna(meter: &Meter, frame: &EchonetliteFrame) {
+    let now = Instant::now();
+    let mut logged_at = meter
+        .not_ready_logged_at
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if logged_at.is_none_or(|t| now.duration_since(t) >= NOT_READY_LOG_INTERVAL) {
+        *logged_at = Some(now);
+        tracing::debug!(
+            meter_id = meter.id,
+            tid = frame.tid,
+            "meter not ready yet, {}",
+            frame.show()
+        );
+    }
 }
 
 /// ERXUDPイベント受信
 async fn rx_erxudp(
-    pool: &PgPool,
+    db: &Database,
+    tz: &Tz,
+    meter: &Meter,
     unit: &SM::UnitForCumlativeAmountsPower,
+    history_requested_at: &DateTime<Utc>,
     erxudp: &Erxudp,
 ) -> result::Result<(), DaqDaemonError> {
     // 受信時刻(分単位)
     let recorded_at = {
-        let jst = Utc::now().with_timezone(&Asia::Tokyo);
-        let modified = Asia::Tokyo
+        let local = Utc::now().with_timezone(tz);
+        let modified = tz
             .with_ymd_and_hms(
-                jst.year(),
-                jst.month(),
-                jst.day(),
-                jst.hour(),
-                jst.minute(),
+                local.year(),
+                local.month(),
+                local.day(),
+                local.hour(),
+                local.minute(),
                 0,
             )
             .single()
@@ -182,9 +783,9 @@ async fn rx_erxudp(
 
     let dump = |xs: &Vec<u8>| xs.iter().map(|b| format!("{:02X}", b)).collect::<String>();
 
-    match erxudp.destination_port {
-        // UDPポート番号 0E1A = 3610 は Echonetliteメッセージ
-        0x0e1a => {
+    match meter.udp_routes.get(erxudp.destination_port) {
+        // Echonetliteメッセージ
+        Some(UdpRoute::EchonetLite) => {
             // ERXUDPメッセージからEchonetliteフレームを取り出す。
             let config = bincode::config::standard()
                 .with_big_endian()
@@ -195,17 +796,53 @@ async fn rx_erxudp(
 
             match decoded {
                 Ok((frame, _len)) => {
+                    METRICS.frames_received.inc();
+                    match Esv::try_from(frame.esv) {
+                        // 要求していない通知
+                        Ok(Esv::Inf) => rx_inf(&frame),
+                        // 読み出し不可応答は蓄積しない
+                        Ok(Esv::GetSna) => {
+                            rx_get_sna(meter, &frame);
+                            return Ok(());
+                        }
+                        _ => {}
+                    }
                     // 受信値をデーターベースに蓄積する
-                    commit_to_database(pool, unit, &recorded_at, &frame).await?;
-                    // 受信値をログに出す
-                    let mut s = Vec::<String>::new();
-                    s.push(frame.show());
-                    for v in frame.edata.iter() {
-                        s.push(v.show(Some(unit)));
+                    commit_to_database(
+                        db,
+                        tz,
+                        meter,
+                        unit,
+                        &recorded_at,
+                        history_requested_at,
+                        erxudp.lqi,
+                        &frame,
+                    )
+                    .await?;
+                    // 受信値を InfluxDB に書き出す
+                    export_to_influxdb(
+                        tz,
+                        meter.id,
+                        unit,
+                        &recorded_at,
+                        history_requested_at,
+                        &frame,
+                    )
+                    .await;
+                    // 異常発生状態と瞬時電力のしきい値を確かめる
+                    check_fault_status(meter, &frame);
+                    check_power_alert(meter, &frame);
+                    if meter.primary {
+                        // 受信値を MQTT で公開する
+                        publish_to_mqtt(unit, &frame);
+                        // 受信値をメトリクスに反映する
+                        update_metrics(&frame);
                     }
-                    tracing::info!("{}", s.join(" "));
+                    // 受信値をログに出す
+                    log_frame(unit, &frame);
                 }
                 Err(e) => {
+                    METRICS.parse_errors.inc();
                     tracing::error!(
                         r#"Echonetlite message "{}" parse error, reason:{}"#,
                         dump(&erxudp.data),
@@ -214,15 +851,26 @@ async fn rx_erxudp(
                 }
             }
         }
-        // UDPポート番号 02CC = 716 は PANAメッセージ(RFC5191)
-        0x02cc => {
+        // PANAメッセージ(RFC5191)
+        Some(UdpRoute::Pana) => {
             tracing::warn!(r#"PANA message "{}" is IGNORED"#, dump(&erxudp.data));
             return Ok(());
         }
+        // 設定した転送先にデーター部をそのまま送る
+        Some(UdpRoute::Forward(addr)) => {
+            if let Err(e) = forward_udp(addr, &erxudp.data).await {
+                tracing::warn!(
+                    r#"rport {} message "{}" cannot be forwarded to {addr}, reason: {e}"#,
+                    erxudp.destination_port,
+                    dump(&erxudp.data)
+                );
+            }
+        }
         // 未知のUDPポート番号
-        rport => {
+        None => {
             tracing::warn!(
-                r#"rport {rport} message "{}" is UNKNOWN and IGNORED."#,
+                r#"rport {} message "{}" is UNKNOWN and IGNORED."#,
+                erxudp.destination_port,
                 dump(&erxudp.data)
             );
         }
@@ -230,175 +878,280 @@ async fn rx_erxudp(
     Ok(())
 }
 
-/// 設定情報をデーターベースから得る
-async fn read_settings(pool: &PgPool) -> result::Result<ConnectionSettings, sqlx::Error> {
-    #[derive(sqlx::FromRow)]
-    #[allow(dead_code)]
-    struct Row {
-        id: i64,
-        note: sqlx::types::Json<ConnectionSettings>,
-    }
+/// UDPデーターを転送する
+async fn forward_udp(addr: &SocketAddr, data: &[u8]) -> io::Result<()> {
+    let bind_addr: SocketAddr = if addr.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind_addr).await?;
+    socket.send_to(data, addr).await?;
+    Ok(())
+}
 
-    let row = sqlx::query_as!(
-        Row,
-        r#"SELECT id, note as "note: sqlx::types::Json<ConnectionSettings>" FROM settings ORDER BY id DESC"#
-    )
-    .fetch_one(pool)
-    .await?;
+/// ARIB STD-T108 の送信総和時間制限が解除されるのを待つ最長時間
+/// (制限は1時間あたりの送信総和時間なので1時間待てば解除されているはず)
+const ARIB108_PAUSE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// データーを収集するスマートメーター
+struct Meter {
+    /// 設定情報の id (測定値の meter_id)
+    id: i64,
+    /// 接続情報
+    settings: ConnectionSettings,
+    /// シリアルデバイス名
+    serial_device: String,
+    /// 最も新しく設定したスマートメーター
+    /// (メトリクスと MQTT はこのスマートメーターの受信値だけを反映する)
+    primary: bool,
+    /// 受信したUDPデーターの送信先UDPポート番号ごとの扱い
+    udp_routes: UdpRoutes,
+    /// 瞬時電力と瞬時電流を溜めておくバッファ(None ならすぐに蓄積する)
+    instant_buffer: Option<Arc<Mutex<InstantBuffer>>>,
+    /// 接続直後の Get_SNA をログに出した時刻
+    not_ready_logged_at: std::sync::Mutex<Option<Instant>>,
+    /// 瞬時電力のしきい値通知(None なら通知しない)
+    power_alert: Option<std::sync::Mutex<PowerAlert>>,
+    /// 最後に受信した異常発生状態
+    fault_status: std::sync::Mutex<Option<bool>>,
+}
 
-    Ok(row.note.0)
+/// 送信したコマンドへの応答(受信側が受け取って送信側へ伝える)
+///
+/// SKSENDTO の応答は2段階に分かれている
+/// 1. コマンドの即時応答(OK / FAIL ER..)
+///    FAIL ならUDPは送信されていないので EVENT 21 は来ない
+/// 2. UDP の送信結果(EVENT 21)
+///    BP35A1 などは EVENT 21 を OK より先に出すので, 順番は決まっていない
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CommandResponse {
+    /// OK
+    Ok,
+    /// FAIL ER..
+    Fail(u8),
+    /// UDP の送信結果(EVENT 21 のパラメータ)
+    UdpSendResult(u8),
 }
 
-/// 瞬時電力をデーターベースに蓄積する
-async fn commit_instant_epower(
-    pool: &PgPool,
-    recorded_at: &DateTime<Utc>,
-    epower: &SM::InstantiousPower,
-) -> result::Result<i64, DaqDaemonError> {
-    let rec = sqlx::query!(
-        r#"INSERT INTO instant_epower ( recorded_at, watt ) VALUES ( $1, $2 ) RETURNING id"#,
-        *recorded_at,
-        epower.0
-    )
-    .fetch_one(pool)
-    .await?;
+/// FAIL ER10 = 指定したコマンドを実行できる状態ではない(PANA 認証が済んでいない)
+const ER10_INVALID_STATE: u8 = 0x10;
 
-    Ok(rec.id)
+/// 受信側から送信側へ伝える状態
+struct ReceiverFeedback {
+    /// 送信総和時間の制限中
+    arib108_limited: watch::Receiver<bool>,
+    /// 送信したコマンドへの応答
+    responses: mpsc::UnboundedReceiver<CommandResponse>,
 }
 
-/// 瞬時電流をデーターベースに蓄積する
-async fn commit_instant_current(
-    pool: &PgPool,
-    recorded_at: &DateTime<Utc>,
-    current: &SM::InstantiousCurrent,
-) -> result::Result<i64, DaqDaemonError> {
-    let rec = sqlx::query!(
-        r#"INSERT INTO instant_current ( recorded_at, r, t ) VALUES ( $1, $2, $3 ) RETURNING id"#,
-        *recorded_at,
-        current.r,
-        current.t
+/// 送信総和時間の制限中なら解除されるまで待つ
+async fn wait_for_arib108_release(arib108_limited: &mut watch::Receiver<bool>) {
+    if !*arib108_limited.borrow() {
+        return;
+    }
+    tracing::warn!("送信総和時間の制限中なので送信を保留する");
+    match tokio::time::timeout(
+        ARIB108_PAUSE_TIMEOUT,
+        arib108_limited.wait_for(|limited| !*limited),
     )
-    .fetch_one(pool)
-    .await?;
-
-    Ok(rec.id)
+    .await
+    {
+        Ok(Ok(_)) => tracing::info!("送信総和時間の制限が解除されたので送信を再開する"),
+        Ok(Err(_)) => {} // 受信側が終了した
+        Err(_) => tracing::warn!(
+            "送信総和時間の制限が {:?} 経っても解除されないので送信を再開する",
+            ARIB108_PAUSE_TIMEOUT
+        ),
+    }
 }
 
-/// 定時積算電力量計測値(正方向計測値)をデーターベースに蓄積する
-async fn commit_cumlative_amount_epower(
-    pool: &PgPool,
-    unit: &SM::UnitForCumlativeAmountsPower,
-    epower: &SM::CumlativeAmountsOfPowerAtFixedTime,
-) -> result::Result<i64, DaqDaemonError> {
-    let jst = Asia::Tokyo
-        .with_ymd_and_hms(
-            epower.time_point.year(),
-            epower.time_point.month(),
-            epower.time_point.day(),
-            epower.time_point.hour(),
-            epower.time_point.minute(),
-            epower.time_point.second(),
-        )
-        .single()
-        .ok_or(DaqDaemonError::Other("time calculate error"))?;
-    let kwh = Decimal::from(epower.cumlative_amounts_power) * unit.0;
-    let rec = sqlx::query!(
-        r#"INSERT INTO cumlative_amount_epower ( recorded_at, kwh ) VALUES ( $1, $2 ) RETURNING id"#,
-        jst.with_timezone(&Utc),
-        kwh
-    )
-    .fetch_one(pool)
-    .await?;
+/// UDP の送信結果(EVENT 21)を待つ最長時間
+const UDP_SEND_RESULT_TIMEOUT: Duration = Duration::from_secs(10);
 
-    Ok(rec.id)
-}
+/// UDP の送信に失敗したときに再送する回数
+const UDP_SEND_MAX_RETRY: u32 = 1;
 
-/// 今日の積算電力量履歴をデーターベースに蓄積する
-async fn commit_historical_cumlative_amount(
-    pool: &PgPool,
-    unit: &SM::UnitForCumlativeAmountsPower,
-    hist: &SM::HistoricalCumlativeAmount,
+/// コマンドの即時応答を待つ最長時間
+const COMMAND_RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// コマンドを送信して即時応答(OK / FAIL)を待つ
+/// FAIL ならセッションを続けられないので接続し直す
+async fn send_command<T: AsyncWrite + Unpin + Send>(
+    serial_port: &Mutex<T>,
+    command: &[u8],
+    responses: &mut mpsc::UnboundedReceiver<CommandResponse>,
 ) -> result::Result<(), DaqDaemonError> {
-    // 現在時刻
-    let jst_now = Utc::now().with_timezone(&Asia::Tokyo);
-
-    // 現在時刻 - hist.n_days_ago 日の午前０時ちょうど
-    let day = Asia::Tokyo
-        .with_ymd_and_hms(jst_now.year(), jst_now.month(), jst_now.day(), 0, 0, 0)
-        .single()
-        .and_then(|jst_today| jst_today.checked_sub_days(Days::new(hist.n_days_ago as u64)))
-        .ok_or(DaqDaemonError::Other("time calculate error"))?;
-
-    // 30分間隔のTimeDelta
-    let halfhour =
-        TimeDelta::new(30 * 60, 0).ok_or(DaqDaemonError::Other("time calculate error"))?;
-
-    // 本日の午前０時ちょうどから30分毎の時刻列を作成するイテレータ
-    let mut accumulator = Some(day);
-    let timeserial = std::iter::from_fn(move || {
-        let ret = accumulator;
-        accumulator = accumulator.and_then(|v| v.checked_add_signed(halfhour));
-        ret
-    });
+    // 以前の応答は捨てる
+    while responses.try_recv().is_ok() {}
+    skstack::send_async(&mut *serial_port.lock().await, command).await?;
+    let deadline = tokio::time::Instant::now() + COMMAND_RESPONSE_TIMEOUT;
+    loop {
+        match tokio::time::timeout_at(deadline, responses.recv()).await {
+            Ok(Some(CommandResponse::Ok)) | Ok(None) => return Ok(()),
+            Ok(Some(CommandResponse::Fail(code))) => {
+                tracing::warn!("コマンド実行に失敗した。{code:X}(hex)");
+                return Err(DaqDaemonError::PanaSessionDisconnected);
+            }
+            Ok(Some(CommandResponse::UdpSendResult(_))) => continue,
+            Err(_) => {
+                tracing::warn!(
+                    "コマンドの応答を {:?} 以内に受信できなかった",
+                    COMMAND_RESPONSE_TIMEOUT
+                );
+                return Ok(());
+            }
+        }
+    }
+}
 
-    // 時間と積算電力量の組を作成する
-    let histrical_kwh = hist
-        .historical
-        .iter()
-        .zip(timeserial)
-        .map(|(opt_val, datetime)| -> Option<(DateTime<Utc>, Decimal)> {
-            match opt_val {
-                Some(val) => {
-                    let kwh = Decimal::from(*val) * unit.0;
-                    Some((datetime.with_timezone(&Utc), kwh))
+/// Echonetliteフレームを送信する
+/// 受信側から伝えられる応答が失敗なら再送する
+/// (即時応答 FAIL ER10 はセッションが無いので再送しないで接続し直す)
+async fn send_echonetlite_frame<T: AsyncWrite + Unpin + Send>(
+    serial_port: &Mutex<T>,
+    command: &[u8],
+    responses: &mut mpsc::UnboundedReceiver<CommandResponse>,
+) -> result::Result<(), DaqDaemonError> {
+    for retry in 0..=UDP_SEND_MAX_RETRY {
+        // 以前の応答は捨てる
+        while responses.try_recv().is_ok() {}
+        if retry > 0 {
+            tracing::warn!("UDP を再送する (retry {}/{})", retry, UDP_SEND_MAX_RETRY);
+        }
+        skstack::send_async(&mut *serial_port.lock().await, command).await?;
+        // 即時応答(OK)と送信結果(EVENT 21)の両方がそろうまで待つ
+        let deadline = tokio::time::Instant::now() + UDP_SEND_RESULT_TIMEOUT;
+        let mut acknowledged = false;
+        let mut send_result = None;
+        while !acknowledged || send_result.is_none() {
+            match tokio::time::timeout_at(deadline, responses.recv()).await {
+                Ok(Some(CommandResponse::Ok)) => acknowledged = true,
+                Ok(Some(CommandResponse::UdpSendResult(param))) => send_result = Some(param),
+                Ok(Some(CommandResponse::Fail(ER10_INVALID_STATE))) => {
+                    tracing::warn!("PANA セッションが無いので SKSENDTO を実行できなかった");
+                    return Err(DaqDaemonError::PanaSessionDisconnected);
+                }
+                Ok(Some(CommandResponse::Fail(code))) => {
+                    tracing::warn!(
+                        "SKSENDTO に失敗した (FAIL ER{:02X}, retry {}/{})",
+                        code,
+                        retry,
+                        UDP_SEND_MAX_RETRY
+                    );
+                    break;
+                }
+                Ok(None) => return Ok(()), // 受信側が終了した
+                Err(_) => {
+                    tracing::warn!(
+                        "UDP の送信結果を {:?} 以内に受信できなかった (retry {}/{})",
+                        UDP_SEND_RESULT_TIMEOUT,
+                        retry,
+                        UDP_SEND_MAX_RETRY
+                    );
+                    return Ok(());
                 }
-                None => None,
             }
-        })
-        .flatten()
-        .collect::<Vec<(DateTime<Utc>, Decimal)>>();
-
-    let mut query_builder =
-        QueryBuilder::new(r#"INSERT INTO cumlative_amount_epower (recorded_at, kwh)"#);
+        }
+        match send_result {
+            Some(0) => return Ok(()),
+            Some(param) => tracing::warn!(
+                "UDP の送信に失敗した (EVENT 21 param {}, retry {}/{})",
+                param,
+                retry,
+                UDP_SEND_MAX_RETRY
+            ),
+            None => {} // FAIL
+        }
+    }
+    tracing::error!(
+        "UDP の送信を {} 回再送したが失敗したので、この要求はあきらめる",
+        UDP_SEND_MAX_RETRY
+    );
+    Ok(())
+}
 
-    query_builder.push_values(histrical_kwh, |mut b, value| {
-        b.push_bind(value.0).push_bind(value.1);
-    });
+/// 送信のスケジュール
+struct RequestSchedules {
+    /// 瞬時電力と瞬時電流計測値
+    instant: Schedule,
+    /// 今日の積算電力量履歴(None なら接続時だけ)
+    history: Option<Schedule>,
+}
 
-    let query = query_builder.build();
-    query.execute(pool).await?;
+/// 同じ時刻に予定された要求を続けて送信するときの間隔
+const INTERLEAVE_INTERVAL: Duration = Duration::from_secs(2);
 
-    Ok(())
+/// メッセージ送信(今日の積算電力量履歴)
+async fn request_today_history<T: AsyncWrite + Unpin + Send>(
+    sender: &Ipv6Addr,
+    history_requested_at: &watch::Sender<DateTime<Utc>>,
+    feedback: &mut ReceiverFeedback,
+    serial_port: &Mutex<T>,
+) -> result::Result<(), DaqDaemonError> {
+    let command = skstack::command_from_echonetliteframe(sender, &TODAY_CWH)?;
+    history_requested_at.send_replace(Utc::now());
+    send_echonetlite_frame(serial_port, &command, &mut feedback.responses).await
 }
 
 #[tracing::instrument(skip_all)]
 /// 送信
-async fn smartmeter_transmitter<T: io::Write + Send>(
+async fn smartmeter_transmitter<T: AsyncWrite + Unpin + Send>(
     sender: &Ipv6Addr,
+    tz: &Tz,
+    schedules: &RequestSchedules,
     session_rejoin_period: Duration,
-    serial_port: &mut T,
+    history_requested_at: &watch::Sender<DateTime<Utc>>,
+    feedback: &mut ReceiverFeedback,
+    serial_port: &Mutex<T>,
 ) -> result::Result<(), DaqDaemonError> {
-    // メッセージ送信(今日の積算電力量履歴)
-    let command = skstack::command_from_echonetliteframe(&sender, &TODAY_CWH)?;
-    skstack::send(serial_port, &command)?;
+    // 接続したらすぐに今日の積算電力量履歴を取得する
+    request_today_history(sender, history_requested_at, feedback, serial_port).await?;
 
     let mut rejoin_time = Instant::now() + session_rejoin_period;
 
     // スケジュールに則りメッセージ送信
-    let schedule = Schedule::from_str("00 */1 * * * *")?;
-    for next in schedule.upcoming(Asia::Tokyo) {
+    // (2つのスケジュールのうち早い方の予定から順に送信する)
+    let mut last = Utc::now().with_timezone(tz);
+    loop {
+        let next_instant = schedules.instant.after(&last).next();
+        let next_history = schedules
+            .history
+            .as_ref()
+            .and_then(|s| s.after(&last).next());
+        let Some(next) = next_instant.into_iter().chain(next_history).min() else {
+            break;
+        };
+        last = next;
+        // 送信を保留している間に過ぎた予定は飛ばす
+        if next.to_utc() < Utc::now() {
+            tracing::trace!("Skip the past scheduled time. ({})", next);
+            continue;
+        }
         // 次回実行予定時刻まで待つ
         let duration = (next.to_utc() - Utc::now()).to_std()?;
         tracing::trace!("Next scheduled time. ({}), sleep ({:?})", next, duration);
         tokio::time::sleep(duration).await;
-        // メッセージ送信(瞬時電力と瞬時電流計測値)
-        let command = skstack::command_from_echonetliteframe(&sender, &INSTANT_WATT_AMPERE)?;
-        skstack::send(serial_port, &command)?;
+        // 送信総和時間の制限中は送信しない
+        wait_for_arib108_release(&mut feedback.arib108_limited).await;
+        if next_instant == Some(next) {
+            // メッセージ送信(瞬時電力と瞬時電流計測値)
+            let command = skstack::command_from_echonetliteframe(sender, &INSTANT_WATT_AMPERE)?;
+            send_echonetlite_frame(serial_port, &command, &mut feedback.responses).await?;
+        }
+        if next_history == Some(next) {
+            // 同じ時刻の瞬時値の要求と間を空ける
+            if next_instant == Some(next) {
+                tokio::time::sleep(INTERLEAVE_INTERVAL).await;
+                wait_for_arib108_release(&mut feedback.arib108_limited).await;
+            }
+            request_today_history(sender, history_requested_at, feedback, serial_port).await?;
+        }
         // 再認証を要求する
         let now = Instant::now();
         if now >= rejoin_time {
             tokio::time::sleep(Duration::from_secs(1)).await;
-            skstack::send(serial_port, b"SKREJOIN\r\n")?;
+            send_command(serial_port, b"SKREJOIN\r\n", &mut feedback.responses).await?;
             rejoin_time = now + session_rejoin_period;
         }
     }
@@ -407,75 +1160,337 @@ async fn smartmeter_transmitter<T: io::Write + Send>(
 
 #[tracing::instrument(skip_all)]
 /// 受信
-async fn smartmeter_receiver<T: io::Read + Send + 'static>(
-    pool: &PgPool,
-    settings: &ConnectionSettings,
-    serial_port_reader: &mut BufReader<T>,
-) -> result::Result<(), DaqDaemonError> {
+async fn smartmeter_receiver<S>(
+    db: &Database,
+    tz: &Tz,
+    meter: &Meter,
+    history_requested_at: &watch::Receiver<DateTime<Utc>>,
+    arib108_limited: &watch::Sender<bool>,
+    responses: &mpsc::UnboundedSender<CommandResponse>,
+    serial_port_reader: &mut S,
+) -> result::Result<(), DaqDaemonError>
+where
+    S: Stream<Item = io::Result<io::Result<skstack::SkRxD>>> + Unpin,
+{
+    // 積算電力量は 計測値 × 係数 × 単位
+    let unit = meter.settings.effective_unit();
     loop {
-        match skstack::receive(serial_port_reader) {
+        let received = match serial_port_reader.next().await {
+            Some(received) => received.and_then(|v| v),
+            None => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+        };
+        match received {
             Ok(skstack::SkRxD::Void) => {}
-            Ok(r @ skstack::SkRxD::Ok) => tracing::trace!("{r:?}"),
+            // コマンドの即時応答は送信側で扱う
+            Ok(r @ skstack::SkRxD::Ok) => {
+                tracing::trace!("{r:?}");
+                let _ = responses.send(CommandResponse::Ok);
+            }
             Ok(skstack::SkRxD::Fail(code)) => {
-                tracing::error!("コマンド実行に失敗した。{code:X}(hex)");
-                return Err(DaqDaemonError::CommandFail(code));
+                tracing::trace!("FAIL ER{code:02X}");
+                let _ = responses.send(CommandResponse::Fail(code));
             }
-            Ok(skstack::SkRxD::Event(event)) => match event.code {
-                0x01 => tracing::trace!("NS を受信した"),
-                0x02 => tracing::trace!("NA を受信した"),
-                0x05 => tracing::trace!("Echo Request を受信した"),
-                0x1f => tracing::trace!("ED スキャンが完了した"),
-                0x20 => tracing::trace!("Beacon を受信した"),
-                0x21 if Some(0) == event.param => tracing::trace!("UDP の送信に成功"),
-                0x21 if Some(1) == event.param => tracing::trace!("UDP の送信に失敗"),
-                0x21 if Some(2) == event.param => {
-                    tracing::trace!("UDP を送信する代わりにアドレス要請を行った")
-                }
-                0x22 => tracing::trace!("アクティブスキャンが完了した"),
-                0x24 => {
-                    tracing::trace!(
-                        "PANA による接続過程でエラーが発生した（接続が完了しなかった）"
-                    );
-                    return Err(DaqDaemonError::PanaSessionDisconnected);
-                }
-                0x25 => tracing::trace!("PANA による接続が完了した"),
-                0x26 => tracing::trace!("接続相手からセッション終了要求を受信した"),
-                0x27 => {
-                    tracing::trace!("PANA セッションの終了に成功した");
+            Ok(skstack::SkRxD::Event(event)) => {
+                // セッションが終わったら接続し直す(表は authn::session_end_event)
+                if let Some(reason) = authn::session_end_event(event.code) {
+                    tracing::info!("{reason} EVENT {:02X}", event.code);
                     return Err(DaqDaemonError::PanaSessionDisconnected);
                 }
-                0x28 => {
-                    tracing::trace!(
-                        "PANA セッションの終了要求に対する応答がなくタイムアウトした（セッションは終了）"
-                    );
-                    return Err(DaqDaemonError::PanaSessionDisconnected);
-                }
-                0x29 => {
-                    tracing::trace!("セッションのライフタイムが経過して期限切れになった");
-                    return Err(DaqDaemonError::PanaSessionDisconnected);
+                match event.code {
+                    0x01 => tracing::trace!("NS を受信した"),
+                    0x02 => tracing::trace!("NA を受信した"),
+                    0x05 => tracing::trace!("Echo Request を受信した"),
+                    0x1f => tracing::trace!("ED スキャンが完了した"),
+                    0x20 => tracing::trace!("Beacon を受信した"),
+                    0x21 => {
+                        match event.param {
+                            Some(0) => tracing::trace!("UDP の送信に成功"),
+                            Some(1) => tracing::trace!("UDP の送信に失敗"),
+                            Some(2) => {
+                                tracing::trace!("UDP を送信する代わりにアドレス要請を行った")
+                            }
+                            _ => tracing::trace!("{event:?}"),
+                        }
+                        // 送信結果を送信側に伝える
+                        if let Some(param) = event.param {
+                            let _ = responses.send(CommandResponse::UdpSendResult(param));
+                        }
+                    }
+                    0x22 => tracing::trace!("アクティブスキャンが完了した"),
+                    0x25 => tracing::trace!("PANA による接続が完了した"),
+                    0x32 => {
+                        tracing::warn!("ARIB108 の送信総和時間の制限が発動した");
+                        arib108_limited.send_replace(true);
+                    }
+                    0x33 => {
+                        tracing::warn!("送信総和時間の制限が解除された");
+                        arib108_limited.send_replace(false);
+                    }
+                    _ => tracing::trace!("{event:?}"),
                 }
-                0x32 => tracing::trace!("ARIB108 の送信総和時間の制限が発動した"),
-                0x33 => tracing::trace!("送信総和時間の制限が解除された"),
-                _ => tracing::trace!("{event:?}"),
-            },
-            Ok(r @ skstack::SkRxD::Epandesc(_)) => tracing::trace!("{r:?}"),
-            Ok(skstack::SkRxD::Erxudp(erxudp)) => rx_erxudp(&pool, &settings.Unit, &erxudp).await?,
+            }
+            Ok(r @ (skstack::SkRxD::Epandesc(_) | skstack::SkRxD::NeighborTable(_))) => {
+                tracing::trace!("{r:?}")
+            }
+            Ok(skstack::SkRxD::Erxudp(erxudp)) => {
+                // 積算電力量履歴を要求した時刻
+                let requested_at = *history_requested_at.borrow();
+                rx_erxudp(db, tz, meter, &unit, &requested_at, &erxudp).await?
+            }
             Err(e) if e.kind() == io::ErrorKind::TimedOut => {} // タイムアウトエラーは無視する
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => tracing::warn!("{e}"), // 次の受信で同期を取り直す
             Err(e) => return Err(DaqDaemonError::from(e)),
         }
-        tokio::task::yield_now().await;
+    }
+}
+
+/// 再始動までの待ち時間の初期値(続けて再始動する毎に倍にする)
+const RESTART_INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+
+/// 再始動までの待ち時間の最大値
+const RESTART_MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// これより長く動き続けたら再始動までの待ち時間を初期値に戻す
+const RESTART_BACKOFF_RESET: Duration = Duration::from_secs(30 * 60);
+
+/// データベースの準備ができるまで再試行する最大回数
+const DATABASE_MAX_RETRY: u32 = 8;
+
+/// 再試行までの待ち時間の初期値(再試行毎に倍にする)
+const DATABASE_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// 再試行までの待ち時間の最大値
+const DATABASE_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// データベースがまだ起動していないだけ(待てば接続できる)のエラーか
+/// 設定の誤りなど待っても直らないエラーは false
+fn is_database_not_ready(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        // 08xxx: connection exception, 57P03: cannot_connect_now(起動中)
+        sqlx::Error::Database(e) => e
+            .code()
+            .is_some_and(|code| code.starts_with("08") || code == "57P03"),
+        _ => false,
+    }
+}
+
+/// データベースの準備ができていなければ待ってから再試行する
+/// 待っている間に終了シグナルを受け取ったら None を返す
+async fn retry_while_database_not_ready<T, F, Fut>(
+    what: &str,
+    shutdown: &CancellationToken,
+    mut f: F,
+) -> result::Result<Option<T>, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = result::Result<T, sqlx::Error>>,
+{
+    let mut backoff = DATABASE_INITIAL_BACKOFF;
+    let mut retry = 0;
+    loop {
+        let result = tokio::select! {
+            v = f() => v,
+            _ = shutdown.cancelled() => return Ok(None),
+        };
+        match result {
+            Ok(v) => return Ok(Some(v)),
+            Err(e) if retry < DATABASE_MAX_RETRY && is_database_not_ready(&e) => {
+                retry += 1;
+                tracing::warn!(
+                    "{} failed, database is not ready yet ({}), retry in {:?} ({}/{})",
+                    what,
+                    e,
+                    backoff,
+                    retry,
+                    DATABASE_MAX_RETRY
+                );
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {},
+                    _ = shutdown.cancelled() => return Ok(None),
+                }
+                backoff = (backoff * 2).min(DATABASE_MAX_BACKOFF);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// 溜めている瞬時電力と瞬時電流を蓄積する
+/// 蓄積できなければ溜めたままにして次の機会に蓄積する
+async fn flush_instant_buffer(db: &Database, buffer: &Mutex<InstantBuffer>) {
+    let mut buffer = buffer.lock().await;
+    match buffer.flush(db).await {
+        Ok(0) => {}
+        Ok(n) => tracing::debug!("{n} instant values are committed to the database"),
+        Err(e) => tracing::error!(
+            "{} instant values are not committed to the database yet, reason: {e}",
+            buffer.len()
+        ),
+    }
+}
+
+/// 溜めている瞬時電力と瞬時電流を一定間隔で蓄積する
+async fn flush_instant_buffer_periodically(
+    db: &Database,
+    buffer: &Mutex<InstantBuffer>,
+    period: Duration,
+) -> ! {
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    loop {
+        interval.tick().await;
+        flush_instant_buffer(db, buffer).await;
     }
 }
 
 /// スマートメーターからデーターを収集する
 async fn exec_data_acquisition(
-    port_name: &str,
-    database_url: &str,
+    config: &Arc<ServiceConfig>,
+    shutdown: &CancellationToken,
 ) -> result::Result<(), DaqDaemonError> {
-    let pool = PgPool::connect(database_url).await?;
+    // データベースが起動するまで待つ
+    let Some(db) = retry_while_database_not_ready("database connect", shutdown, || {
+        Database::connect(&config.database_url)
+    })
+    .await?
+    else {
+        return Ok(()); // 終了シグナルを受け取った
+    };
 
     // データベースからスマートメーターの情報を得る
-    let settings = read_settings(&pool).await?;
+    let rows =
+        retry_while_database_not_ready("read settings", shutdown, || db.read_active_settings())
+            .await?;
+    let Some(rows) = rows else {
+        return Ok(()); // 終了シグナルを受け取った
+    };
+    // ペアリングをまだしていない
+    if rows.is_empty() {
+        return Err(DaqDaemonError::NoSettings);
+    }
+    // 瞬時電力と瞬時電流を溜めておくバッファ(全てのスマートメーターで共有する)
+    let instant_buffer = config
+        .instant_flush_interval
+        .map(|_| Arc::new(Mutex::new(InstantBuffer::default())));
+    let mut meters = Vec::new();
+    for (n, (id, settings)) in rows.into_iter().enumerate() {
+        settings
+            .validate()
+            .map_err(DaqDaemonError::InvalidSettings)?;
+        // 以前の設定情報にはシリアルデバイス名が無い
+        let serial_device = settings
+            .SerialDevice
+            .clone()
+            .unwrap_or(config.serial_device.clone());
+        tracing::info!(
+            meter_id = id,
+            mac_address = settings.MacAddress,
+            "smart meter on {serial_device}"
+        );
+        meters.push(Meter {
+            id,
+            settings,
+            serial_device,
+            primary: n == 0,
+            udp_routes: config.udp_routes.clone(),
+            instant_buffer: instant_buffer.clone(),
+            not_ready_logged_at: std::sync::Mutex::new(None),
+            power_alert: config
+                .power_alert
+                .clone()
+                .map(|threshold| std::sync::Mutex::new(PowerAlert::new(threshold))),
+            fault_status: std::sync::Mutex::new(None),
+        });
+    }
+
+    // Home Assistant にセンサーを知らせる
+    if config.ha_discovery {
+        mqtt::announce_home_assistant(&meters[0].settings.MacAddress);
+    }
+
+    // スマートメーター毎に送受信する
+    // 1台でも止まったら全部止めて再始動する
+    // 接続や再接続で1台が待っている間も他のスマートメーターと終了シグナルを扱えるように
+    // スマートメーター毎にタスクを分ける
+    let stop = shutdown.child_token();
+    let handles = meters
+        .into_iter()
+        .map(|meter| {
+            let config = Arc::clone(config);
+            let db = db.clone();
+            let stop = stop.clone();
+            tokio::spawn(async move {
+                let result = exec_meter(&config, db, meter, &stop).await;
+                if result.is_err() {
+                    stop.cancel();
+                }
+                result
+            })
+        })
+        .collect::<Vec<_>>();
+    let meters = futures::future::join_all(handles);
+    let results = match (&instant_buffer, config.instant_flush_interval) {
+        (Some(buffer), Some(period)) => {
+            tokio::select! {
+                results = meters => results,
+                _ = flush_instant_buffer_periodically(&db, buffer, period) => unreachable!(),
+            }
+        }
+        _ => meters.await,
+    };
+
+    // 溜めている瞬時値を蓄積する(終了時と再始動前)
+    if let Some(buffer) = &instant_buffer {
+        flush_instant_buffer(&db, buffer).await;
+    }
+
+    // データーベースとの接続を閉じる
+    if shutdown.is_cancelled() {
+        db.close().await;
+    }
+    results.into_iter().try_for_each(|v| v?)
+}
+
+/// データーベースとシリアルポートの接続を確かめる
+/// データーベースから設定情報を読んで, スマートメーター毎のシリアルポートにSKVERを送る
+async fn exec_healthcheck(config: &ServiceConfig) -> result::Result<(), DaqDaemonError> {
+    let db = Database::connect(&config.database_url).await?;
+    let rows = db.read_active_settings().await?;
+    db.close().await;
+    println!("database: {} settings", rows.len());
+    // ペアリングをまだしていない
+    if rows.is_empty() {
+        return Err(DaqDaemonError::NoSettings);
+    }
+    for (id, settings) in rows.into_iter() {
+        settings
+            .validate()
+            .map_err(DaqDaemonError::InvalidSettings)?;
+        // 以前の設定情報にはシリアルデバイス名が無い
+        let serial_device = settings
+            .SerialDevice
+            .unwrap_or(config.serial_device.clone());
+        let serial_port =
+            skstack::open_serial(&serial_device, config.serial_baud, config.serial_timeout)?;
+        let mut serial_port_reader = serial_port
+            .try_clone()
+            .map(BufReader::new)
+            .or(Err(DaqDaemonError::Other("Failed to clone serial_port")))?;
+        let mut serial_port_writer = serial_port;
+        let version = skstack::version(&mut serial_port_reader, &mut serial_port_writer)?;
+        println!("meter_id {id}: {serial_device} SKSTACK version {version}");
+    }
+    Ok(())
+}
+
+/// スマートメーターと接続してデーターを収集する
+async fn exec_meter(
+    config: &ServiceConfig,
+    db: Database,
+    meter: Meter,
+    shutdown: &CancellationToken,
+) -> result::Result<(), DaqDaemonError> {
+    let settings = &meter.settings;
     let credentials = authn::Credentials {
         id: authn::Id::from_str(&settings.RouteBId).map_err(|e| DaqDaemonError::InvalidId(e))?,
         password: authn::Password::from_str(&settings.RouteBPassword)
@@ -491,73 +1506,178 @@ async fn exec_data_acquisition(
         0xFE80_0000_0000_0000u128 << 64 | (mac_address as u128 ^ 0x0200_0000_0000_0000u128),
     );
 
-    // シリアルポートを開く
-    let mut serial_port = serialport::new(port_name, 115200)
-        .stop_bits(StopBits::One)
-        .data_bits(DataBits::Eight)
-        .timeout(Duration::from_secs(1))
-        .open()?;
-
-    // シリアルポート読み込みはバッファリングする
-    let mut serial_port_reader = serial_port
-        .try_clone()
-        .and_then(|cloned| Ok(BufReader::new(cloned)))
-        .or(Err(DaqDaemonError::Other("Failed to clone serial_port")))?;
-
-    // PANA セッションライフタイム値
-    const SESSION_LIFETIME_OF_SECOND: u32 = 900;
-
     // PANA セッション再認証間隔
-    let session_rejoin_period = Duration::from_secs_f32(SESSION_LIFETIME_OF_SECOND as f32 * 0.7);
+    let session_rejoin_period =
+        Duration::from_secs_f32(config.session_lifetime as f32 * config.rejoin_factor);
 
     let custom_commands = [
-        format!("SKSREG S16 {:X}\r\n", SESSION_LIFETIME_OF_SECOND), // PANA セッションライフタイム値
+        format!("SKSREG S16 {:X}", config.session_lifetime), // PANA セッションライフタイム値
     ];
 
     // スマートメーターと接続する
-    authn::connect(
-        &mut serial_port_reader,
-        &mut serial_port,
-        &credentials,
-        &sender,
-        settings.Channel,
-        settings.PanId,
-    )?;
-
-    // 追加コマンド発行
-    for command in custom_commands.iter() {
-        skstack::send(&mut serial_port, command.as_bytes())?;
-        thread::sleep(Duration::from_millis(1));
-        if let skstack::SkRxD::Fail(code) = skstack::receive(&mut serial_port_reader)? {
-            tracing::error!(
-                r#"コマンド "{}" 実行に失敗しました。"#,
-                command.escape_debug()
-            );
-            return Err(DaqDaemonError::CommandFail(code));
+    // 接続には数秒かかるので, 他のスマートメーターを止めないようにブロッキングスレッドで行う
+    let serial_device = meter.serial_device.clone();
+    let (serial_baud, serial_timeout) = (config.serial_baud, config.serial_timeout);
+    let (channel, pan_id) = (settings.Channel, settings.PanId);
+    // 接続中に終了シグナルを受け取ったら接続を中止する(None を返す)
+    let cancel = shutdown.clone();
+    let connected = tokio::task::spawn_blocking(move || -> result::Result<_, DaqDaemonError> {
+        if cancel.is_cancelled() {
+            return Ok(None);
         }
-    }
+        // シリアルポートを開く
+        let mut serial_port =
+            skstack::open_serial_native(&serial_device, serial_baud, serial_timeout)?;
+
+        // シリアルポート読み込みはバッファリングする
+        let mut serial_port_reader = serial_port
+            .try_clone_native()
+            .map(BufReader::new)
+            .or(Err(DaqDaemonError::Other("Failed to clone serial_port")))?;
+
+        match authn::connect_cancellable(
+            &mut serial_port_reader,
+            &mut serial_port,
+            &credentials,
+            &sender,
+            channel,
+            pan_id,
+            &|| cancel.is_cancelled(),
+        ) {
+            Err(authn::Error::Cancelled) => return Ok(None),
+            result => result?,
+        }
+
+        // 追加コマンド発行
+        for command in custom_commands.iter() {
+            if cancel.is_cancelled() {
+                return Ok(None);
+            }
+            skstack::send_line(&mut serial_port, command)?;
+            thread::sleep(Duration::from_millis(1));
+            if let skstack::SkRxD::Fail(code) =
+                skstack::receive_skip_unparsed(&mut serial_port_reader)?
+            {
+                tracing::error!(
+                    r#"コマンド "{}" 実行に失敗しました。"#,
+                    command.escape_debug()
+                );
+                return Err(DaqDaemonError::CommandFail(code));
+            }
+        }
+        Ok(Some((serial_port, serial_port_reader)))
+    })
+    .await??;
+    let Some((serial_port, serial_port_reader)) = connected else {
+        return Ok(());
+    };
+
+    // 接続後は非同期で送受信する
+    // (接続までに読み込んでバッファに残っている受信データは引き継ぐ)
+    let leftover = serial_port_reader.buffer().to_vec();
+    drop(serial_port_reader);
+    let (reader, writer) = tokio::io::split(SerialStream::try_from(serial_port)?);
+    let mut serial_port_reader = skstack::framed_reader(reader);
+    serial_port_reader
+        .read_buffer_mut()
+        .extend_from_slice(&leftover);
+    let serial_port = Arc::new(Mutex::new(writer));
+
+    // 終了時にセッションを切断するためのシリアルポート
+    let serial_port_for_term = Arc::clone(&serial_port);
+
+    // 積算電力量履歴を要求した時刻を送信側から受信側へ伝える
+    let (history_requested_at_tx, history_requested_at_rx) = watch::channel(Utc::now());
+
+    // 送信総和時間の制限中であることを受信側から送信側へ伝える
+    let (arib108_limited_tx, arib108_limited_rx) = watch::channel(false);
+
+    // 送信したコマンドへの応答を受信側から送信側へ伝える
+    let (responses_tx, responses_rx) = mpsc::unbounded_channel();
+
+    let mut feedback = ReceiverFeedback {
+        arib108_limited: arib108_limited_rx,
+        responses: responses_rx,
+    };
 
     // スマートメーター送信用スレッド
-    let handle_transmitter = tokio::spawn(async move {
-        smartmeter_transmitter(&sender, session_rejoin_period, &mut serial_port).await
+    let schedules = RequestSchedules {
+        instant: config.poll_schedule.clone(),
+        history: config.history_schedule.clone(),
+    };
+    let tz = config.timezone;
+    let mut handle_transmitter = tokio::spawn(async move {
+        smartmeter_transmitter(
+            &sender,
+            &tz,
+            &schedules,
+            session_rejoin_period,
+            &history_requested_at_tx,
+            &mut feedback,
+            &serial_port,
+        )
+        .await
     });
 
     // スマートメーター受信用スレッド
-    let handle_receiver = tokio::spawn(async move {
-        smartmeter_receiver(&pool, &settings, &mut serial_port_reader).await
+    let mut handle_receiver = tokio::spawn(async move {
+        smartmeter_receiver(
+            &db,
+            &tz,
+            &meter,
+            &history_requested_at_rx,
+            &arib108_limited_tx,
+            &responses_tx,
+            &mut serial_port_reader,
+        )
+        .await
     });
 
-    //
+    // 送信側, 受信側のどちらかが終わるか終了シグナルを受け取ったら送受信を止める
+    // (残った方がシリアルポートを握ったままだと再接続したセッションと競合する)
+    let result = tokio::select! {
+        v = &mut handle_transmitter => {
+            handle_receiver.abort();
+            let _ = handle_receiver.await;
+            v.unwrap_or_else(|e| Err(e.into()))
+        }
+        v = &mut handle_receiver => {
+            handle_transmitter.abort();
+            let _ = handle_transmitter.await;
+            v.unwrap_or_else(|e| Err(e.into()))
+        }
+        _ = shutdown.cancelled() => {
+            handle_transmitter.abort();
+            handle_receiver.abort();
+            let _ = handle_transmitter.await;
+            let _ = handle_receiver.await;
+            Ok(())
+        }
+    };
+
+    // PANAセッションを終了する
+    tracing::info!("terminate PANA session");
+    let term = skstack::send_async(&mut *serial_port_for_term.lock().await, b"SKTERM\r\n").await;
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    result?;
+    term?;
+    Ok(())
+}
+
+/// SIGTERM または SIGINT を待つ
+async fn wait_for_shutdown_signal() -> io::Result<()> {
+    let mut sigterm = signal::unix::signal(signal::unix::SignalKind::terminate())?;
     tokio::select! {
-        v = handle_transmitter => v.unwrap(),
-        v = handle_receiver => v.unwrap()
+        _ = sigterm.recv() => tracing::info!("SIGTERM received"),
+        v = signal::ctrl_c() => { v?; tracing::info!("SIGINT received") },
     }
+    Ok(())
 }
 
-/// SKSETPWD C 以降のパスワードをマスクするフォーマッタ
-struct MaskingRouteBPasswordFormatter;
+/// パスワードやIDなどの秘密情報をマスクするフォーマッタ
+struct MaskingCredentialsFormatter;
 
-impl<S, N> FormatEvent<S, N> for MaskingRouteBPasswordFormatter
+impl<S, N> FormatEvent<S, N> for MaskingCredentialsFormatter
 where
     S: Subscriber + for<'a> LookupSpan<'a>,
     N: for<'writer> FormatFields<'writer> + 'static,
@@ -576,13 +1696,7 @@ where
         }
 
         // マスク処理
-        const PATTERN: &'static str = "SKSETPWD C ";
-        if let Some(pos) = buf.find(PATTERN) {
-            let start = pos + PATTERN.len();
-            let end = (start + 12).min(buf.len() - 1);
-            let masking_str = "#".repeat(end - start);
-            buf.replace_range(start..end, &masking_str)
-        }
+        redact::mask_credentials(&mut buf);
         // 出力
         writer.write_str(&buf)
     }
@@ -590,6 +1704,8 @@ where
 
 #[tokio::main]
 async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
     // プログラムの情報
     let git_head_ref = built_info::GIT_HEAD_REF.unwrap_or_default();
     let app_info = format!(
@@ -619,29 +1735,192 @@ async fn main() -> ExitCode {
                         .with_thread_names(false)
                         .with_thread_ids(false)
                         .with_ansi(false)
-                        .event_format(MaskingRouteBPasswordFormatter),
+                        .event_format(MaskingCredentialsFormatter),
                 )
                 .init();
             tracing::error!("couldn't connect to journald: {}", e)
         }
     }
 
+    // 設定ファイルと環境変数
+    let load_config = || -> result::Result<ServiceConfig, DaqDaemonError> {
+        let file = match cli.config.as_ref() {
+            Some(path) => ConfigFile::load(path)
+                .inspect_err(|_| tracing::error!(r#"Failed to read "{}"."#, path.display()))?,
+            None => ConfigFile::default(),
+        };
+        Ok(ServiceConfig {
+            ha_discovery: cli.ha_discovery,
+            ..ServiceConfig::from_env(&file)?
+        })
+    };
+
+    // 接続を確かめて終了する
+    if cli.check {
+        let result = match load_config() {
+            Ok(config) => exec_healthcheck(&config).await,
+            Err(e) => Err(e),
+        };
+        return match result {
+            Ok(()) => {
+                println!("check passed.");
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("check failed: {e}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    // 終了シグナルを受け取ったらサービスを止める
+    let shutdown = CancellationToken::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            if let Err(e) = wait_for_shutdown_signal().await {
+                tracing::error!("couldn't listen for shutdown signal: {e}");
+                return;
+            }
+            shutdown.cancel();
+        }
+    });
+
+    // メトリクスを公開する
+    if let Ok(addr) = env::var("METRICS_ADDR") {
+        match addr.parse::<SocketAddr>() {
+            Ok(addr) => {
+                tokio::spawn(async move {
+                    if let Err(e) = metrics::serve(addr).await {
+                        tracing::error!("metrics endpoint error: {e}");
+                    }
+                });
+            }
+            Err(e) => {
+                tracing::error!("{app_info} aborted, reason: invalid METRICS_ADDR {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    // 受信値を InfluxDB に書き出す
+    if let Ok(url) = env::var("INFLUXDB_URL") {
+        let flush_interval = match env::var("INFLUXDB_FLUSH_INTERVAL_SECS") {
+            Ok(s) => match s.parse::<u64>().ok().filter(|n| *n > 0) {
+                Some(n) => Duration::from_secs(n),
+                None => {
+                    tracing::error!(
+                        "{app_info} aborted, reason: INFLUXDB_FLUSH_INTERVAL_SECS must be a positive integer (seconds)"
+                    );
+                    return ExitCode::FAILURE;
+                }
+            },
+            Err(_) => influxdb::DEFAULT_FLUSH_INTERVAL,
+        };
+        let config = influxdb::InfluxDbConfig {
+            url,
+            token: env::var("INFLUXDB_TOKEN").ok(),
+            flush_interval,
+        };
+        if let Err(e) = influxdb::start(config) {
+            tracing::error!("{app_info} aborted, reason: influxdb exporter {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    // 出来事を Webhook で知らせる
+    if let Ok(url) = env::var("WEBHOOK_URL") {
+        let events = match env::var("WEBHOOK_EVENTS") {
+            Ok(s) => match webhook::parse_events(&s) {
+                Ok(events) => events,
+                Err(e) => {
+                    tracing::error!("{app_info} aborted, reason: WEBHOOK_EVENTS {e}");
+                    return ExitCode::FAILURE;
+                }
+            },
+            Err(_) => webhook::EVENT_KINDS.iter().map(|s| s.to_string()).collect(),
+        };
+        if let Err(e) = webhook::start(webhook::WebhookConfig { url, events }) {
+            tracing::error!("{app_info} aborted, reason: webhook notifier {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    // 受信値を MQTT で公開する
+    if let Ok(host) = env::var("MQTT_HOST") {
+        let port = match env::var("MQTT_PORT") {
+            Ok(s) => match s.parse::<u16>() {
+                Ok(n) => n,
+                Err(e) => {
+                    tracing::error!("{app_info} aborted, reason: invalid MQTT_PORT {e}");
+                    return ExitCode::FAILURE;
+                }
+            },
+            Err(_) => mqtt::DEFAULT_PORT,
+        };
+        mqtt::start(mqtt::MqttConfig {
+            host,
+            port,
+            client_id: built_info::PKG_NAME.to_string(),
+            username: env::var("MQTT_USERNAME").ok(),
+            password: env::var("MQTT_PASSWORD").ok(),
+            topic_prefix: env::var("MQTT_TOPIC_PREFIX")
+                .unwrap_or(mqtt::DEFAULT_TOPIC_PREFIX.to_string()),
+        });
+    }
+    if cli.ha_discovery && !mqtt::is_enabled() {
+        tracing::warn!("--ha-discovery is ignored because MQTT_HOST is not set");
+    }
+
     // このサービス本体
     let the_service_provider = async || -> result::Result<(), DaqDaemonError> {
-        // 環境変数
-        let serial_device = env::var("SERIAL_DEVICE")
-            .map_err(|_| DaqDaemonError::Other(r#"Must be set to "SERIAL_DEVICE" environment."#))?;
-        let database_url = env::var("DATABASE_URL")
-            .map_err(|_| DaqDaemonError::Other(r#"Must be set to "DATABASE_URL" environment."#))?;
-        exec_data_acquisition(&serial_device, &database_url).await
+        let config = Arc::new(load_config()?);
+        tracing::info!(
+            "serial port: {} ({} baud, timeout {:?})",
+            config.serial_device,
+            config.serial_baud,
+            config.serial_timeout
+        );
+        tracing::info!(
+            "poll schedule: {}, PANA session lifetime: {}s, rejoin factor: {}, timezone: {}",
+            config.poll_schedule,
+            config.session_lifetime,
+            config.rejoin_factor,
+            config.timezone
+        );
+        if let Some(history_schedule) = &config.history_schedule {
+            tracing::info!("history schedule: {history_schedule}");
+        }
+        exec_data_acquisition(&config, &shutdown).await
+    };
+
+    // 再始動まで少々クールダウン時間をもつ
+    // 続けて再始動するたびに待ち時間を倍にして, しばらく動き続けたら初期値に戻す
+    let mut backoff = RESTART_INITIAL_BACKOFF;
+    let mut cool_down = async |running: Duration| {
+        if running >= RESTART_BACKOFF_RESET {
+            backoff = RESTART_INITIAL_BACKOFF;
+        }
+        tracing::info!("restart in {backoff:?}");
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {},
+            _ = shutdown.cancelled() => {},
+        }
+        backoff = (backoff * 2).min(RESTART_MAX_BACKOFF);
     };
 
     // サービスを開始する
     tracing::info!("{app_info} started.");
     let reason = loop {
+        // 終了シグナルを受け取ったので正常終了する
+        if shutdown.is_cancelled() {
+            tracing::info!("{app_info} stopped.");
+            return ExitCode::SUCCESS;
+        }
+        let started_at = Instant::now();
         break match the_service_provider().await {
             Ok(()) => {
-                tokio::time::sleep(Duration::from_secs(5)).await; // 再始動まで少々クールダウン時間をもつ
+                cool_down(started_at.elapsed()).await;
                 continue; // 再始動
             }
             Err(e @ DaqDaemonError::Io(_)) => e.to_string(),
@@ -650,12 +1929,22 @@ async fn main() -> ExitCode {
             Err(e @ DaqDaemonError::OutOfRange(_)) => e.to_string(),
             Err(e @ DaqDaemonError::SerialPort(_)) => e.to_string(),
             Err(e @ DaqDaemonError::Database(_)) => e.to_string(),
+            Err(e @ DaqDaemonError::ConfigFile(_)) => e.to_string(),
+            Err(e @ DaqDaemonError::TaskJoin(_)) => {
+                // タスクの異常終了(panic)はデーモンを止めずに再始動する
+                tracing::error!("{e}");
+                cool_down(started_at.elapsed()).await;
+                continue; // 再始動
+            }
             Err(e @ DaqDaemonError::InvalidId(_)) => e.to_string(),
             Err(e @ DaqDaemonError::InvalidPassword(_)) => e.to_string(),
             Err(e @ DaqDaemonError::InvalidMacAddress) => e.to_string(),
             Err(e @ DaqDaemonError::CommandFail(_)) => e.to_string(),
+            Err(e @ DaqDaemonError::NoSettings) => e.to_string(),
+            Err(e @ DaqDaemonError::InvalidSettings(_)) => e.to_string(),
             Err(DaqDaemonError::PanaSessionDisconnected) => {
-                tokio::time::sleep(Duration::from_secs(5)).await; // 再始動まで少々クールダウン時間をもつ
+                webhook::notify(WebhookEvent::SessionDisconnected);
+                cool_down(started_at.elapsed()).await;
                 continue; // 再始動
             }
             Err(e @ DaqDaemonError::Other(_)) => e.to_string(),
@@ -0,0 +1,301 @@
+// 受信値を蓄積する
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use crate::echonetlite::smart_electric_energy_meter as SM;
+use crate::store::MeasurementStore;
+use chrono::{DateTime, Datelike, TimeDelta, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CommitError {
+    #[error(r#"database "{0}""#)]
+    Database(#[from] sqlx::Error),
+
+    #[error("{0}")]
+    Other(&'static str),
+}
+
+/// スマートメーターの時計と受信時刻のずれの許容範囲
+pub const ALLOWED_METER_CLOCK_SKEW: TimeDelta = TimeDelta::hours(48);
+
+/// 受信したプロパティ値を蓄積する
+/// recorded_at は受信時刻, history_requested_at は積算電力量履歴を要求した時刻
+/// 蓄積しないプロパティなら false を返す
+pub async fn commit_property<S: MeasurementStore>(
+    store: &mut S,
+    meter_id: i64,
+    tz: &Tz,
+    unit: &SM::UnitForCumlativeAmountsPower,
+    recorded_at: &DateTime<Utc>,
+    history_requested_at: &DateTime<Utc>,
+    prop: &SM::Properties,
+) -> Result<bool, CommitError> {
+    match prop {
+        // 0xe2 積算電力量計測値履歴1 (正方向計測値)
+        SM::Properties::HistoricalCumlativeAmount(hist) => {
+            commit_historical_cumlative_amount(
+                store,
+                meter_id,
+                tz,
+                unit,
+                history_requested_at,
+                hist,
+            )
+            .await?;
+        }
+        // 0xe7 瞬時電力計測値
+        SM::Properties::InstantiousPower(epower) => {
+            commit_instant_epower(store, meter_id, recorded_at, epower).await?;
+        }
+        // 0xe8 瞬時電流計測値
+        SM::Properties::InstantiousCurrent(current) => {
+            commit_instant_current(store, meter_id, recorded_at, current).await?;
+        }
+        // 0xea 定時積算電力量計測値(正方向計測値)
+        // 0xeb 定時積算電力量計測値(逆方向計測値)
+        SM::Properties::CumlativeAmountsOfPowerAtFixedTime(_)
+        | SM::Properties::ReverseCumlativeAmountsOfPowerAtFixedTime(_) => {
+            commit_cumlative_amount_epower(store, meter_id, tz, unit, recorded_at, prop).await?;
+        }
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// 瞬時電力を蓄積する
+/// 符号はそのまま蓄積する(マイナスは逆潮流, Watt::is_export)
+pub async fn commit_instant_epower<S: MeasurementStore>(
+    store: &mut S,
+    meter_id: i64,
+    recorded_at: &DateTime<Utc>,
+    epower: &SM::InstantiousPower,
+) -> Result<(), CommitError> {
+    store
+        .insert_instant_power(meter_id, recorded_at, &epower.watts())
+        .await?;
+    Ok(())
+}
+
+/// 瞬時電流を蓄積する
+pub async fn commit_instant_current<S: MeasurementStore>(
+    store: &mut S,
+    meter_id: i64,
+    recorded_at: &DateTime<Utc>,
+    current: &SM::InstantiousCurrent,
+) -> Result<(), CommitError> {
+    store
+        .insert_current(meter_id, recorded_at, &current.r, &current.t)
+        .await?;
+    Ok(())
+}
+
+/// 定時積算電力量計測値を蓄積する
+/// 正方向計測値(0xea)と逆方向計測値(0xeb)で蓄積先のテーブルが異なる
+pub async fn commit_cumlative_amount_epower<S: MeasurementStore>(
+    store: &mut S,
+    meter_id: i64,
+    tz: &Tz,
+    unit: &SM::UnitForCumlativeAmountsPower,
+    recorded_at: &DateTime<Utc>,
+    prop: &SM::Properties,
+) -> Result<(), CommitError> {
+    let (time_point, cumlative_amounts_power, reverse) = match prop {
+        SM::Properties::CumlativeAmountsOfPowerAtFixedTime(a) => {
+            (a.time_point, a.cumlative_amounts_power, false)
+        }
+        SM::Properties::ReverseCumlativeAmountsOfPowerAtFixedTime(a) => {
+            (a.time_point, a.cumlative_amounts_power, true)
+        }
+        _ => return Err(CommitError::Other("not a cumlative amounts of power")),
+    };
+    let local = tz
+        .with_ymd_and_hms(
+            time_point.year(),
+            time_point.month(),
+            time_point.day(),
+            time_point.hour(),
+            time_point.minute(),
+            time_point.second(),
+        )
+        .single()
+        .ok_or(CommitError::Other("time calculate error"))?;
+    // スマートメーターの時計が明らかにおかしい場合は蓄積しない
+    let skew = (local.with_timezone(&Utc) - *recorded_at).abs();
+    if skew > ALLOWED_METER_CLOCK_SKEW {
+        tracing::warn!(
+            r#"Meter time "{}" is too far from {} (skew {}s), this data is not committed to the database"#,
+            local.to_rfc3339(),
+            recorded_at.to_rfc3339(),
+            skew.num_seconds()
+        );
+        return Ok(());
+    }
+    let kwh = unit.kwh(cumlative_amounts_power);
+    let values = [(local.with_timezone(&Utc), kwh)];
+    if reverse {
+        store.insert_reverse_cumulative(meter_id, &values).await?;
+    } else {
+        store.insert_cumulative(meter_id, &values).await?;
+    }
+
+    Ok(())
+}
+
+/// 積算電力量履歴を蓄積する
+pub async fn commit_historical_cumlative_amount<S: MeasurementStore>(
+    store: &mut S,
+    meter_id: i64,
+    tz: &Tz,
+    unit: &SM::UnitForCumlativeAmountsPower,
+    requested_at: &DateTime<Utc>,
+    hist: &SM::HistoricalCumlativeAmount,
+) -> Result<(), CommitError> {
+    // 時間と積算電力量の組を作成する
+    let histrical_kwh = hist
+        .kwh_time_series(unit, requested_at, tz)
+        .ok_or(CommitError::Other("time calculate error"))?;
+
+    store.insert_cumulative(meter_id, &histrical_kwh).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test1() {
+    use crate::influxdb::Lines;
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+    let tz = chrono_tz::Asia::Tokyo;
+    let unit = SM::UnitForCumlativeAmountsPower(Decimal::new(1, 1));
+    let recorded_at = DateTime::parse_from_rfc3339("2025-07-13T12:01:00+09:00")
+        .unwrap()
+        .to_utc();
+    let fixed_time = |day: u32| {
+        SM::Properties::CumlativeAmountsOfPowerAtFixedTime(SM::CumlativeAmountsOfPowerAtFixedTime {
+            time_point: NaiveDate::from_ymd_opt(2025, 7, day)
+                .and_then(|a| a.and_hms_opt(12, 0, 0))
+                .unwrap(),
+            cumlative_amounts_power: 1234,
+        })
+    };
+    let mut lines = Lines::default();
+    let props = [
+        fixed_time(13),
+        // 受信時刻から離れすぎている定時積算電力量は蓄積しない
+        fixed_time(1),
+    ];
+    for prop in props.iter() {
+        let committed =
+            commit_property(&mut lines, 1, &tz, &unit, &recorded_at, &recorded_at, prop)
+                .await
+                .unwrap();
+        assert!(committed);
+    }
+    assert_eq!(
+        lines.0,
+        vec!["cumlative_amount_epower,meter_id=1 kwh=123.4 1752375600000000000"]
+    );
+    // 蓄積しないプロパティ
+    let prop = SM::Properties::Coefficient(SM::Coefficient(1));
+    let committed = commit_property(&mut lines, 1, &tz, &unit, &recorded_at, &recorded_at, &prop)
+        .await
+        .unwrap();
+    assert!(!committed);
+}
+
+#[tokio::test]
+async fn test2() {
+    // ERXUDP の受信から蓄積までを通して確かめる
+    use crate::echonetlite::EchonetliteFrame;
+    use crate::skstack::{SkRxD, parser::parse_rxd};
+    use crate::store::Database;
+    use rust_decimal::Decimal;
+    let tz = chrono_tz::Asia::Tokyo;
+    let unit = SM::UnitForCumlativeAmountsPower(Decimal::new(1, 1));
+    let recorded_at = DateTime::parse_from_rfc3339("2025-07-13T12:01:00+09:00")
+        .unwrap()
+        .to_utc();
+    // Get_Res 0xe7 瞬時電力計測値 = 1368W, 0xe8 瞬時電流計測値 R相 = 13.0A, T相 = 5.0A
+    let rxd = "ERXUDP FE80:0000:0000:0000:1234:5678:9ABC:DEF0 FE80:0000:0000:0000:1234:5678:9ABC:DEF1 0E1A 0E1A 123456789ABCDEF0 1 0018 1081000102880105FF017202E70400000558E80400820032\r\n";
+    let (rest, SkRxD::Erxudp(erxudp)) = parse_rxd(rxd).unwrap() else {
+        panic!("not ERXUDP")
+    };
+    assert_eq!(rest, "");
+    let config = bincode::config::standard()
+        .with_big_endian()
+        .with_fixed_int_encoding();
+    let (frame, len): (EchonetliteFrame, usize) =
+        bincode::borrow_decode_from_slice(&erxudp.data, config).unwrap();
+    assert_eq!(len, erxudp.data.len());
+    assert_eq!(frame.esv, 0x72);
+    assert_eq!(frame.edata.len(), 2);
+
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    let mut tx = db.begin().await.unwrap();
+    for edata in frame.edata.iter() {
+        let prop = SM::Properties::try_from(edata).unwrap();
+        let committed = commit_property(&mut tx, 1, &tz, &unit, &recorded_at, &recorded_at, &prop)
+            .await
+            .unwrap();
+        assert!(committed);
+    }
+    tx.commit().await.unwrap();
+
+    let Database::Sqlite(pool) = &db else {
+        unreachable!()
+    };
+    let (meter_id, at, watt): (i64, DateTime<Utc>, String) =
+        sqlx::query_as("SELECT meter_id, recorded_at, watt FROM instant_epower")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+    assert_eq!((meter_id, at, watt.as_str()), (1, recorded_at, "1368"));
+    let (r, t): (String, Option<String>) = sqlx::query_as("SELECT r, t FROM instant_current")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    assert_eq!((r.as_str(), t.as_deref()), ("13.0", Some("5.0")));
+}
+
+#[tokio::test]
+async fn test3() {
+    // 逆潮流(マイナスの瞬時電力)は符号付きのまま蓄積して読み戻せる
+    use crate::echonetlite::EchonetliteEdata;
+    use crate::store::Database;
+    use crate::units::Watt;
+    use rust_decimal::Decimal;
+    use std::str::FromStr;
+    let recorded_at = DateTime::parse_from_rfc3339("2025-07-13T12:01:00+09:00")
+        .unwrap()
+        .to_utc();
+    // 0xe7 瞬時電力計測値 = -500W
+    let edata = EchonetliteEdata {
+        epc: 0xe7,
+        pdc: 4,
+        edt: &(-500i32).to_be_bytes(),
+    };
+    let SM::Properties::InstantiousPower(epower) = SM::Properties::try_from(&edata).unwrap() else {
+        panic!("not InstantiousPower")
+    };
+    assert!(epower.watts().is_export());
+
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    let mut tx = db.begin().await.unwrap();
+    commit_instant_epower(&mut tx, 1, &recorded_at, &epower)
+        .await
+        .unwrap();
+    tx.commit().await.unwrap();
+    let Database::Sqlite(pool) = &db else {
+        unreachable!()
+    };
+    let watt: String = sqlx::query_scalar("SELECT watt FROM instant_epower")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    assert_eq!(watt, "-500");
+    let watt = Watt(Decimal::from_str(&watt).unwrap());
+    assert_eq!(watt, epower.watts());
+    assert_eq!(watt.magnitude(), 500);
+}
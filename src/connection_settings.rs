@@ -3,7 +3,21 @@
 // SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
 //
 use crate::echonetlite::smart_electric_energy_meter as SM;
+use crate::skstack::authn;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// MACアドレスの最大文字数(16進数, 上位の0は省略されている)
+const MAC_ADDRESS_LENGTH: usize = 16;
+
+/// 論理チャンネル番号の範囲(SKSREG S2)
+const CHANNEL_RANGE: RangeInclusive<u8> = 0x21..=0x3c;
+
+/// ブロードキャスト用の PAN ID
+const BROADCAST_PAN_ID: u16 = 0xffff;
 
 /// スマートメータ接続情報
 #[derive(Serialize, Deserialize, Debug)]
@@ -16,4 +30,232 @@ pub struct ConnectionSettings {
     pub PanId: u16,
     pub Unit: SM::UnitForCumlativeAmountsPower,
     pub Coefficient: SM::Coefficient,
+    /// 積算電力量有効桁数(以前の設定には無い)
+    #[serde(default)]
+    pub NumberOfEffectiveDigits: Option<SM::NumberOfEffectiveDigits>,
+    /// Getプロパティマップ(以前の設定には無い)
+    #[serde(default)]
+    pub GetPropertyMap: Option<Vec<u8>>,
+    /// スマートメーターと通信するシリアルデバイス名(以前の設定には無い)
+    #[serde(default)]
+    pub SerialDevice: Option<String>,
+    /// この設定情報を使い始めた時刻(以前の設定には無い)
+    /// pairing --activate で更新する
+    #[serde(default)]
+    pub ActivatedAt: Option<DateTime<Utc>>,
+}
+
+/// 設定情報(settings.id, 接続情報)の新しさ
+/// 使い始めた時刻が新しいほど新しい, 使い始めた時刻の無い以前の設定情報は id が大きいほど新しい
+pub fn recency(row: &(i64, ConnectionSettings)) -> (Option<DateTime<Utc>>, i64) {
+    let (id, settings) = row;
+    (settings.ActivatedAt, *id)
+}
+
+/// 設定情報(settings.id, 接続情報)の中から使用するスマートメーターを選ぶ
+/// シリアルデバイス毎に最も新しい設定情報(recency)を使う
+/// シリアルデバイス名の無い以前の設定情報は最も新しいもの1つだけを使う
+/// (シリアルデバイス名のある設定情報があれば使わない)
+/// 新しい順に返す
+pub fn active_meters(mut rows: Vec<(i64, ConnectionSettings)>) -> Vec<(i64, ConnectionSettings)> {
+    rows.sort_by_key(|row| std::cmp::Reverse(recency(row)));
+    let has_device = rows.iter().any(|(_, s)| s.SerialDevice.is_some());
+    let mut devices: Vec<Option<String>> = Vec::new();
+    rows.into_iter()
+        .filter(|(_, s)| has_device == s.SerialDevice.is_some())
+        .filter(|(_, s)| {
+            if devices.contains(&s.SerialDevice) {
+                false
+            } else {
+                devices.push(s.SerialDevice.clone());
+                true
+            }
+        })
+        .collect()
+}
+
+impl ConnectionSettings {
+    /// 接続情報を検査する
+    /// 問題があればすべての問題点を返す
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        if let Err(e) = authn::Id::from_str(&self.RouteBId) {
+            problems.push(format!("RouteBId: {e}"));
+        }
+        if let Err(e) = authn::Password::from_str(&self.RouteBPassword) {
+            problems.push(format!("RouteBPassword: {e}"));
+        }
+        if self.MacAddress.len() > MAC_ADDRESS_LENGTH
+            || u64::from_str_radix(&self.MacAddress, 16).is_err()
+        {
+            problems.push(format!(
+                r#"MacAddress "{}" は{}桁までの16進数です"#,
+                self.MacAddress, MAC_ADDRESS_LENGTH
+            ));
+        }
+        if !CHANNEL_RANGE.contains(&self.Channel) {
+            problems.push(format!(
+                "Channel {} は{}から{}の範囲です",
+                self.Channel,
+                CHANNEL_RANGE.start(),
+                CHANNEL_RANGE.end()
+            ));
+        }
+        if self.PanId == BROADCAST_PAN_ID {
+            problems.push(format!("PanId {:04X} はブロードキャスト用です", self.PanId));
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Get可能なプロパティか(Getプロパティマップが分からなければ None)
+    pub fn can_get(&self, epc: u8) -> Option<bool> {
+        self.GetPropertyMap.as_ref().map(|epcs| epcs.contains(&epc))
+    }
+
+    /// 係数を掛けた積算電力量単位
+    pub fn effective_unit(&self) -> SM::UnitForCumlativeAmountsPower {
+        self.Unit.with_coefficient(&self.Coefficient)
+    }
+
+    /// 積算電力量(kWh)が一巡する値(有効桁数が分からなければ None)
+    pub fn wrap_kwh(&self) -> Option<Decimal> {
+        self.NumberOfEffectiveDigits
+            .as_ref()
+            .map(|digits| digits.wrap_kwh(&self.effective_unit()))
+    }
+}
+
+#[cfg(test)]
+fn example() -> ConnectionSettings {
+    ConnectionSettings {
+        RouteBId: "0123456789ABCDEF0123456789ABCDEF".to_string(),
+        RouteBPassword: "PASSWORD1234".to_string(),
+        Channel: 0x3b,
+        MacAddress: "0123456789ABCDEF".to_string(),
+        PanId: 0xabcd,
+        Unit: SM::UnitForCumlativeAmountsPower(Decimal::new(1, 1)),
+        Coefficient: SM::Coefficient(1),
+        NumberOfEffectiveDigits: None,
+        GetPropertyMap: None,
+        SerialDevice: None,
+        ActivatedAt: None,
+    }
+}
+
+#[test]
+fn test1() {
+    assert_eq!(example().validate(), Ok(()));
+}
+
+#[test]
+fn test2() {
+    // すべての問題点を返す
+    let settings = ConnectionSettings {
+        RouteBId: "0123".to_string(),
+        RouteBPassword: "PASSWORD12345".to_string(),
+        Channel: 0x20,
+        MacAddress: "0123456789ABCDEG".to_string(),
+        PanId: 0xffff,
+        ..example()
+    };
+    let problems = settings.validate().unwrap_err();
+    assert_eq!(problems.len(), 5);
+    assert!(problems[0].starts_with("RouteBId"));
+    assert!(problems[1].starts_with("RouteBPassword"));
+    assert!(problems[2].starts_with("MacAddress"));
+    assert!(problems[3].starts_with("Channel"));
+    assert!(problems[4].starts_with("PanId"));
+    // MACアドレスの上位の0は省略されている
+    let settings = ConnectionSettings {
+        MacAddress: "11223344556677".to_string(),
+        ..example()
+    };
+    assert_eq!(settings.validate(), Ok(()));
+    // MACアドレスの桁数が多すぎる
+    let settings = ConnectionSettings {
+        MacAddress: "0123456789ABCDEF0".to_string(),
+        ..example()
+    };
+    assert_eq!(settings.validate().unwrap_err().len(), 1);
+}
+
+#[test]
+fn test3() {
+    // 以前の設定にはGetプロパティマップが無い
+    let json = r#"{"RouteBId":"0123456789ABCDEF0123456789ABCDEF","RouteBPassword":"PASSWORD1234","Channel":59,"MacAddress":"0123456789ABCDEF","PanId":43981,"Unit":"0.1 kwh","Coefficient":1}"#;
+    let settings: ConnectionSettings = serde_json::from_str(json).unwrap();
+    assert_eq!(settings.GetPropertyMap, None);
+    assert_eq!(settings.can_get(0xe3), None);
+    let settings = ConnectionSettings {
+        GetPropertyMap: Some(vec![0x80, 0xe0, 0xe7, 0xe8]),
+        ..example()
+    };
+    assert_eq!(settings.can_get(0xe7), Some(true));
+    assert_eq!(settings.can_get(0xe3), Some(false));
+}
+
+#[test]
+fn test4() {
+    let with_device = |device: &str| ConnectionSettings {
+        SerialDevice: Some(device.to_string()),
+        ..example()
+    };
+    // 以前の設定情報だけなら最も新しいもの
+    let ids = |rows: Vec<(i64, ConnectionSettings)>| {
+        active_meters(rows)
+            .iter()
+            .map(|(id, _)| *id)
+            .collect::<Vec<i64>>()
+    };
+    assert_eq!(ids(vec![(1, example()), (2, example())]), vec![2]);
+    // シリアルデバイス毎に最も新しいもの
+    assert_eq!(
+        ids(vec![
+            (1, example()),
+            (2, with_device("/dev/ttyUSB0")),
+            (3, with_device("/dev/ttyUSB1")),
+            (4, with_device("/dev/ttyUSB0")),
+        ]),
+        vec![4, 3]
+    );
+    assert_eq!(ids(Vec::new()), Vec::<i64>::new());
+}
+
+#[test]
+fn test5() {
+    let activated_at = |device: &str, at: &str| ConnectionSettings {
+        SerialDevice: Some(device.to_string()),
+        ActivatedAt: Some(DateTime::parse_from_rfc3339(at).unwrap().to_utc()),
+        ..example()
+    };
+    let ids = |rows: Vec<(i64, ConnectionSettings)>| {
+        active_meters(rows)
+            .iter()
+            .map(|(id, _)| *id)
+            .collect::<Vec<i64>>()
+    };
+    // 使い始めた時刻が新しいものを使う
+    assert_eq!(
+        ids(vec![
+            (1, activated_at("/dev/ttyUSB0", "2025-09-02T00:00:00Z")),
+            (2, activated_at("/dev/ttyUSB0", "2025-09-01T00:00:00Z")),
+        ]),
+        vec![1]
+    );
+    // 使い始めた時刻の無い以前の設定情報よりも新しい
+    let legacy = ConnectionSettings {
+        SerialDevice: Some("/dev/ttyUSB0".to_string()),
+        ..example()
+    };
+    assert_eq!(
+        ids(vec![
+            (1, activated_at("/dev/ttyUSB0", "2025-09-01T00:00:00Z")),
+            (2, legacy),
+        ]),
+        vec![1]
+    );
 }
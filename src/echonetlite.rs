@@ -1,5 +1,9 @@
 pub mod edata;
 pub use edata::*;
+pub mod eoj;
+pub use eoj::*;
+pub mod esv;
+pub use esv::*;
 pub mod frame;
 pub use frame::*;
 
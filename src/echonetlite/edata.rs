@@ -41,7 +41,27 @@ impl<'a, 'de: 'a, Context> bincode::BorrowDecode<'de, Context> for EchonetliteEd
         let epc: u8 = bincode::BorrowDecode::borrow_decode(decoder)?;
         let pdc: u8 = bincode::BorrowDecode::borrow_decode(decoder)?;
         decoder.claim_bytes_read(pdc as usize)?;
-        let edt = decoder.borrow_reader().take_bytes(pdc as usize)?;
+        // PDCの長さのEDTが残っていなければ途切れたフレーム
+        let edt = decoder
+            .borrow_reader()
+            .take_bytes(pdc as usize)
+            .map_err(|e| match e {
+                bincode::error::DecodeError::UnexpectedEnd { additional } => {
+                    bincode::error::DecodeError::OtherString(format!(
+                        "EPC:0x{:02X} PDC:{} but EDT is {} bytes short",
+                        epc, pdc, additional
+                    ))
+                }
+                e => e,
+            })?;
+        if edt.len() != pdc as usize {
+            return Err(bincode::error::DecodeError::OtherString(format!(
+                "EPC:0x{:02X} PDC:{} but EDT is {} bytes",
+                epc,
+                pdc,
+                edt.len()
+            )));
+        }
         Ok(Self { epc, pdc, edt })
     }
 }
@@ -98,3 +118,28 @@ fn test1() {
 
     assert_eq!(e7, decoded);
 }
+
+#[test]
+fn test2() {
+    let config = bincode::config::standard()
+        .with_big_endian()
+        .with_fixed_int_encoding();
+    // PDCは4バイトだがEDTが2バイトしか残っていない
+    let binary: Vec<u8> = vec![0xe7, 0x04, 0x01, 0x02];
+    let decoded: Result<(EchonetliteEdata, usize), _> =
+        bincode::borrow_decode_from_slice(&binary[..], config);
+    match decoded {
+        Err(bincode::error::DecodeError::OtherString(s)) => {
+            assert_eq!(s, "EPC:0xE7 PDC:4 but EDT is 2 bytes short")
+        }
+        other => panic!("{:?}", other),
+    }
+    // 途切れたフレームも解読しない
+    let binary: Vec<u8> = vec![
+        0x10, 0x81, 0x00, 0x01, 0x02, 0x88, 0x01, 0x05, 0xff, 0x01, 0x72, 0x01, 0xe7, 0x04, 0x00,
+        0x00,
+    ];
+    let decoded: Result<(crate::echonetlite::EchonetliteFrame, usize), _> =
+        bincode::borrow_decode_from_slice(&binary[..], config);
+    assert!(decoded.is_err());
+}
@@ -0,0 +1,73 @@
+// Echonetlite オブジェクト(EOJ)
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use std::fmt;
+
+/// Echonetlite オブジェクト(EOJ)
+/// 電文上はクラスグループコード, クラスコード, インスタンスコードの3バイト
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Eoj {
+    pub class_group: u8, // クラスグループコード
+    pub class: u8,       // クラスコード
+    pub instance: u8,    // インスタンスコード
+}
+
+impl Eoj {
+    /// 0x05FF01 コントローラ(このプログラム)
+    pub const HOME_CONTROLLER: Eoj = Eoj::new(0x05, 0xff, 0x01);
+
+    /// 0x028801 低圧スマート電力量メータ
+    pub const SMART_METER: Eoj = Eoj::new(0x02, 0x88, 0x01);
+
+    pub const fn new(class_group: u8, class: u8, instance: u8) -> Self {
+        Self {
+            class_group,
+            class,
+            instance,
+        }
+    }
+
+    /// インスタンスコードを除いて同じクラスか
+    pub fn is_same_class(&self, other: &Eoj) -> bool {
+        self.class_group == other.class_group && self.class == other.class
+    }
+}
+
+impl From<[u8; 3]> for Eoj {
+    fn from([class_group, class, instance]: [u8; 3]) -> Self {
+        Self::new(class_group, class, instance)
+    }
+}
+
+impl From<Eoj> for [u8; 3] {
+    fn from(eoj: Eoj) -> Self {
+        [eoj.class_group, eoj.class, eoj.instance]
+    }
+}
+
+impl fmt::Display for Eoj {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:02X}{:02X}{:02X}",
+            self.class_group, self.class, self.instance
+        )
+    }
+}
+
+#[test]
+fn test1() {
+    let eoj = Eoj::from([0x02, 0x88, 0x01]);
+    assert_eq!(eoj, Eoj::SMART_METER);
+    assert_eq!(<[u8; 3]>::from(Eoj::HOME_CONTROLLER), [0x05, 0xff, 0x01]);
+    assert_eq!(Eoj::SMART_METER.to_string(), "028801");
+}
+
+#[test]
+fn test2() {
+    // インスタンスコードは比べない
+    assert!(Eoj::new(0x02, 0x88, 0x02).is_same_class(&Eoj::SMART_METER));
+    assert!(!Eoj::HOME_CONTROLLER.is_same_class(&Eoj::SMART_METER));
+    assert!(!Eoj::new(0x02, 0x79, 0x01).is_same_class(&Eoj::SMART_METER));
+}
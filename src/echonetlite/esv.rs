@@ -0,0 +1,126 @@
+// Echonetlite サービス(ESV)
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use std::fmt;
+
+/// Echonetlite サービス(ESV)
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Esv {
+    /// 0x50 プロパティ値書き込み要求不可応答
+    SetISna,
+    /// 0x51 プロパティ値書き込み要求不可応答
+    SetCSna,
+    /// 0x52 プロパティ値読み出し不可応答
+    GetSna,
+    /// 0x53 プロパティ値通知不可応答
+    InfSna,
+    /// 0x5e プロパティ値書き込み・読み出し不可応答
+    SetGetSna,
+    /// 0x60 プロパティ値書き込み要求(応答不要)
+    SetI,
+    /// 0x61 プロパティ値書き込み要求(応答要)
+    SetC,
+    /// 0x62 プロパティ値読み出し要求
+    Get,
+    /// 0x63 プロパティ値通知要求
+    InfReq,
+    /// 0x6e プロパティ値書き込み・読み出し要求
+    SetGet,
+    /// 0x71 プロパティ値書き込み応答
+    SetRes,
+    /// 0x72 プロパティ値読み出し応答
+    GetRes,
+    /// 0x73 プロパティ値通知
+    Inf,
+    /// 0x74 プロパティ値通知(応答要)
+    Infc,
+    /// 0x7a プロパティ値通知応答
+    InfcRes,
+    /// 0x7e プロパティ値書き込み・読み出し応答
+    SetGetRes,
+}
+
+impl TryFrom<u8> for Esv {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x50 => Ok(Esv::SetISna),
+            0x51 => Ok(Esv::SetCSna),
+            0x52 => Ok(Esv::GetSna),
+            0x53 => Ok(Esv::InfSna),
+            0x5e => Ok(Esv::SetGetSna),
+            0x60 => Ok(Esv::SetI),
+            0x61 => Ok(Esv::SetC),
+            0x62 => Ok(Esv::Get),
+            0x63 => Ok(Esv::InfReq),
+            0x6e => Ok(Esv::SetGet),
+            0x71 => Ok(Esv::SetRes),
+            0x72 => Ok(Esv::GetRes),
+            0x73 => Ok(Esv::Inf),
+            0x74 => Ok(Esv::Infc),
+            0x7a => Ok(Esv::InfcRes),
+            0x7e => Ok(Esv::SetGetRes),
+            _ => Err(format!("UNKNOWN ESV:0x{:02X}", value)),
+        }
+    }
+}
+
+impl From<Esv> for u8 {
+    fn from(esv: Esv) -> u8 {
+        match esv {
+            Esv::SetISna => 0x50,
+            Esv::SetCSna => 0x51,
+            Esv::GetSna => 0x52,
+            Esv::InfSna => 0x53,
+            Esv::SetGetSna => 0x5e,
+            Esv::SetI => 0x60,
+            Esv::SetC => 0x61,
+            Esv::Get => 0x62,
+            Esv::InfReq => 0x63,
+            Esv::SetGet => 0x6e,
+            Esv::SetRes => 0x71,
+            Esv::GetRes => 0x72,
+            Esv::Inf => 0x73,
+            Esv::Infc => 0x74,
+            Esv::InfcRes => 0x7a,
+            Esv::SetGetRes => 0x7e,
+        }
+    }
+}
+
+impl fmt::Display for Esv {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Esv::SetISna => "SetI_SNA",
+            Esv::SetCSna => "SetC_SNA",
+            Esv::GetSna => "Get_SNA",
+            Esv::InfSna => "INF_SNA",
+            Esv::SetGetSna => "SetGet_SNA",
+            Esv::SetI => "SetI",
+            Esv::SetC => "SetC",
+            Esv::Get => "Get",
+            Esv::InfReq => "INF_REQ",
+            Esv::SetGet => "SetGet",
+            Esv::SetRes => "Set_Res",
+            Esv::GetRes => "Get_Res",
+            Esv::Inf => "INF",
+            Esv::Infc => "INFC",
+            Esv::InfcRes => "INFC_Res",
+            Esv::SetGetRes => "SetGet_Res",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[test]
+fn test1() {
+    for n in 0x00..=0xff {
+        if let Ok(esv) = Esv::try_from(n) {
+            assert_eq!(u8::from(esv), n);
+        }
+    }
+    assert_eq!(Esv::try_from(0x73), Ok(Esv::Inf));
+    assert!(Esv::try_from(0x00).is_err());
+}
@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: MPL-2.0
 // SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
 //
-use crate::echonetlite::EchonetliteEdata;
+use crate::echonetlite::{EchonetliteEdata, Eoj};
 use bincode;
 use core::result;
 use std::fmt;
@@ -11,8 +11,8 @@ use std::fmt;
 pub struct EchonetliteFrame<'a> {
     pub ehd: u16,
     pub tid: u16,
-    pub seoj: [u8; 3],
-    pub deoj: [u8; 3],
+    pub seoj: Eoj,
+    pub deoj: Eoj,
     pub esv: u8,
     pub opc: u8,
     pub edata: Vec<EchonetliteEdata<'a>>,
@@ -72,8 +72,10 @@ impl<'de, Context> bincode::BorrowDecode<'de, Context> for EchonetliteFrame<'de>
             let tid: u16 = bincode::BorrowDecode::borrow_decode(decoder)?;
             // offset[4:7] 送信元EOJ(3byte)
             let seoj: [u8; 3] = bincode::BorrowDecode::borrow_decode(decoder)?;
+            let seoj = Eoj::from(seoj);
             // offset[7:10] 送信先EOJ(3byte)
             let deoj: [u8; 3] = bincode::BorrowDecode::borrow_decode(decoder)?;
+            let deoj = Eoj::from(deoj);
             // offset[10:11] Echonetliteサービス(1byte)
             let esv: u8 = bincode::BorrowDecode::borrow_decode(decoder)?;
             // offset[11:12] EDATAプロパティ数(1byte)
@@ -107,8 +109,8 @@ impl<'a> bincode::Encode for EchonetliteFrame<'a> {
     ) -> result::Result<(), bincode::error::EncodeError> {
         bincode::Encode::encode(&self.ehd, encoder)?;
         bincode::Encode::encode(&self.tid, encoder)?;
-        bincode::Encode::encode(&self.seoj, encoder)?;
-        bincode::Encode::encode(&self.deoj, encoder)?;
+        bincode::Encode::encode(&<[u8; 3]>::from(self.seoj), encoder)?;
+        bincode::Encode::encode(&<[u8; 3]>::from(self.deoj), encoder)?;
         bincode::Encode::encode(&self.esv, encoder)?;
         bincode::Encode::encode(&self.opc, encoder)?;
         for v in &self.edata {
@@ -130,8 +132,8 @@ impl<'a> Default for EchonetliteFrame<'a> {
         EchonetliteFrame {
             ehd: 0x1081,
             tid: 0,
-            seoj: [0, 0, 0],
-            deoj: [0, 0, 0],
+            seoj: Eoj::default(),
+            deoj: Eoj::default(),
             esv: 0,
             opc: 0,
             edata: vec![],
@@ -144,8 +146,8 @@ fn test1() {
     let frame = EchonetliteFrame {
         ehd: 0x1081,
         tid: 0x1234,
-        seoj: [0x05, 0xff, 0x01],
-        deoj: [0x02, 0x88, 0x01],
+        seoj: Eoj::HOME_CONTROLLER,
+        deoj: Eoj::SMART_METER,
         esv: 0x62,
         opc: 0x01,
         edata: vec![EchonetliteEdata {
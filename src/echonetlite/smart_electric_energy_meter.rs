@@ -3,23 +3,186 @@
 // SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
 //
 use crate::echonetlite::{EchonetliteEdata, superclass};
-use chrono::{NaiveDate, NaiveDateTime};
+use crate::units::{Ampere, KiloWattHour, Watt};
+use chrono::{
+    DateTime, Datelike, Days, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Timelike, Utc,
+};
+use chrono_tz::Tz;
 use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
 use serde::de::{self, Visitor};
+use serde::ser::SerializeStruct;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
 
-#[derive(Clone, Eq, PartialEq, Debug)]
+/// 計測値なし(unsigned long)
+pub const NO_MEASUREMENT_U32: u32 = 0xffff_fffe;
+/// オーバーフロー(unsigned long) 値が不明なので計測値なしと同様に扱う
+pub const OVERFLOW_U32: u32 = 0xffff_ffff;
+/// 計測値なし(signed short) 単相2線式のT相など
+pub const NO_MEASUREMENT_I16: i16 = 0x7ffe;
+/// オーバーフロー(signed short)
+pub const OVERFLOW_I16: i16 = 0x7fff;
+/// 計測値なし(signed long)
+pub const NO_MEASUREMENT_I32: i32 = 0x7fff_fffe;
+/// オーバーフロー(signed long)
+pub const OVERFLOW_I32: i32 = 0x7fff_ffff;
+/// アンダーフロー(signed long)
+pub const UNDERFLOW_I32: i32 = i32::MIN;
+
+/// 計測値なし、オーバーフローを None にする(unsigned long)
+fn measured_u32(value: u32) -> Option<u32> {
+    match value {
+        NO_MEASUREMENT_U32 | OVERFLOW_U32 => None,
+        n => Some(n),
+    }
+}
+
+/// プロパティの名前
+pub fn property_name(epc: u8) -> Option<&'static str> {
+    match epc {
+        0x80 => Some("動作状態"),
+        0x81 => Some("設置場所"),
+        0x82 => Some("規格Version情報"),
+        0x88 => Some("異常発生状態"),
+        0x8a => Some("メーカーコード"),
+        0x97 => Some("現在時刻設定"),
+        0x98 => Some("現在年月日設定"),
+        0x9d => Some("状変アナウンスプロパティマップ"),
+        0x9e => Some("Setプロパティマップ"),
+        0x9f => Some("Getプロパティマップ"),
+        0xd3 => Some("係数"),
+        0xd7 => Some("積算電力量有効桁数"),
+        0xe0 => Some("積算電力量計測値(正方向計測値)"),
+        0xe1 => Some("積算電力量単位(正方向、逆方向計測値)"),
+        0xe2 => Some("積算電力量計測値履歴1(正方向計測値)"),
+        0xe3 => Some("積算電力量計測値(逆方向計測値)"),
+        0xe4 => Some("積算電力量計測値履歴1(逆方向計測値)"),
+        0xe5 => Some("積算履歴収集日1"),
+        0xe7 => Some("瞬時電力計測値"),
+        0xe8 => Some("瞬時電流計測値"),
+        0xea => Some("定時積算電力量計測値(正方向計測値)"),
+        0xeb => Some("定時積算電力量計測値(逆方向計測値)"),
+        0xec => Some("積算電力量計測値履歴2(正方向、逆方向計測値)"),
+        0xed => Some("積算履歴収集日2"),
+        _ => None,
+    }
+}
+
+/// JSONの数値として書き出すDecimal
+struct DecimalNumber<'a>(&'a Decimal);
+
+impl Serialize for DecimalNumber<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let value = self.0.normalize();
+        match value.to_i64() {
+            Some(n) if value.scale() == 0 => serializer.serialize_i64(n),
+            _ => serializer.serialize_f64(value.to_f64().unwrap_or(f64::NAN)),
+        }
+    }
+}
+
+/// 値を1つだけ持つJSONオブジェクトとして書き出す
+fn serialize_single_field<S, T>(
+    name: &'static str,
+    field: &'static str,
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize + ?Sized,
+{
+    let mut state = serializer.serialize_struct(name, 1)?;
+    state.serialize_field(field, value)?;
+    state.end()
+}
+
+/// デコードしたプロパティ
+/// JSONでは {"type":"instant_power","watt":123} のように type で区別する
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Properties {
-    Superclass(superclass::Properties),
+    #[serde(
+        serialize_with = "serialize_coefficient",
+        deserialize_with = "deserialize_coefficient"
+    )]
     Coefficient(Coefficient),
+    #[serde(
+        serialize_with = "serialize_number_of_effective_digits",
+        deserialize_with = "deserialize_number_of_effective_digits"
+    )]
     NumberOfEffectiveDigits(NumberOfEffectiveDigits),
     CumlativeAmountsPower(CumlativeAmountsPower),
+    ReverseCumlativeAmountsPower(ReverseCumlativeAmountsPower),
+    #[serde(
+        serialize_with = "serialize_unit_for_cumlative_amounts_power",
+        deserialize_with = "deserialize_unit_for_cumlative_amounts_power"
+    )]
     UnitForCumlativeAmountsPower(UnitForCumlativeAmountsPower),
     HistoricalCumlativeAmount(HistoricalCumlativeAmount),
+    #[serde(rename = "instant_power")]
     InstantiousPower(InstantiousPower),
+    #[serde(rename = "instant_current")]
     InstantiousCurrent(InstantiousCurrent),
     CumlativeAmountsOfPowerAtFixedTime(CumlativeAmountsOfPowerAtFixedTime),
+    ReverseCumlativeAmountsOfPowerAtFixedTime(ReverseCumlativeAmountsOfPowerAtFixedTime),
+    HistoricalCumlativeAmount2(HistoricalCumlativeAmount2),
+    DayForHistoricalData2(DayForHistoricalData2),
+    /// スーパークラスのプロパティは type をそのまま使う
+    #[serde(untagged)]
+    Superclass(superclass::Properties),
+}
+
+fn serialize_coefficient<S: Serializer>(a: &Coefficient, serializer: S) -> Result<S::Ok, S::Error> {
+    serialize_single_field("Coefficient", "coefficient", &a.0, serializer)
+}
+
+fn serialize_number_of_effective_digits<S: Serializer>(
+    a: &NumberOfEffectiveDigits,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serialize_single_field("NumberOfEffectiveDigits", "digits", &a.0, serializer)
+}
+
+fn serialize_unit_for_cumlative_amounts_power<S: Serializer>(
+    a: &UnitForCumlativeAmountsPower,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serialize_single_field("UnitForCumlativeAmountsPower", "unit", a, serializer)
+}
+
+fn deserialize_coefficient<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Coefficient, D::Error> {
+    #[derive(Deserialize)]
+    struct Fields {
+        coefficient: u8,
+    }
+    Fields::deserialize(deserializer).map(|a| Coefficient(a.coefficient))
+}
+
+fn deserialize_number_of_effective_digits<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<NumberOfEffectiveDigits, D::Error> {
+    #[derive(Deserialize)]
+    struct Fields {
+        digits: u8,
+    }
+    Fields::deserialize(deserializer).map(|a| NumberOfEffectiveDigits(a.digits))
+}
+
+fn deserialize_unit_for_cumlative_amounts_power<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<UnitForCumlativeAmountsPower, D::Error> {
+    #[derive(Deserialize)]
+    struct Fields {
+        unit: UnitForCumlativeAmountsPower,
+    }
+    Fields::deserialize(deserializer).map(|a| a.unit)
 }
 
 impl<'a> Properties {
@@ -29,11 +192,15 @@ impl<'a> Properties {
             Self::Coefficient(a) => format!("{}", a),
             Self::NumberOfEffectiveDigits(a) => format!("{}", a),
             Self::CumlativeAmountsPower(a) => a.show(appendix_unit),
+            Self::ReverseCumlativeAmountsPower(a) => a.show(appendix_unit),
             Self::UnitForCumlativeAmountsPower(a) => format!("{}", a),
             Self::HistoricalCumlativeAmount(a) => a.show(appendix_unit),
             Self::InstantiousPower(a) => format!("{}", a),
             Self::InstantiousCurrent(a) => format!("{}", a),
             Self::CumlativeAmountsOfPowerAtFixedTime(a) => a.show(appendix_unit),
+            Self::ReverseCumlativeAmountsOfPowerAtFixedTime(a) => a.show(appendix_unit),
+            Self::HistoricalCumlativeAmount2(a) => a.show(appendix_unit),
+            Self::DayForHistoricalData2(a) => format!("{}", a),
         }
     }
 }
@@ -50,6 +217,8 @@ impl<'a> TryFrom<&'a EchonetliteEdata<'_>> for Properties {
             Ok(Properties::NumberOfEffectiveDigits(a))
         } else if let Ok(a) = CumlativeAmountsPower::try_from(edata) {
             Ok(Properties::CumlativeAmountsPower(a))
+        } else if let Ok(a) = ReverseCumlativeAmountsPower::try_from(edata) {
+            Ok(Properties::ReverseCumlativeAmountsPower(a))
         } else if let Ok(a) = UnitForCumlativeAmountsPower::try_from(edata) {
             Ok(Properties::UnitForCumlativeAmountsPower(a))
         } else if let Ok(a) = HistoricalCumlativeAmount::try_from(edata) {
@@ -60,6 +229,12 @@ impl<'a> TryFrom<&'a EchonetliteEdata<'_>> for Properties {
             Ok(Properties::InstantiousCurrent(a))
         } else if let Ok(a) = CumlativeAmountsOfPowerAtFixedTime::try_from(edata) {
             Ok(Properties::CumlativeAmountsOfPowerAtFixedTime(a))
+        } else if let Ok(a) = ReverseCumlativeAmountsOfPowerAtFixedTime::try_from(edata) {
+            Ok(Properties::ReverseCumlativeAmountsOfPowerAtFixedTime(a))
+        } else if let Ok(a) = HistoricalCumlativeAmount2::try_from(edata) {
+            Ok(Properties::HistoricalCumlativeAmount2(a))
+        } else if let Ok(a) = DayForHistoricalData2::try_from(edata) {
+            Ok(Properties::DayForHistoricalData2(a))
         } else {
             Err(format!("UNKNOWN EPC:0x{:X} EDT:{:?}", edata.epc, edata.edt))
         }
@@ -103,11 +278,21 @@ impl fmt::Display for Coefficient {
 }
 
 /// 0xd7 積算電力量有効桁数
-#[derive(Clone, Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
 pub struct NumberOfEffectiveDigits(pub u8);
 
 impl NumberOfEffectiveDigits {
     pub const EPC: u8 = 0xd7; // 0xd7 積算電力量有効桁数
+
+    /// 積算電力量計測値が一巡する値(10の有効桁数乗)
+    pub fn modulus(&self) -> u64 {
+        10u64.pow(self.0 as u32)
+    }
+
+    /// 積算電力量(kWh)が一巡する値
+    pub fn wrap_kwh(&self, unit: &UnitForCumlativeAmountsPower) -> Decimal {
+        Decimal::from(self.modulus()) * unit.0
+    }
 }
 
 impl<'a> TryFrom<&'a EchonetliteEdata<'_>> for NumberOfEffectiveDigits {
@@ -134,13 +319,32 @@ pub struct CumlativeAmountsPower(pub u32);
 impl CumlativeAmountsPower {
     pub const EPC: u8 = 0xe0; // 0xe0 積算電力量計測値(正方向計測値)
 
-    pub fn kwh(&self, unit: &UnitForCumlativeAmountsPower) -> Decimal {
-        return Decimal::from(self.0) * unit.0;
+    pub fn kwh(&self, unit: &UnitForCumlativeAmountsPower) -> KiloWattHour {
+        return unit.kwh(self.0);
+    }
+
+    /// 前回の計測値(prev)からの増分
+    /// 計測値は有効桁数で一巡して0に戻るので、減っていたら一巡したとみなす
+    /// (kWhにするには積算電力量単位を掛ける)
+    pub fn wrapped_delta(
+        &self,
+        prev: &CumlativeAmountsPower,
+        digits: &NumberOfEffectiveDigits,
+    ) -> Decimal {
+        let (current, prev) = (self.0 as u64, prev.0 as u64);
+        if current >= prev {
+            Decimal::from(current - prev)
+        } else {
+            Decimal::from(current + digits.modulus() - prev)
+        }
     }
 
     pub fn show(&self, appendix_unit: Option<&UnitForCumlativeAmountsPower>) -> String {
         match appendix_unit {
-            Some(unit) => format!("積算電力量計測値(正方向計測値)={:8} kwh", self.kwh(unit)),
+            Some(unit) => format!(
+                "積算電力量計測値(正方向計測値)={} kwh",
+                unit.format_kwh(self.kwh(unit))
+            ),
             None => format!("積算電力量計測値(正方向計測値)={:8}", self.0),
         }
     }
@@ -163,12 +367,68 @@ impl fmt::Display for CumlativeAmountsPower {
     }
 }
 
+impl Serialize for CumlativeAmountsPower {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_single_field(
+            "CumlativeAmountsPower",
+            "cumlative_amounts_power",
+            &self.0,
+            serializer,
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for CumlativeAmountsPower {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Fields {
+            cumlative_amounts_power: u32,
+        }
+        Fields::deserialize(deserializer).map(|a| Self(a.cumlative_amounts_power))
+    }
+}
+
 /// 0xe1 積算電力量単位(正方向、逆方向計測値)
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct UnitForCumlativeAmountsPower(pub Decimal);
 
 impl UnitForCumlativeAmountsPower {
     pub const EPC: u8 = 0xe1; // 0xe1 積算電力量単位(正方向、逆方向計測値)
+
+    /// 係数を掛けた単位
+    /// 積算電力量(kWh) = 計測値 × 係数 × 単位 なのでこの単位を掛ければよい
+    pub fn with_coefficient(&self, coefficient: &Coefficient) -> Self {
+        Self(self.0 * Decimal::from(coefficient.0))
+    }
+
+    /// 計測値を積算電力量(kWh)にする
+    pub fn kwh(&self, count: u32) -> KiloWattHour {
+        KiloWattHour(Decimal::from(count) * self.0)
+    }
+
+    /// 積算電力量(kWh)を表示する小数点以下の桁数
+    /// 単位の小数点以下の桁数(末尾の0は数えない 例 0.0100 kWh なら 2桁)
+    pub fn fraction_digits(&self) -> u32 {
+        self.0.normalize().scale()
+    }
+
+    /// 積算電力量(kWh)を表示する
+    /// 小数点以下は単位の桁数に揃えて, 計測値の最大値でも桁がずれないように右寄せする
+    pub fn format_kwh(&self, kwh: KiloWattHour) -> String {
+        let digits = self.fraction_digits();
+        let rescaled = |mut v: Decimal| {
+            v.rescale(digits);
+            v
+        };
+        let width = rescaled(self.kwh(u32::MAX).0).to_string().len();
+        format!("{:>width$}", rescaled(kwh.0).to_string())
+    }
 }
 
 impl<'a> TryFrom<&'a EchonetliteEdata<'_>> for UnitForCumlativeAmountsPower {
@@ -242,8 +502,35 @@ impl<'de> Deserialize<'de> for UnitForCumlativeAmountsPower {
     }
 }
 
+/// 定時積算電力量計測値の間隔
+pub const FIXED_TIME_INTERVAL: TimeDelta = TimeDelta::minutes(30);
+
+/// 時刻の古い順に並んだ30分毎の積算電力量(kWh)から30分毎の使用量(kWh)を求める
+/// 使用量の時刻は区間の終わりの時刻
+/// 30分間隔で並んでいない(欠測や重複のある)区間は飛ばす
+/// 積算電力量が減っていたら一巡したとみなして wrap_kwh を足す(wrap_kwh が無ければ飛ばす)
+pub fn consecutive_deltas(
+    readings: &[(DateTime<Utc>, Decimal)],
+    wrap_kwh: Option<Decimal>,
+) -> Vec<(DateTime<Utc>, Decimal)> {
+    readings
+        .windows(2)
+        .filter_map(|pair| {
+            let (prev_at, prev) = pair[0];
+            let (at, current) = pair[1];
+            if at - prev_at != FIXED_TIME_INTERVAL {
+                None
+            } else if current >= prev {
+                Some((at, current - prev))
+            } else {
+                wrap_kwh.map(|wrap| (at, current + wrap - prev))
+            }
+        })
+        .collect()
+}
+
 /// 0xe2 積算電力量計測値履歴1 (正方向計測値)
-#[derive(Clone, Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
 pub struct HistoricalCumlativeAmount {
     pub n_days_ago: u16,
     pub historical: Vec<Option<u32>>,
@@ -252,6 +539,37 @@ pub struct HistoricalCumlativeAmount {
 impl HistoricalCumlativeAmount {
     pub const EPC: u8 = 0xe2; // 0xe2 積算電力量計測値履歴1 (正方向計測値)
 
+    /// 30分毎の時刻と積算電力量の組にする
+    /// 日付は受信時刻ではなく要求を送った時刻(requested_at)を基準にする
+    /// (日付をまたいで応答を受信しても前日の履歴を今日の履歴と取り違えないため)
+    /// 日の区切りはタイムゾーン(tz)の午前０時
+    pub fn kwh_time_series(
+        &self,
+        unit: &UnitForCumlativeAmountsPower,
+        requested_at: &DateTime<Utc>,
+        tz: &Tz,
+    ) -> Option<Vec<(DateTime<Utc>, KiloWattHour)>> {
+        let local = requested_at.with_timezone(tz);
+        // 要求時刻 - n_days_ago 日の午前０時ちょうど
+        let day = tz
+            .with_ymd_and_hms(local.year(), local.month(), local.day(), 0, 0, 0)
+            .single()?
+            .checked_sub_days(Days::new(self.n_days_ago as u64))?;
+        // 30分間隔のTimeDelta
+        let halfhour = TimeDelta::minutes(30);
+        // 午前０時ちょうどから30分毎の時刻列を作成するイテレータ
+        let timeserial = std::iter::successors(Some(day), |v| v.checked_add_signed(halfhour));
+        Some(
+            self.historical
+                .iter()
+                .zip(timeserial)
+                .filter_map(|(opt_val, datetime)| {
+                    opt_val.map(|val| (datetime.to_utc(), unit.kwh(val)))
+                })
+                .collect(),
+        )
+    }
+
     pub fn show(&self, appendix_unit: Option<&UnitForCumlativeAmountsPower>) -> String {
         match appendix_unit {
             Some(unit) => format!(
@@ -259,10 +577,13 @@ impl HistoricalCumlativeAmount {
                 self.n_days_ago,
                 self.historical
                     .iter()
-                    .map(|a: &Option<u32>| a.map_or("NA".to_string(), |n| {
-                        format!("{} kwh", Decimal::from(n) * unit.0)
-                    }))
-                    .map(|s| format!("{:>13}", s))
+                    .map(|a: &Option<u32>| {
+                        let kwh = unit.format_kwh(unit.kwh(a.unwrap_or_default()));
+                        match a {
+                            Some(_) => format!("{kwh} kwh"),
+                            None => format!("{:>width$}", "NA", width = kwh.len() + 4),
+                        }
+                    })
                     .collect::<Vec<String>>()
                     .join(",")
             ),
@@ -294,11 +615,7 @@ impl<'a> TryFrom<&'a EchonetliteEdata<'_>> for HistoricalCumlativeAmount {
                         .map(|n: [u8; 4]| u32::from_be_bytes(n))
                         .map_err(|e| e.to_string())?;
                     //
-                    vs.push(if dword == 0xfffffffe {
-                        None
-                    } else {
-                        Some(dword)
-                    });
+                    vs.push(measured_u32(dword));
                 }
                 Ok(Self {
                     n_days_ago: day,
@@ -316,12 +633,85 @@ impl fmt::Display for HistoricalCumlativeAmount {
     }
 }
 
+/// 0xe3 積算電力量計測値(逆方向計測値)
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ReverseCumlativeAmountsPower(pub u32);
+
+impl ReverseCumlativeAmountsPower {
+    pub const EPC: u8 = 0xe3; // 0xe3 積算電力量計測値(逆方向計測値)
+
+    pub fn kwh(&self, unit: &UnitForCumlativeAmountsPower) -> KiloWattHour {
+        unit.kwh(self.0)
+    }
+
+    pub fn show(&self, appendix_unit: Option<&UnitForCumlativeAmountsPower>) -> String {
+        match appendix_unit {
+            Some(unit) => format!(
+                "積算電力量計測値(逆方向計測値)={} kwh",
+                unit.format_kwh(self.kwh(unit))
+            ),
+            None => format!("積算電力量計測値(逆方向計測値)={:8}", self.0),
+        }
+    }
+}
+
+impl TryFrom<&EchonetliteEdata<'_>> for ReverseCumlativeAmountsPower {
+    type Error = String;
+
+    fn try_from(edata: &EchonetliteEdata) -> Result<Self, Self::Error> {
+        match edata.edt {
+            &[a, b, c, d] if edata.epc == Self::EPC => Ok(Self(u32::from_be_bytes([a, b, c, d]))),
+            _ => Err(format!("BAD EPC:0x{:X} EDT:{:?}", edata.epc, edata.edt)),
+        }
+    }
+}
+
+impl fmt::Display for ReverseCumlativeAmountsPower {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.show(None))
+    }
+}
+
+impl Serialize for ReverseCumlativeAmountsPower {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_single_field(
+            "ReverseCumlativeAmountsPower",
+            "cumlative_amounts_power",
+            &self.0,
+            serializer,
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for ReverseCumlativeAmountsPower {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Fields {
+            cumlative_amounts_power: u32,
+        }
+        Fields::deserialize(deserializer).map(|a| Self(a.cumlative_amounts_power))
+    }
+}
+
 /// 0xe7 瞬時電力計測値
+/// 仕様上の単位は 1 W (signed long)
+/// 係数(0xd3)と積算電力量単位(0xe1)は積算電力量にだけ適用されるので瞬時電力には掛けない
 #[derive(Clone, Eq, PartialEq, Debug)]
-pub struct InstantiousPower(pub Decimal);
+pub struct InstantiousPower(Watt);
 
 impl InstantiousPower {
     pub const EPC: u8 = 0xe7; // 0xe7 瞬時電力計測値
+
+    /// 瞬時電力(W)
+    pub fn watts(&self) -> Watt {
+        self.0
+    }
 }
 
 impl<'a> TryFrom<&'a EchonetliteEdata<'_>> for InstantiousPower {
@@ -330,10 +720,13 @@ impl<'a> TryFrom<&'a EchonetliteEdata<'_>> for InstantiousPower {
     fn try_from(edata: &EchonetliteEdata) -> Result<Self, Self::Error> {
         match edata.edt {
             &[a, b, c, d] if edata.epc == Self::EPC => {
-                Ok(Self(Decimal::new(
-                    i32::from_be_bytes([a, b, c, d]) as i64,
-                    0,
-                ))) // マイナスの値もある
+                match i32::from_be_bytes([a, b, c, d]) {
+                    NO_MEASUREMENT_I32 | OVERFLOW_I32 | UNDERFLOW_I32 => Err(format!(
+                        "NO MEASUREMENT EPC:0x{:X} EDT:{:?}",
+                        edata.epc, edata.edt
+                    )),
+                    w => Ok(Self(Watt(Decimal::new(w as i64, 0)))), // マイナスの値もある
+                }
             }
             _ => Err(format!("BAD EPC:0x{:X} EDT:{:?}", edata.epc, edata.edt)),
         }
@@ -346,11 +739,38 @@ impl fmt::Display for InstantiousPower {
     }
 }
 
+impl Serialize for InstantiousPower {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_single_field(
+            "InstantiousPower",
+            "watt",
+            &DecimalNumber(&self.0.0),
+            serializer,
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for InstantiousPower {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Fields {
+            watt: Watt,
+        }
+        Fields::deserialize(deserializer).map(|a| Self(a.watt))
+    }
+}
+
 /// 0xe8 瞬時電流計測値
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct InstantiousCurrent {
-    pub r: Decimal,
-    pub t: Option<Decimal>,
+    pub r: Ampere,
+    pub t: Option<Ampere>,
 }
 
 impl InstantiousCurrent {
@@ -363,9 +783,18 @@ impl<'a> TryFrom<&'a EchonetliteEdata<'_>> for InstantiousCurrent {
     fn try_from(edata: &EchonetliteEdata) -> Result<Self, Self::Error> {
         match edata.edt {
             &[a, b, c, d] if edata.epc == Self::EPC => {
+                // 0.1 A 単位
+                let ampere = |n: i16| Ampere(Decimal::new(n as i64, 1));
                 let rt = match (i16::from_be_bytes([a, b]), i16::from_be_bytes([c, d])) {
-                    (r, 0x7ffe) => (Decimal::new(r as i64, 1), None), // 単相2線式
-                    (r, t) => (Decimal::new(r as i64, 1), Some(Decimal::new(t as i64, 1))),
+                    (NO_MEASUREMENT_I16 | OVERFLOW_I16, _) => {
+                        return Err(format!(
+                            "NO MEASUREMENT EPC:0x{:X} EDT:{:?}",
+                            edata.epc, edata.edt
+                        ));
+                    }
+                    (r, NO_MEASUREMENT_I16) => (ampere(r), None), // 単相2線式
+                    (r, OVERFLOW_I16) => (ampere(r), None),
+                    (r, t) => (ampere(r), Some(ampere(t))),
                 };
                 Ok(Self { r: rt.0, t: rt.1 })
             }
@@ -383,8 +812,34 @@ impl fmt::Display for InstantiousCurrent {
     }
 }
 
+impl Serialize for InstantiousCurrent {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("InstantiousCurrent", 2)?;
+        state.serialize_field("r", &DecimalNumber(&self.r.0))?;
+        state.serialize_field("t", &self.t.as_ref().map(|t| DecimalNumber(&t.0)))?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for InstantiousCurrent {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Fields {
+            r: Ampere,
+            t: Option<Ampere>,
+        }
+        Fields::deserialize(deserializer).map(|a| Self { r: a.r, t: a.t })
+    }
+}
+
 /// 0xea 定時積算電力量計測値(正方向計測値)
-#[derive(Clone, Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
 pub struct CumlativeAmountsOfPowerAtFixedTime {
     pub time_point: NaiveDateTime,
     pub cumlative_amounts_power: u32,
@@ -396,9 +851,9 @@ impl CumlativeAmountsOfPowerAtFixedTime {
     pub fn show(&self, appendix_unit: Option<&UnitForCumlativeAmountsPower>) -> String {
         match appendix_unit {
             Some(unit) => format!(
-                "定時積算電力量計測値(正方向計測値)={} ({:8} kwh)",
+                "定時積算電力量計測値(正方向計測値)={} ({} kwh)",
                 self.time_point.format("%Y-%m-%d %H:%M:%S").to_string(),
-                Decimal::from(self.cumlative_amounts_power) * unit.0
+                unit.format_kwh(unit.kwh(self.cumlative_amounts_power))
             ),
             None => format!(
                 "定時積算電力量計測値(正方向計測値)={} ({:8})",
@@ -412,34 +867,154 @@ impl CumlativeAmountsOfPowerAtFixedTime {
 impl<'a> TryFrom<&'a EchonetliteEdata<'_>> for CumlativeAmountsOfPowerAtFixedTime {
     type Error = String;
 
+    fn try_from(edata: &EchonetliteEdata) -> Result<Self, Self::Error> {
+        if edata.epc == Self::EPC {
+            let (time_point, cumlative_amounts_power) = parse_at_fixed_time(edata)?;
+            Ok(Self {
+                time_point,
+                cumlative_amounts_power,
+            })
+        } else {
+            Err(format!("BAD EPC:0x{:X} EDT:{:?}", edata.epc, edata.edt))
+        }
+    }
+}
+
+impl fmt::Display for CumlativeAmountsOfPowerAtFixedTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.show(None))
+    }
+}
+
+/// 0xeb 定時積算電力量計測値(逆方向計測値)
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct ReverseCumlativeAmountsOfPowerAtFixedTime {
+    pub time_point: NaiveDateTime,
+    pub cumlative_amounts_power: u32,
+}
+
+impl ReverseCumlativeAmountsOfPowerAtFixedTime {
+    pub const EPC: u8 = 0xeb; // 0xeb 定時積算電力量計測値(逆方向計測値)
+
+    pub fn show(&self, appendix_unit: Option<&UnitForCumlativeAmountsPower>) -> String {
+        match appendix_unit {
+            Some(unit) => format!(
+                "定時積算電力量計測値(逆方向計測値)={} ({} kwh)",
+                self.time_point.format("%Y-%m-%d %H:%M:%S"),
+                unit.format_kwh(unit.kwh(self.cumlative_amounts_power))
+            ),
+            None => format!(
+                "定時積算電力量計測値(逆方向計測値)={} ({:8})",
+                self.time_point.format("%Y-%m-%d %H:%M:%S"),
+                self.cumlative_amounts_power
+            ),
+        }
+    }
+}
+
+impl TryFrom<&EchonetliteEdata<'_>> for ReverseCumlativeAmountsOfPowerAtFixedTime {
+    type Error = String;
+
+    fn try_from(edata: &EchonetliteEdata) -> Result<Self, Self::Error> {
+        if edata.epc == Self::EPC {
+            let (time_point, cumlative_amounts_power) = parse_at_fixed_time(edata)?;
+            Ok(Self {
+                time_point,
+                cumlative_amounts_power,
+            })
+        } else {
+            Err(format!("BAD EPC:0x{:X} EDT:{:?}", edata.epc, edata.edt))
+        }
+    }
+}
+
+impl fmt::Display for ReverseCumlativeAmountsOfPowerAtFixedTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.show(None))
+    }
+}
+
+/// 0xec 積算電力量計測値履歴2 (正方向、逆方向計測値)
+/// time_point から30分ずつ遡った(正方向, 逆方向)の積算電力量
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct HistoricalCumlativeAmount2 {
+    pub time_point: NaiveDateTime,
+    pub historical: Vec<(Option<u32>, Option<u32>)>,
+}
+
+impl HistoricalCumlativeAmount2 {
+    pub const EPC: u8 = 0xec; // 0xec 積算電力量計測値履歴2 (正方向、逆方向計測値)
+
+    /// 30分毎の時刻と(正方向, 逆方向)の積算電力量の組にする
+    pub fn kwh_time_series(
+        &self,
+        unit: &UnitForCumlativeAmountsPower,
+    ) -> Vec<(NaiveDateTime, Option<KiloWattHour>, Option<KiloWattHour>)> {
+        // time_point から30分毎に遡る時刻列を作成するイテレータ
+        let timeserial = std::iter::successors(Some(self.time_point), |v| {
+            v.checked_sub_signed(FIXED_TIME_INTERVAL)
+        });
+        self.historical
+            .iter()
+            .zip(timeserial)
+            .map(|((forward, reverse), datetime)| {
+                (
+                    datetime,
+                    forward.map(|n| unit.kwh(n)),
+                    reverse.map(|n| unit.kwh(n)),
+                )
+            })
+            .collect()
+    }
+
+    pub fn show(&self, appendix_unit: Option<&UnitForCumlativeAmountsPower>) -> String {
+        let value = |a: &Option<u32>| match (a, appendix_unit) {
+            (Some(n), Some(unit)) => format!("{} kwh", unit.kwh(*n)),
+            (Some(n), None) => format!("{}", n),
+            (None, _) => "NA".to_string(),
+        };
+        format!(
+            "積算電力量計測値履歴2 (正方向、逆方向計測値)={}[{}]",
+            self.time_point.format("%Y-%m-%d %H:%M"),
+            self.historical
+                .iter()
+                .map(|(forward, reverse)| format!("({}/{})", value(forward), value(reverse)))
+                .collect::<Vec<String>>()
+                .join(",")
+        )
+    }
+}
+
+impl TryFrom<&EchonetliteEdata<'_>> for HistoricalCumlativeAmount2 {
+    type Error = String;
+
     fn try_from(edata: &EchonetliteEdata) -> Result<Self, Self::Error> {
         match edata.edt {
-            &[
-                year0,                // 年 2bytes
-                year1,                //
-                month,                // 月 bytes
-                day,                  // 日 bytes
-                hour,                 // 時 bytes
-                minute,               // 分 1bytes
-                second,               // 秒 1bytes
-                cumlative_watt_hour0, // 積算電力量 4bytes
-                cumlative_watt_hour1, //
-                cumlative_watt_hour2, //
-                cumlative_watt_hour3, //
-            ] if edata.epc == Self::EPC => {
-                let year = u16::from_be_bytes([year0, year1]);
-                let datetime = NaiveDate::from_ymd_opt(year as i32, month as u32, day as u32)
-                    .and_then(|a| a.and_hms_opt(hour as u32, minute as u32, second as u32))
-                    .ok_or("time calculate error")?;
-                let value = u32::from_be_bytes([
-                    cumlative_watt_hour0,
-                    cumlative_watt_hour1,
-                    cumlative_watt_hour2,
-                    cumlative_watt_hour3,
-                ]);
+            [year0, year1, month, day, hour, minute, count, xs @ ..]
+                if edata.epc == Self::EPC && xs.len() == *count as usize * 8 =>
+            {
+                let time_point = parse_history2_time_point(
+                    edata,
+                    u16::from_be_bytes([*year0, *year1]),
+                    *month,
+                    *day,
+                    *hour,
+                    *minute,
+                )?;
+                let dword = |n: &[u8]| {
+                    n.try_into()
+                        .map(|n: [u8; 4]| measured_u32(u32::from_be_bytes(n)))
+                        .map_err(|e: std::array::TryFromSliceError| e.to_string())
+                };
+                let mut vs = Vec::new();
+                for octuple in xs.chunks_exact(8) {
+                    // 正方向, 逆方向の順に並んでいる
+                    let (forward, reverse) = octuple.split_at(4);
+                    vs.push((dword(forward)?, dword(reverse)?));
+                }
                 Ok(Self {
-                    time_point: datetime,
-                    cumlative_amounts_power: value,
+                    time_point,
+                    historical: vs,
                 })
             }
             _ => Err(format!("BAD EPC:0x{:X} EDT:{:?}", edata.epc, edata.edt)),
@@ -447,8 +1022,505 @@ impl<'a> TryFrom<&'a EchonetliteEdata<'_>> for CumlativeAmountsOfPowerAtFixedTim
     }
 }
 
-impl fmt::Display for CumlativeAmountsOfPowerAtFixedTime {
+impl fmt::Display for HistoricalCumlativeAmount2 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.show(None))
     }
 }
+
+/// 0xed 積算履歴収集日2
+/// 積算電力量計測値履歴2(0xec)で読み出す日時と収集コマ数
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct DayForHistoricalData2 {
+    pub time_point: NaiveDateTime,
+    pub number_of_segments: u8,
+}
+
+impl DayForHistoricalData2 {
+    pub const EPC: u8 = 0xed; // 0xed 積算履歴収集日2
+
+    /// Set要求で送るEDT
+    pub fn edt(&self) -> Vec<u8> {
+        let year = (self.time_point.year() as u16).to_be_bytes();
+        vec![
+            year[0],
+            year[1],
+            self.time_point.month() as u8,
+            self.time_point.day() as u8,
+            self.time_point.hour() as u8,
+            self.time_point.minute() as u8,
+            self.number_of_segments,
+        ]
+    }
+}
+
+impl TryFrom<&EchonetliteEdata<'_>> for DayForHistoricalData2 {
+    type Error = String;
+
+    fn try_from(edata: &EchonetliteEdata) -> Result<Self, Self::Error> {
+        match edata.edt {
+            &[year0, year1, month, day, hour, minute, number_of_segments]
+                if edata.epc == Self::EPC =>
+            {
+                let time_point = parse_history2_time_point(
+                    edata,
+                    u16::from_be_bytes([year0, year1]),
+                    month,
+                    day,
+                    hour,
+                    minute,
+                )?;
+                Ok(Self {
+                    time_point,
+                    number_of_segments,
+                })
+            }
+            _ => Err(format!("BAD EPC:0x{:X} EDT:{:?}", edata.epc, edata.edt)),
+        }
+    }
+}
+
+impl fmt::Display for DayForHistoricalData2 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "積算履歴収集日2={} ({}コマ)",
+            self.time_point.format("%Y-%m-%d %H:%M"),
+            self.number_of_segments
+        )
+    }
+}
+
+/// 積算電力量計測値履歴2(0xec, 0xed共通)の日時を取り出す
+fn parse_history2_time_point(
+    edata: &EchonetliteEdata,
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+) -> Result<NaiveDateTime, String> {
+    NaiveDate::from_ymd_opt(year as i32, month as u32, day as u32)
+        .and_then(|a| a.and_hms_opt(hour as u32, minute as u32, 0))
+        .ok_or(format!(
+            "BAD DATETIME EPC:0x{:X} {:04}-{:02}-{:02} {:02}:{:02}",
+            edata.epc, year, month, day, hour, minute
+        ))
+}
+
+/// 定時積算電力量計測値(0xea, 0xeb共通)の日時と積算電力量を取り出す
+fn parse_at_fixed_time(edata: &EchonetliteEdata) -> Result<(NaiveDateTime, u32), String> {
+    match edata.edt {
+        &[
+            year0,                // 年 2bytes
+            year1,                //
+            month,                // 月 bytes
+            day,                  // 日 bytes
+            hour,                 // 時 bytes
+            minute,               // 分 1bytes
+            second,               // 秒 1bytes
+            cumlative_watt_hour0, // 積算電力量 4bytes
+            cumlative_watt_hour1, //
+            cumlative_watt_hour2, //
+            cumlative_watt_hour3, //
+        ] => {
+            let year = u16::from_be_bytes([year0, year1]);
+            let datetime = NaiveDate::from_ymd_opt(year as i32, month as u32, day as u32)
+                .and_then(|a| a.and_hms_opt(hour as u32, minute as u32, second as u32))
+                .ok_or(format!(
+                    "BAD DATETIME EPC:0x{:X} {:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                    edata.epc, year, month, day, hour, minute, second
+                ))?;
+            let value = measured_u32(u32::from_be_bytes([
+                cumlative_watt_hour0,
+                cumlative_watt_hour1,
+                cumlative_watt_hour2,
+                cumlative_watt_hour3,
+            ]))
+            .ok_or(format!(
+                "NO MEASUREMENT EPC:0x{:X} EDT:{:?}",
+                edata.epc, edata.edt
+            ))?;
+            Ok((datetime, value))
+        }
+        _ => Err(format!("BAD EPC:0x{:X} EDT:{:?}", edata.epc, edata.edt)),
+    }
+}
+
+#[test]
+fn test1() {
+    let edt = [
+        0x07, 0xe9, 0x07, 0x0d, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x04, 0xd2,
+    ];
+    let edata = EchonetliteEdata {
+        epc: 0xeb,
+        pdc: edt.len() as u8,
+        edt: &edt,
+    };
+    let expected = ReverseCumlativeAmountsOfPowerAtFixedTime {
+        time_point: NaiveDate::from_ymd_opt(2025, 7, 13)
+            .and_then(|a| a.and_hms_opt(12, 0, 0))
+            .unwrap(),
+        cumlative_amounts_power: 1234,
+    };
+    assert_eq!(
+        Properties::try_from(&edata),
+        Ok(Properties::ReverseCumlativeAmountsOfPowerAtFixedTime(
+            expected
+        ))
+    );
+}
+
+#[test]
+fn test2() {
+    // 計測値なしとオーバーフローが混ざった履歴
+    let edt = [
+        0x00, 0x01, // 1日前
+        0x00, 0x00, 0x00, 0x64, // 100
+        0xff, 0xff, 0xff, 0xfe, // 計測値なし
+        0x00, 0x00, 0x00, 0x65, // 101
+        0xff, 0xff, 0xff, 0xff, // オーバーフロー
+        0x00, 0x00, 0x00, 0x66, // 102
+    ];
+    let edata = EchonetliteEdata {
+        epc: 0xe2,
+        pdc: edt.len() as u8,
+        edt: &edt,
+    };
+    let hist = HistoricalCumlativeAmount::try_from(&edata).unwrap();
+    assert_eq!(hist.n_days_ago, 1);
+    assert_eq!(
+        hist.historical,
+        vec![Some(100), None, Some(101), None, Some(102)]
+    );
+}
+
+#[test]
+fn test3() {
+    // 23:59:58 JSTに要求して翌日 00:00:05 JSTに受信した今日の履歴は要求した日の履歴
+    let requested_at = DateTime::parse_from_rfc3339("2025-07-13T23:59:58+09:00")
+        .unwrap()
+        .to_utc();
+    let hist = HistoricalCumlativeAmount {
+        n_days_ago: 0,
+        historical: vec![Some(100), None, Some(102)],
+    };
+    let unit = UnitForCumlativeAmountsPower(Decimal::new(1, 1));
+    let expected = vec![
+        (
+            DateTime::parse_from_rfc3339("2025-07-13T00:00:00+09:00")
+                .unwrap()
+                .to_utc(),
+            KiloWattHour(Decimal::new(100, 1)),
+        ),
+        (
+            DateTime::parse_from_rfc3339("2025-07-13T01:00:00+09:00")
+                .unwrap()
+                .to_utc(),
+            KiloWattHour(Decimal::new(102, 1)),
+        ),
+    ];
+    assert_eq!(
+        hist.kwh_time_series(&unit, &requested_at, &chrono_tz::Asia::Tokyo),
+        Some(expected)
+    );
+    // UTCでは要求時刻 2025-07-13T14:59:58Z の日の午前０時が起点になる
+    let utc_series = hist
+        .kwh_time_series(&unit, &requested_at, &chrono_tz::UTC)
+        .unwrap();
+    assert_eq!(
+        utc_series[0].0,
+        DateTime::parse_from_rfc3339("2025-07-13T00:00:00Z")
+            .unwrap()
+            .to_utc()
+    );
+}
+
+#[test]
+fn test4() {
+    // リセット直後のメーターが送ってくる日付が全部0の定時積算電力量
+    let edt = [0u8; 11];
+    let edata = EchonetliteEdata {
+        epc: 0xea,
+        pdc: edt.len() as u8,
+        edt: &edt,
+    };
+    assert_eq!(
+        CumlativeAmountsOfPowerAtFixedTime::try_from(&edata),
+        Err("BAD DATETIME EPC:0xEA 0000-00-00 00:00:00".to_string())
+    );
+    assert!(Properties::try_from(&edata).is_err());
+}
+
+#[test]
+fn test5() {
+    // 有効桁数6桁の計測値は 999999 の次に 0 へ戻る
+    let digits = NumberOfEffectiveDigits(6);
+    assert_eq!(digits.modulus(), 1_000_000);
+    let prev = CumlativeAmountsPower(999_990);
+    assert_eq!(
+        CumlativeAmountsPower(999_995).wrapped_delta(&prev, &digits),
+        Decimal::from(5)
+    );
+    assert_eq!(
+        CumlativeAmountsPower(5).wrapped_delta(&prev, &digits),
+        Decimal::from(15)
+    );
+}
+
+#[test]
+fn test6() {
+    // 係数 2, 単位 0.1 kWh のメーター
+    let unit = UnitForCumlativeAmountsPower(Decimal::new(1, 1)).with_coefficient(&Coefficient(2));
+    assert_eq!(unit, UnitForCumlativeAmountsPower(Decimal::new(2, 1)));
+    assert_eq!(
+        CumlativeAmountsPower(1234).kwh(&unit),
+        KiloWattHour(Decimal::new(2468, 1))
+    );
+    let edt = [
+        0x07, 0xe9, 0x07, 0x0d, 0x0c, 0x1e, 0x00, 0x00, 0x00, 0x04, 0xd2,
+    ];
+    let edata = EchonetliteEdata {
+        epc: 0xea,
+        pdc: edt.len() as u8,
+        edt: &edt,
+    };
+    let Ok(Properties::CumlativeAmountsOfPowerAtFixedTime(a)) = Properties::try_from(&edata) else {
+        panic!("0xea decode failed");
+    };
+    assert!(a.show(Some(&unit)).contains("246.8"));
+}
+
+#[test]
+fn test7() {
+    let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().to_utc();
+    let readings = [
+        (at("2025-07-13T00:00:00+09:00"), Decimal::new(99990, 1)),
+        (at("2025-07-13T00:30:00+09:00"), Decimal::new(99995, 1)),
+        // 一巡した
+        (at("2025-07-13T01:00:00+09:00"), Decimal::new(3, 1)),
+        // 01:30 が欠測
+        (at("2025-07-13T02:00:00+09:00"), Decimal::new(10, 1)),
+        (at("2025-07-13T02:30:00+09:00"), Decimal::new(15, 1)),
+    ];
+    // 有効桁数5桁, 単位 0.1 kWh なら 10000 kWh で一巡する
+    let wrap =
+        NumberOfEffectiveDigits(5).wrap_kwh(&UnitForCumlativeAmountsPower(Decimal::new(1, 1)));
+    assert_eq!(wrap, Decimal::from(10000));
+    assert_eq!(
+        consecutive_deltas(&readings, Some(wrap)),
+        vec![
+            (at("2025-07-13T00:30:00+09:00"), Decimal::new(5, 1)),
+            (at("2025-07-13T01:00:00+09:00"), Decimal::new(8, 1)),
+            (at("2025-07-13T02:30:00+09:00"), Decimal::new(5, 1)),
+        ]
+    );
+    // 有効桁数が分からなければ一巡した区間も飛ばす
+    assert_eq!(consecutive_deltas(&readings, None).len(), 2);
+}
+
+#[test]
+fn test8() {
+    // 2025-07-13 12:00 から遡って2コマ, 2コマ目の逆方向は計測値なし
+    let edt = [
+        0x07, 0xe9, 0x07, 0x0d, 0x0c, 0x00, 0x02, // 年月日時分, 収集コマ数
+        0x00, 0x00, 0x04, 0xd2, 0x00, 0x00, 0x00, 0x0a, // 12:00 正方向, 逆方向
+        0x00, 0x00, 0x04, 0xd0, 0xff, 0xff, 0xff, 0xfe, // 11:30 正方向, 逆方向
+    ];
+    let edata = EchonetliteEdata {
+        epc: 0xec,
+        pdc: edt.len() as u8,
+        edt: &edt,
+    };
+    let time_point = NaiveDate::from_ymd_opt(2025, 7, 13)
+        .and_then(|a| a.and_hms_opt(12, 0, 0))
+        .unwrap();
+    let expected = HistoricalCumlativeAmount2 {
+        time_point,
+        historical: vec![(Some(1234), Some(10)), (Some(1232), None)],
+    };
+    assert_eq!(
+        Properties::try_from(&edata),
+        Ok(Properties::HistoricalCumlativeAmount2(expected.clone()))
+    );
+    let unit = UnitForCumlativeAmountsPower(Decimal::new(1, 1));
+    assert_eq!(
+        expected.kwh_time_series(&unit),
+        vec![
+            (
+                time_point,
+                Some(KiloWattHour(Decimal::new(1234, 1))),
+                Some(KiloWattHour(Decimal::new(10, 1)))
+            ),
+            (
+                time_point - TimeDelta::minutes(30),
+                Some(KiloWattHour(Decimal::new(1232, 1))),
+                None
+            ),
+        ]
+    );
+    // 収集コマ数とデーターの長さが合わない
+    let edata = EchonetliteEdata {
+        epc: 0xec,
+        pdc: (edt.len() - 8) as u8,
+        edt: &edt[..edt.len() - 8],
+    };
+    assert!(HistoricalCumlativeAmount2::try_from(&edata).is_err());
+    // 積算履歴収集日2
+    let day = DayForHistoricalData2 {
+        time_point,
+        number_of_segments: 2,
+    };
+    let edt = day.edt();
+    let edata = EchonetliteEdata {
+        epc: 0xed,
+        pdc: edt.len() as u8,
+        edt: &edt,
+    };
+    assert_eq!(
+        Properties::try_from(&edata),
+        Ok(Properties::DayForHistoricalData2(day))
+    );
+}
+
+#[test]
+fn test9() {
+    let power = |edt: &[u8]| {
+        InstantiousPower::try_from(&EchonetliteEdata {
+            epc: InstantiousPower::EPC,
+            pdc: edt.len() as u8,
+            edt,
+        })
+    };
+    assert_eq!(
+        power(&[0x00, 0x00, 0x06, 0xf7]).map(|a| a.watts()),
+        Ok(Watt(Decimal::from(1783)))
+    );
+    // 逆潮流はマイナス
+    assert_eq!(
+        power(&[0xff, 0xff, 0xff, 0x9c]).map(|a| a.watts()),
+        Ok(Watt(Decimal::from(-100)))
+    );
+    // 計測値なし, オーバーフロー, アンダーフロー
+    assert!(power(&[0x7f, 0xff, 0xff, 0xfe]).is_err());
+    assert!(power(&[0x7f, 0xff, 0xff, 0xff]).is_err());
+    assert!(power(&[0x80, 0x00, 0x00, 0x00]).is_err());
+    // 桁数違い
+    assert!(power(&[0x00, 0x06, 0xf7]).is_err());
+}
+
+#[test]
+fn test10() {
+    use serde_json::json;
+    let power = Properties::InstantiousPower(InstantiousPower(Watt(Decimal::from(1783))));
+    assert_eq!(
+        serde_json::to_value(&power).unwrap(),
+        json!({"type": "instant_power", "watt": 1783})
+    );
+    let current = Properties::InstantiousCurrent(InstantiousCurrent {
+        r: Ampere(Decimal::new(125, 1)),
+        t: None,
+    });
+    assert_eq!(
+        serde_json::to_value(&current).unwrap(),
+        json!({"type": "instant_current", "r": 12.5, "t": null})
+    );
+    let unit =
+        Properties::UnitForCumlativeAmountsPower(UnitForCumlativeAmountsPower(Decimal::new(1, 1)));
+    assert_eq!(
+        serde_json::to_value(&unit).unwrap(),
+        json!({"type": "unit_for_cumlative_amounts_power", "unit": "0.1 kwh"})
+    );
+    let fixed_time =
+        Properties::CumlativeAmountsOfPowerAtFixedTime(CumlativeAmountsOfPowerAtFixedTime {
+            time_point: NaiveDate::from_ymd_opt(2025, 7, 13)
+                .and_then(|a| a.and_hms_opt(12, 0, 0))
+                .unwrap(),
+            cumlative_amounts_power: 1234,
+        });
+    assert_eq!(
+        serde_json::to_value(&fixed_time).unwrap(),
+        json!({
+            "type": "cumlative_amounts_of_power_at_fixed_time",
+            "time_point": "2025-07-13T12:00:00",
+            "cumlative_amounts_power": 1234
+        })
+    );
+    // スーパークラスのプロパティ
+    let time = Properties::Superclass(superclass::Properties::CurrentTimeSetting(
+        superclass::CurrentTimeSetting(chrono::NaiveTime::from_hms_opt(12, 34, 0).unwrap()),
+    ));
+    assert_eq!(
+        serde_json::to_value(&time).unwrap(),
+        json!({"type": "current_time_setting", "time": "12:34:00"})
+    );
+}
+
+#[test]
+fn test11() {
+    // JSONにして戻すと同じになる
+    let props = [
+        Properties::Coefficient(Coefficient(10)),
+        Properties::UnitForCumlativeAmountsPower(UnitForCumlativeAmountsPower(Decimal::new(1, 2))),
+        Properties::InstantiousPower(InstantiousPower(Watt(Decimal::from(-120)))),
+        Properties::InstantiousCurrent(InstantiousCurrent {
+            r: Ampere(Decimal::new(125, 1)),
+            t: Some(Ampere(Decimal::new(17, 1))),
+        }),
+        Properties::HistoricalCumlativeAmount(HistoricalCumlativeAmount {
+            n_days_ago: 1,
+            historical: vec![Some(1234), None],
+        }),
+        Properties::Superclass(superclass::Properties::CurrentDateSetting(
+            superclass::CurrentDateSetting(NaiveDate::from_ymd_opt(2025, 7, 13).unwrap()),
+        )),
+    ];
+    for prop in props.iter() {
+        let json = serde_json::to_string(prop).unwrap();
+        assert_eq!(&serde_json::from_str::<Properties>(&json).unwrap(), prop);
+    }
+    // 知らない type は読めない
+    assert!(serde_json::from_str::<Properties>(r#"{"type":"unknown"}"#).is_err());
+}
+
+#[test]
+fn test12() {
+    // 単位 0.1 kWh
+    let unit = UnitForCumlativeAmountsPower(Decimal::new(1, 1));
+    assert_eq!(unit.fraction_digits(), 1);
+    assert_eq!(
+        CumlativeAmountsPower(12345).show(Some(&unit)),
+        "積算電力量計測値(正方向計測値)=     1234.5 kwh"
+    );
+    // 最大値でも桁がずれない
+    assert_eq!(
+        CumlativeAmountsPower(u32::MAX).show(Some(&unit)),
+        "積算電力量計測値(正方向計測値)=429496729.5 kwh"
+    );
+    // 単位 0.0001 kWh
+    let unit = UnitForCumlativeAmountsPower(Decimal::new(1, 4));
+    assert_eq!(unit.fraction_digits(), 4);
+    assert_eq!(
+        unit.format_kwh(KiloWattHour(Decimal::new(12345678, 4))),
+        "  1234.5678"
+    );
+    assert_eq!(
+        unit.format_kwh(KiloWattHour(Decimal::new(12, 0))),
+        "    12.0000"
+    );
+    // 係数を掛けて末尾に0が付いた単位(0.0001 × 100 = 0.0100)
+    let unit = unit.with_coefficient(&Coefficient(100));
+    assert_eq!(unit.fraction_digits(), 2);
+    assert_eq!(
+        ReverseCumlativeAmountsPower(1234).show(Some(&unit)),
+        "積算電力量計測値(逆方向計測値)=      12.34 kwh"
+    );
+    let hist = HistoricalCumlativeAmount {
+        n_days_ago: 0,
+        historical: vec![Some(1234), None],
+    };
+    assert_eq!(
+        hist.show(Some(&unit)),
+        "積算電力量計測値履歴1 (正方向計測値)= 0日前[      12.34 kwh,             NA]"
+    );
+}
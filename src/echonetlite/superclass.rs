@@ -3,20 +3,34 @@
 // SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
 //
 use crate::echonetlite::EchonetliteEdata;
+use chrono::{Datelike, NaiveDate, NaiveTime, Timelike};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
 
-#[derive(Clone, Eq, PartialEq, Debug)]
+/// デコードしたプロパティ
+/// JSONでは {"type":"current_date_setting","date":"2025-07-13"} のように type で区別する
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Properties {
+    StatusChangeAnnouncementPropertyMap(StatusChangeAnnouncementPropertyMap),
+    SetPropertyMap(SetPropertyMap),
     GetPropertyMap(GetPropertyMap),
     Manufacturer(Manufacturer),
+    CurrentTimeSetting(CurrentTimeSetting),
+    CurrentDateSetting(CurrentDateSetting),
     NotifyInstances(NotifyInstances),
 }
 
 impl<'a> Properties {
     pub fn show(&self) -> String {
         match self {
+            Self::StatusChangeAnnouncementPropertyMap(a) => format!("{}", a),
+            Self::SetPropertyMap(a) => format!("{}", a),
             Self::GetPropertyMap(a) => format!("{}", a),
             Self::Manufacturer(a) => format!("{}", a),
+            Self::CurrentTimeSetting(a) => format!("{}", a),
+            Self::CurrentDateSetting(a) => format!("{}", a),
             Self::NotifyInstances(a) => format!("{}", a),
         }
     }
@@ -26,10 +40,18 @@ impl<'a> TryFrom<&'a EchonetliteEdata<'_>> for Properties {
     type Error = String;
 
     fn try_from(edata: &EchonetliteEdata) -> Result<Self, Self::Error> {
-        if let Ok(a) = GetPropertyMap::try_from(edata) {
+        if let Ok(a) = StatusChangeAnnouncementPropertyMap::try_from(edata) {
+            Ok(Properties::StatusChangeAnnouncementPropertyMap(a))
+        } else if let Ok(a) = SetPropertyMap::try_from(edata) {
+            Ok(Properties::SetPropertyMap(a))
+        } else if let Ok(a) = GetPropertyMap::try_from(edata) {
             Ok(Properties::GetPropertyMap(a))
         } else if let Ok(a) = Manufacturer::try_from(edata) {
             Ok(Properties::Manufacturer(a))
+        } else if let Ok(a) = CurrentTimeSetting::try_from(edata) {
+            Ok(Properties::CurrentTimeSetting(a))
+        } else if let Ok(a) = CurrentDateSetting::try_from(edata) {
+            Ok(Properties::CurrentDateSetting(a))
         } else if let Ok(a) = NotifyInstances::try_from(edata) {
             Ok(Properties::NotifyInstances(a))
         } else {
@@ -47,75 +69,161 @@ impl fmt::Display for Properties {
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub enum SmartElectricEnergyMeter {}
 
+/// プロパティマップを解析する
+/// 先頭はプロパティの個数で、16個未満はEPCの列挙、16個以上は16バイトのビットマップ
+fn decode_property_map(edt: &[u8]) -> Result<Vec<u8>, String> {
+    match edt {
+        [count, props @ ..] if *count < 16 => {
+            // 16個未満はそのまま
+            if props.len() == *count as usize {
+                Ok(props.to_vec())
+            } else {
+                Err(format!("BAD PROPERTY MAP:{:?}", edt))
+            }
+        }
+        [_count, props @ ..] if props.len() == 16 => {
+            // 16個以上は表を参照する
+            let table: [[u8; 8]; 16] = [
+                [0x80, 0x90, 0xa0, 0xb0, 0xc0, 0xd0, 0xe0, 0xf0],
+                [0x81, 0x91, 0xa1, 0xb1, 0xc1, 0xd1, 0xe1, 0xf1],
+                [0x82, 0x92, 0xa2, 0xb2, 0xc2, 0xd2, 0xe2, 0xf2],
+                [0x83, 0x93, 0xa3, 0xb3, 0xc3, 0xd3, 0xe3, 0xf3],
+                [0x84, 0x94, 0xa4, 0xb4, 0xc4, 0xd4, 0xe4, 0xf4],
+                [0x85, 0x95, 0xa5, 0xb5, 0xc5, 0xd5, 0xe5, 0xf5],
+                [0x86, 0x96, 0xa6, 0xb6, 0xc6, 0xd6, 0xe6, 0xf6],
+                [0x87, 0x97, 0xa7, 0xb7, 0xc7, 0xd7, 0xe7, 0xf7],
+                [0x88, 0x98, 0xa8, 0xb8, 0xc8, 0xd8, 0xe8, 0xf8],
+                [0x89, 0x99, 0xa9, 0xb9, 0xc9, 0xd9, 0xe9, 0xf9],
+                [0x8a, 0x9a, 0xaa, 0xba, 0xca, 0xda, 0xea, 0xfa],
+                [0x8b, 0x9b, 0xab, 0xbb, 0xcb, 0xdb, 0xeb, 0xfb],
+                [0x8c, 0x9c, 0xac, 0xbc, 0xcc, 0xdc, 0xec, 0xfc],
+                [0x8d, 0x9d, 0xad, 0xbd, 0xcd, 0xdd, 0xed, 0xfd],
+                [0x8e, 0x9e, 0xae, 0xbe, 0xce, 0xde, 0xee, 0xfe],
+                [0x8f, 0x9f, 0xaf, 0xbf, 0xcf, 0xdf, 0xef, 0xff],
+            ];
+            let mut property_map: Vec<u8> = Vec::new();
+            for (bits, epcs) in props.iter().zip(table.iter()) {
+                for (col, epc) in epcs.iter().enumerate() {
+                    if bits & (1 << col) != 0 {
+                        property_map.push(*epc);
+                    }
+                }
+            }
+            property_map.sort();
+            Ok(property_map)
+        }
+        _ => Err(format!("BAD PROPERTY MAP:{:?}", edt)),
+    }
+}
+
+/// プロパティマップを表示する
+fn show_property_map(f: &mut fmt::Formatter, name: &str, properties: &[u8]) -> fmt::Result {
+    write!(
+        f,
+        "{}プロパティマップ [{}]",
+        name,
+        properties
+            .iter()
+            .map(|x| format!("0x{:02X}", x))
+            .collect::<Vec<String>>()
+            .join(",")
+    )
+}
+
+/// 0x9d 状変アナウンスプロパティマップ
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct StatusChangeAnnouncementPropertyMap {
+    properties: Vec<u8>,
+}
+
+impl StatusChangeAnnouncementPropertyMap {
+    pub const EPC: u8 = 0x9d; // 0x9d 状変アナウンスプロパティマップ
+}
+
+impl TryFrom<&EchonetliteEdata<'_>> for StatusChangeAnnouncementPropertyMap {
+    type Error = String;
+
+    fn try_from(edata: &EchonetliteEdata) -> Result<Self, Self::Error> {
+        if edata.epc == Self::EPC {
+            let properties = decode_property_map(edata.edt)?;
+            Ok(StatusChangeAnnouncementPropertyMap { properties })
+        } else {
+            Err(format!("BAD EPC:0x{:X} EDT:{:?}", edata.epc, edata.edt))
+        }
+    }
+}
+
+impl fmt::Display for StatusChangeAnnouncementPropertyMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        show_property_map(f, "状変アナウンス", &self.properties)
+    }
+}
+
+/// 0x9e Setプロパティマップ
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct SetPropertyMap {
+    properties: Vec<u8>,
+}
+
+impl SetPropertyMap {
+    pub const EPC: u8 = 0x9e; // 0x9e Setプロパティマップ
+}
+
+impl TryFrom<&EchonetliteEdata<'_>> for SetPropertyMap {
+    type Error = String;
+
+    fn try_from(edata: &EchonetliteEdata) -> Result<Self, Self::Error> {
+        if edata.epc == Self::EPC {
+            let properties = decode_property_map(edata.edt)?;
+            Ok(SetPropertyMap { properties })
+        } else {
+            Err(format!("BAD EPC:0x{:X} EDT:{:?}", edata.epc, edata.edt))
+        }
+    }
+}
+
+impl fmt::Display for SetPropertyMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        show_property_map(f, "Set", &self.properties)
+    }
+}
+
 /// 0x9f Getプロパティマップ
-#[derive(Clone, Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
 pub struct GetPropertyMap {
     properties: Vec<u8>,
 }
 
 impl GetPropertyMap {
     pub const EPC: u8 = 0x9f; // 0x9f Getプロパティマップ
+
+    /// Get可能なEPCの一覧
+    pub fn epcs(&self) -> &[u8] {
+        &self.properties
+    }
+
+    /// Get可能なEPCか
+    pub fn contains(&self, epc: u8) -> bool {
+        self.properties.contains(&epc)
+    }
 }
 
 impl<'a> TryFrom<&'a EchonetliteEdata<'_>> for GetPropertyMap {
     type Error = String;
 
     fn try_from(edata: &EchonetliteEdata) -> Result<Self, Self::Error> {
-        match edata.edt {
-            [count, props @ ..] if edata.epc == Self::EPC => {
-                let mut get_property_map: Vec<u8> = Vec::with_capacity(*count as usize);
-                if *count < 16 {
-                    // 16個未満はそのまま
-                    get_property_map.copy_from_slice(props);
-                } else {
-                    // 16個以上は表を参照する
-                    let table: [[u8; 8]; 16] = [
-                        [0x80, 0x90, 0xa0, 0xb0, 0xc0, 0xd0, 0xe0, 0xf0],
-                        [0x81, 0x91, 0xa1, 0xb1, 0xc1, 0xd1, 0xe1, 0xf1],
-                        [0x82, 0x92, 0xa2, 0xb2, 0xc2, 0xd2, 0xe2, 0xf2],
-                        [0x83, 0x93, 0xa3, 0xb3, 0xc3, 0xd3, 0xe3, 0xf3],
-                        [0x84, 0x94, 0xa4, 0xb4, 0xc4, 0xd4, 0xe4, 0xf4],
-                        [0x85, 0x95, 0xa5, 0xb5, 0xc5, 0xd5, 0xe5, 0xf5],
-                        [0x86, 0x96, 0xa6, 0xb6, 0xc6, 0xd6, 0xe6, 0xf6],
-                        [0x87, 0x97, 0xa7, 0xb7, 0xc7, 0xd7, 0xe7, 0xf7],
-                        [0x88, 0x98, 0xa8, 0xb8, 0xc8, 0xd8, 0xe8, 0xf8],
-                        [0x89, 0x99, 0xa9, 0xb9, 0xc9, 0xd9, 0xe9, 0xf9],
-                        [0x8a, 0x9a, 0xaa, 0xba, 0xca, 0xda, 0xea, 0xfa],
-                        [0x8b, 0x9b, 0xab, 0xbb, 0xcb, 0xdb, 0xeb, 0xfb],
-                        [0x8c, 0x9c, 0xac, 0xbc, 0xcc, 0xdc, 0xec, 0xfc],
-                        [0x8d, 0x9d, 0xad, 0xbd, 0xcd, 0xdd, 0xed, 0xfd],
-                        [0x8e, 0x9e, 0xae, 0xbe, 0xce, 0xde, 0xee, 0xfe],
-                        [0x8f, 0x9f, 0xaf, 0xbf, 0xcf, 0xdf, 0xef, 0xff],
-                    ];
-                    for row in 0..16 {
-                        for col in 0..8 {
-                            if props[row] & (1 << col) != 0 {
-                                get_property_map.push(table[row][col]);
-                            }
-                        }
-                    }
-                    get_property_map.sort();
-                }
-                Ok(GetPropertyMap {
-                    properties: get_property_map,
-                })
-            }
-            _ => Err(format!("BAD EPC:0x{:X} EDT:{:?}", edata.epc, edata.edt)),
+        if edata.epc == Self::EPC {
+            let properties = decode_property_map(edata.edt)?;
+            Ok(GetPropertyMap { properties })
+        } else {
+            Err(format!("BAD EPC:0x{:X} EDT:{:?}", edata.epc, edata.edt))
         }
     }
 }
 
 impl fmt::Display for GetPropertyMap {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Getプロパティマップ [{}]",
-            self.properties
-                .iter()
-                .map(|x| format!("0x{:02X}", x))
-                .collect::<Vec<String>>()
-                .join(",")
-        )
+        show_property_map(f, "Get", &self.properties)
     }
 }
 
@@ -150,8 +258,156 @@ impl fmt::Display for Manufacturer {
     }
 }
 
-/// 0xd5 インスタンスリスト通知
+impl Serialize for Manufacturer {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Manufacturer", 1)?;
+        state.serialize_field("manufacturer", &self.0)?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Manufacturer {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Fields {
+            manufacturer: String,
+        }
+        Fields::deserialize(deserializer).map(|a| Self(a.manufacturer))
+    }
+}
+
+/// 0x97 現在時刻設定
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct CurrentTimeSetting(pub NaiveTime);
+
+impl CurrentTimeSetting {
+    pub const EPC: u8 = 0x97; // 0x97 現在時刻設定
+
+    /// 書き込み要求(SetC)のEDT 時, 分
+    pub fn edt(&self) -> [u8; 2] {
+        [self.0.hour() as u8, self.0.minute() as u8]
+    }
+}
+
+impl TryFrom<&EchonetliteEdata<'_>> for CurrentTimeSetting {
+    type Error = String;
+
+    fn try_from(edata: &EchonetliteEdata) -> Result<Self, Self::Error> {
+        match edata.edt {
+            &[hour, minute] if edata.epc == Self::EPC => {
+                NaiveTime::from_hms_opt(hour as u32, minute as u32, 0)
+                    .map(CurrentTimeSetting)
+                    .ok_or(format!(
+                        "BAD TIME EPC:0x{:X} {:02}:{:02}",
+                        edata.epc, hour, minute
+                    ))
+            }
+            _ => Err(format!("BAD EPC:0x{:X} EDT:{:?}", edata.epc, edata.edt)),
+        }
+    }
+}
+
+impl fmt::Display for CurrentTimeSetting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "現在時刻設定={}", self.0.format("%H:%M"))
+    }
+}
+
+impl Serialize for CurrentTimeSetting {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("CurrentTimeSetting", 1)?;
+        state.serialize_field("time", &self.0)?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for CurrentTimeSetting {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Fields {
+            time: NaiveTime,
+        }
+        Fields::deserialize(deserializer).map(|a| Self(a.time))
+    }
+}
+
+/// 0x98 現在年月日設定
 #[derive(Clone, Eq, PartialEq, Debug)]
+pub struct CurrentDateSetting(pub NaiveDate);
+
+impl CurrentDateSetting {
+    pub const EPC: u8 = 0x98; // 0x98 現在年月日設定
+
+    /// 書き込み要求(SetC)のEDT 年(2バイト), 月, 日
+    pub fn edt(&self) -> [u8; 4] {
+        let year = (self.0.year() as u16).to_be_bytes();
+        [year[0], year[1], self.0.month() as u8, self.0.day() as u8]
+    }
+}
+
+impl TryFrom<&EchonetliteEdata<'_>> for CurrentDateSetting {
+    type Error = String;
+
+    fn try_from(edata: &EchonetliteEdata) -> Result<Self, Self::Error> {
+        match edata.edt {
+            &[year0, year1, month, day] if edata.epc == Self::EPC => {
+                let year = u16::from_be_bytes([year0, year1]);
+                NaiveDate::from_ymd_opt(year as i32, month as u32, day as u32)
+                    .map(CurrentDateSetting)
+                    .ok_or(format!(
+                        "BAD DATE EPC:0x{:X} {:04}-{:02}-{:02}",
+                        edata.epc, year, month, day
+                    ))
+            }
+            _ => Err(format!("BAD EPC:0x{:X} EDT:{:?}", edata.epc, edata.edt)),
+        }
+    }
+}
+
+impl fmt::Display for CurrentDateSetting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "現在年月日設定={}", self.0.format("%Y-%m-%d"))
+    }
+}
+
+impl Serialize for CurrentDateSetting {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("CurrentDateSetting", 1)?;
+        state.serialize_field("date", &self.0)?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for CurrentDateSetting {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Fields {
+            date: NaiveDate,
+        }
+        Fields::deserialize(deserializer).map(|a| Self(a.date))
+    }
+}
+
+/// 0xd5 インスタンスリスト通知
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
 pub struct NotifyInstances {
     count: u8,
     instances: Vec<[u8; 3]>,
@@ -159,6 +415,16 @@ pub struct NotifyInstances {
 
 impl NotifyInstances {
     pub const EPC: u8 = 0xd5; // 0xd5 インスタンスリスト通知
+
+    /// インスタンス総数
+    pub fn count(&self) -> u8 {
+        self.count
+    }
+
+    /// インスタンスリスト(EOJ)
+    pub fn instances(&self) -> &[[u8; 3]] {
+        &self.instances
+    }
 }
 
 impl<'a> TryFrom<&'a EchonetliteEdata<'_>> for NotifyInstances {
@@ -203,3 +469,121 @@ impl fmt::Display for NotifyInstances {
         )
     }
 }
+
+#[test]
+fn test1() {
+    let edt = [0x03, 0x80, 0x81, 0xe5];
+    let edata = EchonetliteEdata {
+        epc: 0x9e,
+        pdc: edt.len() as u8,
+        edt: &edt,
+    };
+    assert_eq!(
+        Properties::try_from(&edata),
+        Ok(Properties::SetPropertyMap(SetPropertyMap {
+            properties: vec![0x80, 0x81, 0xe5]
+        }))
+    );
+}
+
+#[test]
+fn test2() {
+    // 16個以上はビットマップ
+    let mut edt = [0u8; 17];
+    edt[0] = 19;
+    edt[1] = 0b0000_0011; // 0x80, 0x90
+    edt[2] = 0b0100_0011; // 0x81, 0x91, 0xe1
+    edt[8] = 0b1100_0000; // 0xe7, 0xf7
+    edt[14] = 0b1111_1111; // 0x8d, 0x9d, 0xad, 0xbd, 0xcd, 0xdd, 0xed, 0xfd
+    edt[15] = 0b0000_0011; // 0x8e, 0x9e
+    edt[16] = 0b0000_0011; // 0x8f, 0x9f
+    let edata = EchonetliteEdata {
+        epc: 0x9d,
+        pdc: edt.len() as u8,
+        edt: &edt,
+    };
+    assert_eq!(
+        Properties::try_from(&edata),
+        Ok(Properties::StatusChangeAnnouncementPropertyMap(
+            StatusChangeAnnouncementPropertyMap {
+                properties: vec![
+                    0x80, 0x81, 0x8d, 0x8e, 0x8f, 0x90, 0x91, 0x9d, 0x9e, 0x9f, 0xad, 0xbd, 0xcd,
+                    0xdd, 0xe1, 0xe7, 0xed, 0xf7, 0xfd
+                ]
+            }
+        ))
+    );
+}
+
+#[test]
+fn test3() {
+    let date = EchonetliteEdata {
+        epc: 0x98,
+        pdc: 4,
+        edt: &[0x07, 0xe9, 0x07, 0x0d],
+    };
+    let time = EchonetliteEdata {
+        epc: 0x97,
+        pdc: 2,
+        edt: &[0x0c, 0x22],
+    };
+    assert_eq!(
+        Properties::try_from(&date),
+        Ok(Properties::CurrentDateSetting(CurrentDateSetting(
+            NaiveDate::from_ymd_opt(2025, 7, 13).unwrap()
+        )))
+    );
+    assert_eq!(
+        Properties::try_from(&time),
+        Ok(Properties::CurrentTimeSetting(CurrentTimeSetting(
+            NaiveTime::from_hms_opt(12, 34, 0).unwrap()
+        )))
+    );
+}
+
+#[test]
+fn test4() {
+    let date = CurrentDateSetting(NaiveDate::from_ymd_opt(2025, 7, 13).unwrap());
+    assert_eq!(
+        serde_json::to_value(Properties::CurrentDateSetting(date)).unwrap(),
+        serde_json::json!({"type": "current_date_setting", "date": "2025-07-13"})
+    );
+    let map = GetPropertyMap {
+        properties: vec![0x80, 0xe7],
+    };
+    assert_eq!(
+        serde_json::to_value(Properties::GetPropertyMap(map)).unwrap(),
+        serde_json::json!({"type": "get_property_map", "properties": [0x80, 0xe7]})
+    );
+}
+
+#[test]
+fn test5() {
+    let date = CurrentDateSetting(NaiveDate::from_ymd_opt(2025, 7, 13).unwrap());
+    let time = CurrentTimeSetting(NaiveTime::from_hms_opt(12, 34, 56).unwrap());
+    assert_eq!(date.edt(), [0x07, 0xe9, 0x07, 0x0d]);
+    // 秒は書き込まない
+    assert_eq!(time.edt(), [0x0c, 0x22]);
+    // 読み出したEDTと同じになる
+    let edt = date.edt();
+    let edata = EchonetliteEdata {
+        epc: CurrentDateSetting::EPC,
+        pdc: edt.len() as u8,
+        edt: &edt,
+    };
+    assert_eq!(CurrentDateSetting::try_from(&edata), Ok(date));
+}
+
+#[test]
+fn test6() {
+    let edt = [0x04, 0x80, 0x9f, 0xe0, 0xe7];
+    let edata = EchonetliteEdata {
+        epc: 0x9f,
+        pdc: edt.len() as u8,
+        edt: &edt,
+    };
+    let map = GetPropertyMap::try_from(&edata).unwrap();
+    assert!(map.contains(0xe0));
+    assert!(!map.contains(0xe3));
+    assert_eq!(map.epcs(), &[0x80, 0x9f, 0xe0, 0xe7]);
+}
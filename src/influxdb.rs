@@ -0,0 +1,362 @@
+// InfluxDB line protocol で測定値を書き出す
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use crate::store::MeasurementStore;
+use crate::units::{Ampere, KiloWattHour, Watt};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// 書き出し間隔の既定値
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// 1回のHTTP要求で送る最大行数
+const MAX_BATCH_LINES: usize = 5000;
+
+/// 書き出せずに溜めておく最大行数(超えたら古い行から捨てる)
+const MAX_BUFFERED_LINES: usize = 100_000;
+
+/// HTTP要求のタイムアウト
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 書き出し先の設定
+#[derive(Debug, Clone)]
+pub struct InfluxDbConfig {
+    /// 書き込みURL(例 http://localhost:8086/api/v2/write?org=home&bucket=uchinopower)
+    pub url: String,
+    /// APIトークン
+    pub token: Option<String>,
+    /// 書き出し間隔
+    pub flush_interval: Duration,
+}
+
+/// 測定名, タグ, フィールドキーの ',' ' ' '=' をエスケープする
+fn escape_key(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, ',' | ' ' | '=' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// line protocol の1行を作る
+/// フィールド値は浮動小数点数, タイムスタンプはナノ秒
+pub fn line(
+    measurement: &str,
+    tags: &[(&str, String)],
+    fields: &[(&str, Decimal)],
+    timestamp: &DateTime<Utc>,
+) -> Option<String> {
+    if fields.is_empty() {
+        return None;
+    }
+    let series = tags.iter().fold(escape_key(measurement), |acc, (k, v)| {
+        format!("{},{}={}", acc, escape_key(k), escape_key(v))
+    });
+    let fields = fields
+        .iter()
+        .map(|(k, v)| format!("{}={}", escape_key(k), v.normalize()))
+        .collect::<Vec<String>>()
+        .join(",");
+    let nanos = timestamp.timestamp_nanos_opt()?;
+    Some(format!("{} {} {}", series, fields, nanos))
+}
+
+/// line protocol の行を溜める
+/// データーベースと同じ測定名(テーブル名)とフィールド名(カラム名)にする
+/// スマートメーター(settings.id)は meter_id タグにする
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Lines(pub Vec<String>);
+
+impl Lines {
+    fn push(
+        &mut self,
+        measurement: &str,
+        meter_id: i64,
+        fields: &[(&str, Decimal)],
+        at: &DateTime<Utc>,
+    ) {
+        match line(
+            measurement,
+            &[("meter_id", meter_id.to_string())],
+            fields,
+            at,
+        ) {
+            Some(s) => self.0.push(s),
+            None => tracing::warn!("{measurement} at {at} cannot be written as line protocol"),
+        }
+    }
+}
+
+impl MeasurementStore for Lines {
+    async fn insert_instant_power(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        watt: &Watt,
+    ) -> Result<(), sqlx::Error> {
+        self.push("instant_epower", meter_id, &[("watt", watt.0)], recorded_at);
+        Ok(())
+    }
+
+    async fn insert_current(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        r: &Ampere,
+        t: &Option<Ampere>,
+    ) -> Result<(), sqlx::Error> {
+        let mut fields = vec![("r", r.0)];
+        if let Some(t) = t {
+            fields.push(("t", t.0));
+        }
+        self.push("instant_current", meter_id, &fields, recorded_at);
+        Ok(())
+    }
+
+    async fn insert_cumulative(
+        &mut self,
+        meter_id: i64,
+        values: &[(DateTime<Utc>, KiloWattHour)],
+    ) -> Result<(), sqlx::Error> {
+        for (recorded_at, kwh) in values {
+            self.push(
+                "cumlative_amount_epower",
+                meter_id,
+                &[("kwh", kwh.0)],
+                recorded_at,
+            );
+        }
+        Ok(())
+    }
+
+    async fn insert_reverse_cumulative(
+        &mut self,
+        meter_id: i64,
+        values: &[(DateTime<Utc>, KiloWattHour)],
+    ) -> Result<(), sqlx::Error> {
+        for (recorded_at, kwh) in values {
+            self.push(
+                "reverse_cumlative_amount_epower",
+                meter_id,
+                &[("kwh", kwh.0)],
+                recorded_at,
+            );
+        }
+        Ok(())
+    }
+
+    async fn insert_link_quality(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        lqi: u8,
+    ) -> Result<(), sqlx::Error> {
+        self.push(
+            "link_quality",
+            meter_id,
+            &[("lqi", Decimal::from(lqi))],
+            recorded_at,
+        );
+        Ok(())
+    }
+}
+
+/// 書き出しタスクへの送信口
+static EXPORTER: OnceLock<mpsc::UnboundedSender<Lines>> = OnceLock::new();
+
+/// 書き出しが有効か
+pub fn is_enabled() -> bool {
+    EXPORTER.get().is_some()
+}
+
+/// 書き出しタスクに渡す
+/// 書き出しが有効でなければ何もしない
+pub fn export(lines: Lines) {
+    if lines.0.is_empty() {
+        return;
+    }
+    if let Some(tx) = EXPORTER.get()
+        && tx.send(lines).is_err()
+    {
+        tracing::warn!("influxdb exporter is not running");
+    }
+}
+
+/// 書き出せなかった行を溜めておいて再送するか
+/// サーバーの異常(5xx)と混雑(429)は再送する
+/// それ以外(不正な行やトークンなどの 4xx)は何度送っても受け付けられないので捨てる
+fn should_retry(status: reqwest::StatusCode) -> bool {
+    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+/// 溜めた行を書き出す
+/// 送信できなかったときと再送する応答(should_retry)のときは行を溜めたままにしてエラーを返す
+async fn flush(
+    client: &reqwest::Client,
+    config: &InfluxDbConfig,
+    buffer: &mut VecDeque<String>,
+) -> reqwest::Result<()> {
+    while !buffer.is_empty() {
+        let n = buffer.len().min(MAX_BATCH_LINES);
+        let body = buffer
+            .range(..n)
+            .cloned()
+            .collect::<Vec<String>>()
+            .join("\n");
+        let mut request = client.post(&config.url).body(body);
+        if let Some(token) = config.token.as_ref() {
+            request = request.header(reqwest::header::AUTHORIZATION, format!("Token {token}"));
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            buffer.drain(..n);
+            tracing::debug!("{n} lines written to influxdb");
+        } else if should_retry(status) {
+            return response.error_for_status().map(|_| ());
+        } else {
+            let body = response.text().await.unwrap_or_default();
+            buffer.drain(..n);
+            tracing::error!("influxdb rejected {n} lines, status: {status}, response: {body}");
+        }
+    }
+    Ok(())
+}
+
+/// 書き出しタスクを開始する
+/// 書き出しに失敗しても行を溜めておいて次の機会に書き出す(受け付けられなかった行は捨てる)
+pub fn start(config: InfluxDbConfig) -> reqwest::Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+    let (tx, mut rx) = mpsc::unbounded_channel::<Lines>();
+    if EXPORTER.set(tx).is_err() {
+        tracing::warn!("influxdb exporter is already running");
+        return Ok(());
+    }
+    tracing::info!(
+        "influxdb exporter started, url: {}, flush interval: {:?}",
+        config.url,
+        config.flush_interval
+    );
+    tokio::spawn(async move {
+        let mut buffer = VecDeque::<String>::new();
+        let mut interval = tokio::time::interval(config.flush_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                received = rx.recv() => {
+                    let Some(Lines(lines)) = received else {
+                        break;
+                    };
+                    buffer.extend(lines);
+                    if buffer.len() > MAX_BUFFERED_LINES {
+                        let dropped = buffer.len() - MAX_BUFFERED_LINES;
+                        buffer.drain(..dropped);
+                        tracing::warn!("influxdb buffer is full, {dropped} oldest lines dropped");
+                    }
+                    if buffer.len() < MAX_BATCH_LINES {
+                        continue;
+                    }
+                }
+                _ = interval.tick() => {}
+            }
+            if let Err(e) = flush(&client, &config, &mut buffer).await {
+                tracing::warn!(
+                    "influxdb write failed, {} lines kept for retry, reason: {e}",
+                    buffer.len()
+                );
+            }
+        }
+    });
+    Ok(())
+}
+
+#[test]
+fn test1() {
+    use rust_decimal::dec;
+    let at = DateTime::parse_from_rfc3339("2025-06-01T00:30:00Z")
+        .unwrap()
+        .with_timezone(&Utc);
+    assert_eq!(
+        line("instant_epower", &[], &[("watt", dec!(1234))], &at),
+        Some("instant_epower watt=1234 1748737800000000000".to_string())
+    );
+    assert_eq!(
+        line(
+            "instant_current",
+            &[],
+            &[("r", dec!(12.50)), ("t", dec!(3.0))],
+            &at
+        ),
+        Some("instant_current r=12.5,t=3 1748737800000000000".to_string())
+    );
+    // エスケープ
+    assert_eq!(
+        line(
+            "a b,c",
+            &[("m=1", "x y".to_string())],
+            &[("x=y", dec!(-0.1))],
+            &at
+        ),
+        Some(r"a\ b\,c,m\=1=x\ y x\=y=-0.1 1748737800000000000".to_string())
+    );
+    // フィールドがない
+    assert_eq!(line("instant_epower", &[], &[], &at), None);
+}
+
+#[tokio::test]
+async fn test2() {
+    use rust_decimal::dec;
+    let at = DateTime::parse_from_rfc3339("2025-06-01T00:30:00Z")
+        .unwrap()
+        .with_timezone(&Utc);
+    let mut lines = Lines::default();
+    lines
+        .insert_current(1, &at, &Ampere(dec!(10.0)), &None)
+        .await
+        .unwrap();
+    lines
+        .insert_cumulative(
+            1,
+            &[
+                (at, KiloWattHour(dec!(100.5))),
+                (at, KiloWattHour(dec!(101))),
+            ],
+        )
+        .await
+        .unwrap();
+    lines
+        .insert_reverse_cumulative(2, &[(at, KiloWattHour(dec!(2)))])
+        .await
+        .unwrap();
+    assert_eq!(
+        lines.0,
+        vec![
+            "instant_current,meter_id=1 r=10 1748737800000000000",
+            "cumlative_amount_epower,meter_id=1 kwh=100.5 1748737800000000000",
+            "cumlative_amount_epower,meter_id=1 kwh=101 1748737800000000000",
+            "reverse_cumlative_amount_epower,meter_id=2 kwh=2 1748737800000000000",
+        ]
+    );
+}
+
+#[test]
+fn test3() {
+    use reqwest::StatusCode;
+    assert!(should_retry(StatusCode::INTERNAL_SERVER_ERROR));
+    assert!(should_retry(StatusCode::SERVICE_UNAVAILABLE));
+    assert!(should_retry(StatusCode::TOO_MANY_REQUESTS));
+    // 不正な行, トークン, 大きすぎる要求は捨てる
+    assert!(!should_retry(StatusCode::BAD_REQUEST));
+    assert!(!should_retry(StatusCode::UNAUTHORIZED));
+    assert!(!should_retry(StatusCode::PAYLOAD_TOO_LARGE));
+}
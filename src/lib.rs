@@ -1,7 +1,17 @@
+pub mod alert;
+pub mod commit;
 pub mod connection_settings;
 pub mod echonetlite;
+pub mod influxdb;
+pub mod metrics;
+pub mod mqtt;
 pub mod pairing;
 pub mod skstack;
+pub mod store;
+pub mod timezone;
+pub mod units;
+pub mod webhook;
 
 pub use connection_settings::*;
 pub use pairing::*;
+pub use timezone::*;
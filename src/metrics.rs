@@ -0,0 +1,92 @@
+// Prometheus メトリクス
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use axum::{Router, http::StatusCode, routing::get};
+use prometheus::{Encoder, Gauge, GaugeVec, IntCounter, Opts, Registry, TextEncoder};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::LazyLock;
+
+/// 公開するメトリクス
+pub struct Metrics {
+    registry: Registry,
+    /// 瞬時電力(W)
+    pub instant_power: Gauge,
+    /// 瞬時電流(A) ラベル phase = "r" | "t"
+    pub instant_current: GaugeVec,
+    /// 受信したEchonetliteフレーム数
+    pub frames_received: IntCounter,
+    /// 解析に失敗したEchonetliteフレーム数
+    pub parse_errors: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new_custom(Some("uchinopower".to_string()), None)?;
+        let instant_power = Gauge::with_opts(Opts::new(
+            "instant_power_watts",
+            "instantious electric power (W)",
+        ))?;
+        let instant_current = GaugeVec::new(
+            Opts::new("instant_current_amperes", "instantious current (A)"),
+            &["phase"],
+        )?;
+        let frames_received = IntCounter::with_opts(Opts::new(
+            "frames_received_total",
+            "number of received echonet lite frames",
+        ))?;
+        let parse_errors = IntCounter::with_opts(Opts::new(
+            "parse_errors_total",
+            "number of echonet lite frames that failed to parse",
+        ))?;
+        registry.register(Box::new(instant_power.clone()))?;
+        registry.register(Box::new(instant_current.clone()))?;
+        registry.register(Box::new(frames_received.clone()))?;
+        registry.register(Box::new(parse_errors.clone()))?;
+        Ok(Self {
+            registry,
+            instant_power,
+            instant_current,
+            frames_received,
+            parse_errors,
+        })
+    }
+
+    /// テキスト形式で書き出す
+    pub fn encode(&self) -> prometheus::Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+/// メトリクス
+pub static METRICS: LazyLock<Metrics> =
+    LazyLock::new(|| Metrics::new().expect("Failed to register metrics"));
+
+/// GET /metrics
+async fn metrics_handler() -> Result<String, (StatusCode, String)> {
+    METRICS
+        .encode()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// メトリクスをHTTPで公開する
+pub async fn serve(addr: SocketAddr) -> io::Result<()> {
+    let app = Router::new().route("/metrics", get(metrics_handler));
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    tracing::info!("metrics endpoint listening on http://{addr}/metrics");
+    axum::serve(listener, app).await
+}
+
+#[test]
+fn test1() {
+    METRICS.instant_power.set(123.0);
+    METRICS.instant_current.with_label_values(&["r"]).set(4.5);
+    METRICS.frames_received.inc();
+    let text = METRICS.encode().unwrap();
+    assert!(text.contains("uchinopower_instant_power_watts 123"));
+    assert!(text.contains(r#"uchinopower_instant_current_amperes{phase="r"} 4.5"#));
+    assert!(text.contains("uchinopower_frames_received_total"));
+}
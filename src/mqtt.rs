@@ -0,0 +1,222 @@
+// MQTT で測定値を公開する
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use rust_decimal::Decimal;
+use serde_json::json;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// MQTT ブローカーのポート番号の既定値
+pub const DEFAULT_PORT: u16 = 1883;
+
+/// 状態トピックの接頭辞の既定値
+pub const DEFAULT_TOPIC_PREFIX: &str = "uchinopower";
+
+/// Home Assistant MQTT discovery の接頭辞
+const HA_DISCOVERY_PREFIX: &str = "homeassistant";
+
+/// 送信待ちにできる最大要求数(超えたら捨てる)
+const REQUEST_CAPACITY: usize = 64;
+
+/// 接続に失敗したときに再接続するまでの時間
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// 公開するセンサー(名前, device_class, unit_of_measurement, state_class, 表示名)
+pub const SENSORS: [(&str, &str, &str, &str, &str); 4] = [
+    // 0xe7 瞬時電力計測値
+    ("power", "power", "W", "measurement", "Instant power"),
+    // 0xe8 瞬時電流計測値
+    (
+        "current_r",
+        "current",
+        "A",
+        "measurement",
+        "Instant current R",
+    ),
+    (
+        "current_t",
+        "current",
+        "A",
+        "measurement",
+        "Instant current T",
+    ),
+    // 0xea 定時積算電力量計測値(正方向計測値)
+    (
+        "energy",
+        "energy",
+        "kWh",
+        "total_increasing",
+        "Cumlative energy",
+    ),
+];
+
+/// 接続先の設定
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// 状態トピックの接頭辞
+    pub topic_prefix: String,
+}
+
+/// 公開する
+struct Publisher {
+    client: AsyncClient,
+    topic_prefix: String,
+    /// 接続する度に公開する Home Assistant discovery 設定(トピック, JSON)
+    discovery: Mutex<Vec<(String, String)>>,
+}
+
+impl Publisher {
+    fn try_publish(&self, topic: String, retain: bool, payload: String) {
+        if let Err(e) = self
+            .client
+            .try_publish(&topic, QoS::AtLeastOnce, retain, payload)
+        {
+            tracing::debug!("mqtt publish to {topic} dropped, reason: {e}");
+        }
+    }
+
+    fn publish_discovery(&self) {
+        let discovery = self.discovery.lock().map(|v| v.clone()).unwrap_or_default();
+        for (topic, payload) in discovery {
+            self.try_publish(topic, true, payload);
+        }
+    }
+}
+
+static PUBLISHER: OnceLock<Publisher> = OnceLock::new();
+
+/// 公開が有効か
+pub fn is_enabled() -> bool {
+    PUBLISHER.get().is_some()
+}
+
+/// 状態トピック
+pub fn state_topic(topic_prefix: &str, sensor: &str) -> String {
+    format!("{topic_prefix}/{sensor}")
+}
+
+/// 測定値を状態トピックに公開する
+/// 公開が有効でなければ何もしない
+pub fn publish_state(sensor: &str, value: &Decimal) {
+    if let Some(publisher) = PUBLISHER.get() {
+        let topic = state_topic(&publisher.topic_prefix, sensor);
+        publisher.try_publish(topic, false, value.normalize().to_string());
+    }
+}
+
+/// 出来事(JSON)をトピックに公開する
+/// 公開が有効でなければ何もしない
+pub fn publish_event(name: &str, payload: String) {
+    if let Some(publisher) = PUBLISHER.get() {
+        let topic = state_topic(&publisher.topic_prefix, name);
+        publisher.try_publish(topic, false, payload);
+    }
+}
+
+/// Home Assistant MQTT discovery 設定(トピック, JSON)を作る
+/// スマートメーターのMACアドレスを機器の識別子にする
+pub fn home_assistant_discovery(topic_prefix: &str, mac_address: &str) -> Vec<(String, String)> {
+    SENSORS
+        .iter()
+        .map(|(name, device_class, unit, state_class, display_name)| {
+            let topic = format!("{HA_DISCOVERY_PREFIX}/sensor/uchinopower_{name}/config");
+            let payload = json!({
+                "name": display_name,
+                "unique_id": format!("uchinopower_{mac_address}_{name}"),
+                "state_topic": state_topic(topic_prefix, name),
+                "device_class": device_class,
+                "unit_of_measurement": unit,
+                "state_class": state_class,
+                "device": {
+                    "identifiers": [mac_address],
+                    "name": "Smart meter",
+                    "manufacturer": "uchinopower",
+                },
+            });
+            (topic, payload.to_string())
+        })
+        .collect()
+}
+
+/// Home Assistant MQTT discovery 設定を公開する
+/// 再接続したときも公開しなおす(retain)
+pub fn announce_home_assistant(mac_address: &str) {
+    let Some(publisher) = PUBLISHER.get() else {
+        tracing::warn!("mqtt is not configured, home assistant discovery is skipped");
+        return;
+    };
+    let discovery = home_assistant_discovery(&publisher.topic_prefix, mac_address);
+    if let Ok(mut v) = publisher.discovery.lock() {
+        *v = discovery;
+    }
+    publisher.publish_discovery();
+}
+
+/// MQTT ブローカーへの接続を開始する
+/// 接続できなくても測定は続けて, 後で再接続する
+pub fn start(config: MqttConfig) {
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some(username) = config.username.as_ref() {
+        options.set_credentials(username, config.password.as_deref().unwrap_or_default());
+    }
+    let (client, mut eventloop) = AsyncClient::new(options, REQUEST_CAPACITY);
+    let publisher = Publisher {
+        client,
+        topic_prefix: config.topic_prefix.clone(),
+        discovery: Mutex::new(Vec::new()),
+    };
+    if PUBLISHER.set(publisher).is_err() {
+        tracing::warn!("mqtt publisher is already running");
+        return;
+    }
+    tracing::info!(
+        "mqtt publisher started, broker: {}:{}, topic prefix: {}",
+        config.host,
+        config.port,
+        config.topic_prefix
+    );
+    tokio::spawn(async move {
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    tracing::info!("mqtt connected to {}:{}", config.host, config.port);
+                    if let Some(publisher) = PUBLISHER.get() {
+                        publisher.publish_discovery();
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::warn!("mqtt connection error: {e}");
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            }
+        }
+    });
+}
+
+#[test]
+fn test1() {
+    let discovery = home_assistant_discovery("uchinopower", "0123456789ABCDEF");
+    assert_eq!(discovery.len(), SENSORS.len());
+    let (topic, payload) = &discovery[0];
+    assert_eq!(topic, "homeassistant/sensor/uchinopower_power/config");
+    let v: serde_json::Value = serde_json::from_str(payload).unwrap();
+    assert_eq!(v["unique_id"], "uchinopower_0123456789ABCDEF_power");
+    assert_eq!(v["state_topic"], "uchinopower/power");
+    assert_eq!(v["device_class"], "power");
+    assert_eq!(v["unit_of_measurement"], "W");
+    assert_eq!(v["device"]["identifiers"][0], "0123456789ABCDEF");
+    let (topic, payload) = &discovery[3];
+    assert_eq!(topic, "homeassistant/sensor/uchinopower_energy/config");
+    let v: serde_json::Value = serde_json::from_str(payload).unwrap();
+    assert_eq!(v["unit_of_measurement"], "kWh");
+    assert_eq!(v["state_class"], "total_increasing");
+}
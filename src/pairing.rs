@@ -3,16 +3,12 @@
 // SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
 //
 use crate::connection_settings::ConnectionSettings;
-use crate::echonetlite::{
-    EchonetliteEdata, EchonetliteFrame, smart_electric_energy_meter as SM, superclass,
-};
+use crate::echonetlite::{smart_electric_energy_meter as SM, superclass};
 use crate::skstack;
 use crate::skstack::authn;
 use anyhow::Context;
-use core::time;
 use std::io;
 use std::net::Ipv6Addr;
-use std::thread;
 
 /// 接続するスマートメーターをアクティブスキャンで探す
 pub fn pairing(
@@ -43,92 +39,55 @@ pub fn pairing(
         )?;
 
         //
-        let props: Vec<EchonetliteEdata> = vec![
-            EchonetliteEdata {
-                epc: SM::UnitForCumlativeAmountsPower::EPC, // 積算電力量単位(正方向、逆方向計測値)
-                ..Default::default()
-            },
-            EchonetliteEdata {
-                epc: superclass::GetPropertyMap::EPC, // Getプロパティマップ
-                ..Default::default()
-            },
-            EchonetliteEdata {
-                epc: SM::Coefficient::EPC, // 係数(存在しない場合は×1倍)
-                ..Default::default()
-            },
-            EchonetliteEdata {
-                epc: SM::NumberOfEffectiveDigits::EPC, // 積算電力量有効桁数
-                ..Default::default()
-            },
+        let props: [u8; 4] = [
+            SM::UnitForCumlativeAmountsPower::EPC, // 積算電力量単位(正方向、逆方向計測値)
+            superclass::GetPropertyMap::EPC,       // Getプロパティマップ
+            SM::Coefficient::EPC,                  // 係数(存在しない場合は×1倍)
+            SM::NumberOfEffectiveDigits::EPC,      // 積算電力量有効桁数
         ];
 
         //
         let mut unit_for_cumlative_amounts_power: Option<SM::UnitForCumlativeAmountsPower> = None;
         let mut coefficient: Option<SM::Coefficient> = None;
+        let mut number_of_effective_digits: Option<SM::NumberOfEffectiveDigits> = None;
+        let mut get_property_map: Option<superclass::GetPropertyMap> = None;
         //
-        for edata in props {
-            let frame = EchonetliteFrame {
-                ehd: 0x1081,              // 0x1081 = echonet lite
-                tid: 1,                   // tid
-                seoj: [0x05, 0xff, 0x01], // home controller
-                deoj: [0x02, 0x88, 0x01], // smartmeter
-                esv: 0x62,                // get要求
-                opc: 1,                   // 1つ
-                edata: vec![edata],
-            };
-            let command = skstack::command_from_echonetliteframe(&sender, &frame)?;
-            skstack::send(port_writer, &command)?;
-            thread::sleep(time::Duration::from_secs(5));
-            // イベント受信
-            'exit: loop {
-                match skstack::receive(port_reader) {
-                    Ok(skstack::SkRxD::Void) => {}
-                    Ok(r @ skstack::SkRxD::Ok) => {
-                        tracing::trace!("{:?}", r);
-                    }
-                    Ok(r @ skstack::SkRxD::Fail(_)) => {
-                        tracing::trace!("{:?}", r);
-                    }
-                    Ok(r @ skstack::SkRxD::Event(_)) => {
-                        tracing::trace!("{:?}", r);
-                    }
-                    Ok(r @ skstack::SkRxD::Epandesc(_)) => {
-                        tracing::trace!("{:?}", r);
-                    }
-                    Ok(skstack::SkRxD::Erxudp(erxudp)) => {
-                        let config = bincode::config::standard()
-                            .with_big_endian()
-                            .with_fixed_int_encoding();
-                        let (frame, _len): (EchonetliteFrame, usize) =
-                            bincode::borrow_decode_from_slice(&erxudp.data, config)?;
-
-                        let mut s = Vec::<String>::new();
-                        s.push(format!("{}", frame));
-                        for v in frame.edata.iter() {
-                            s.push(format!("{}", v));
-                        }
-                        tracing::info!("{}", s.join(" "));
-                        // 積算電力量単位値を取り出す
-                        for edata in frame.edata.iter() {
-                            match SM::Properties::try_from(edata) {
-                                Ok(SM::Properties::UnitForCumlativeAmountsPower(a)) => {
-                                    unit_for_cumlative_amounts_power = Some(a);
-                                }
-                                Ok(SM::Properties::Coefficient(a)) => {
-                                    coefficient = Some(a);
-                                }
-                                _ => {}
-                            }
-                        }
-                        break 'exit;
-                    }
-                    Err(e) if e.kind() == io::ErrorKind::TimedOut => break 'exit,
-                    Err(e) => return Err(e).context("serial port read failed!"),
+        // まとめて要求する
+        let values = match skstack::request_properties(port_reader, port_writer, &sender, &props) {
+            Ok(values) => values,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                tracing::warn!("{}", e);
+                Vec::new()
+            }
+            Err(e) => return Err(e).context("request property failed!"),
+        };
+        for value in values.iter() {
+            let edata = value.as_edata();
+            tracing::info!("{}", edata);
+            // 積算電力量単位値を取り出す
+            match SM::Properties::try_from(&edata) {
+                Ok(SM::Properties::UnitForCumlativeAmountsPower(a)) => {
+                    unit_for_cumlative_amounts_power = Some(a);
+                }
+                Ok(SM::Properties::Coefficient(a)) => {
+                    coefficient = Some(a);
+                }
+                Ok(SM::Properties::NumberOfEffectiveDigits(a)) => {
+                    number_of_effective_digits = Some(a);
                 }
+                Ok(SM::Properties::Superclass(superclass::Properties::GetPropertyMap(a))) => {
+                    get_property_map = Some(a);
+                }
+                _ => {}
             }
         }
+        // 係数が無いメーターは×1倍
+        let coeff = coefficient.unwrap_or_else(|| {
+            tracing::info!("Coefficient(0xd3) is not available, assume 1");
+            SM::Coefficient(1)
+        });
         // スマートメータの接続情報
-        if let (Some(unit), Some(coeff)) = (unit_for_cumlative_amounts_power, coefficient) {
+        if let Some(unit) = unit_for_cumlative_amounts_power {
             let connection_settings = ConnectionSettings {
                 RouteBId: credentials.id.to_string(),
                 RouteBPassword: credentials.password.to_string(),
@@ -137,6 +96,12 @@ pub fn pairing(
                 PanId: epandesc.pan_id,
                 Unit: unit,
                 Coefficient: coeff,
+                NumberOfEffectiveDigits: number_of_effective_digits,
+                GetPropertyMap: get_property_map.map(|a| a.epcs().to_vec()),
+                // シリアルデバイス名は呼び出し側が知っている
+                SerialDevice: None,
+                // 使い始めた時刻も呼び出し側が決める
+                ActivatedAt: None,
             };
             return Ok(Some(connection_settings));
         }
@@ -144,3 +109,71 @@ pub fn pairing(
 
     Ok(None)
 }
+
+#[test]
+fn test1() {
+    use crate::skstack::mock_transport::MockTransport;
+    use rust_decimal::Decimal;
+    use std::str::FromStr;
+
+    let credentials = authn::Credentials {
+        id: authn::Id::from_str("0123456789ABCDEF0123456789ABCDEF").unwrap(),
+        password: authn::Password::from_str("PASSWORD1234").unwrap(),
+    };
+    let meter = "FE80:0000:0000:0000:1034:5678:9ABC:DEF0";
+    let epandesc = concat!(
+        "OK\r\n",
+        "EPANDESC\r\n",
+        "  Channel:21\r\n",
+        "  Channel Page:09\r\n",
+        "  Pan ID:1234\r\n",
+        "  Addr:123456789ABCDEF0\r\n",
+        "  LQI:E1\r\n",
+        "  PairID:01234567\r\n",
+    );
+    // 0xd3(係数)を実装していないメーターのGet_SNA応答
+    // Getプロパティマップ(0x9f)は 0x80, 0xe1, 0xd7 だけ
+    let erxudp = format!(
+        "OK\r\nERXUDP {0} {0} 0E1A 0E1A 123456789ABCDEF0 1 001A 1081000102880105FF015204E101019F040380E1D7D300D70106\r\n",
+        meter
+    );
+    let script = [
+        ("SKRESET", "OK\r\n".to_string()),
+        ("SKSETPWD C ", "OK\r\n".to_string()),
+        ("SKSETRBID ", "OK\r\n".to_string()),
+        (
+            "SKSCAN 2 FFFFFFFF 6",
+            format!("{}EVENT 22 {}\r\n", epandesc, meter),
+        ),
+        ("SKRESET", "OK\r\n".to_string()),
+        ("SKSREG SFE 0", "OK\r\n".to_string()),
+        ("SKSETPWD C ", "OK\r\n".to_string()),
+        ("SKSETRBID ", "OK\r\n".to_string()),
+        ("SKSREG S2 21", "OK\r\n".to_string()),
+        ("SKSREG S3 1234", "OK\r\n".to_string()),
+        (
+            "SKJOIN FE80:0000:0000:0000:1034:5678:9ABC:DEF0",
+            format!("OK\r\nEVENT 25 {}\r\n", meter),
+        ),
+        ("SKSENDTO 1 FE80:0000:0000:0000:1034:5678:9ABC:DEF0", erxudp),
+    ];
+    let mut writer = MockTransport::new(&script);
+    let mut reader = io::BufReader::new(writer.clone());
+
+    let settings = pairing(&mut reader, &mut writer, 6, &credentials)
+        .unwrap()
+        .unwrap();
+    assert_eq!(settings.Channel, 0x21);
+    assert_eq!(settings.PanId, 0x1234);
+    assert_eq!(settings.MacAddress, "123456789ABCDEF0");
+    assert_eq!(
+        settings.Unit,
+        SM::UnitForCumlativeAmountsPower(Decimal::new(1, 1))
+    );
+    assert_eq!(settings.Coefficient, SM::Coefficient(1));
+    assert_eq!(
+        settings.NumberOfEffectiveDigits,
+        Some(SM::NumberOfEffectiveDigits(6))
+    );
+    assert_eq!(settings.GetPropertyMap, Some(vec![0x80, 0xe1, 0xd7]));
+}
@@ -1,9 +1,18 @@
 pub mod active_scan;
 pub mod authn;
+pub mod codec;
+#[cfg(test)]
+pub mod mock_transport;
 pub mod parser;
+pub mod redact;
 pub mod send_receive;
+pub mod serial;
 pub mod skrxd;
+pub mod udp_route;
 
 pub use active_scan::*;
+pub use codec::*;
 pub use send_receive::*;
+pub use serial::*;
 pub use skrxd::*;
+pub use udp_route::*;
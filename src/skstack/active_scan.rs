@@ -14,16 +14,16 @@ pub fn active_scan(
     credentials: &authn::Credentials,
 ) -> anyhow::Result<Vec<skstack::Epandesc>> {
     let pairing_sequence = [
-        "SKRESET\r\n".to_owned(),                           // リセット
-        format!("SKSETPWD C {}\r\n", credentials.password), // パスワードを登録する。
-        format!("SKSETRBID {}\r\n", credentials.id),        // IDを登録する。
-        format!("SKSCAN 2 FFFFFFFF {:X}\r\n", scan_time),   // アクティブスキャン
+        "SKRESET".to_owned(),                           // リセット
+        format!("SKSETPWD C {}", credentials.password), // パスワードを登録する。
+        format!("SKSETRBID {}", credentials.id),        // IDを登録する。
+        format!("SKSCAN 2 FFFFFFFF {:X}", scan_time),   // アクティブスキャン
     ];
 
     // コマンド発行
     for command in pairing_sequence.iter() {
-        skstack::send(port_writer, command.as_bytes()).context("write failed!")?;
-        if let skstack::SkRxD::Fail(code) = skstack::receive(port_reader)? {
+        skstack::send_line(port_writer, command).context("write failed!")?;
+        if let skstack::SkRxD::Fail(code) = skstack::receive_skip_unparsed(port_reader)? {
             bail!(
                 r#"コマンド "{}" 実行に失敗しました。 ER{}"#,
                 command.escape_debug(),
@@ -34,8 +34,8 @@ pub fn active_scan(
 
     let mut found = Vec::<skstack::Epandesc>::new();
     // アクティブスキャン結果待ち
-    'exit: loop {
-        match skstack::receive(port_reader) {
+    'exit: for received in skstack::responses(port_reader) {
+        match received {
             Ok(skstack::SkRxD::Void) => {}
             Ok(skstack::SkRxD::Ok) => {}
             Ok(fail @ skstack::SkRxD::Fail(_)) => {
@@ -57,7 +57,11 @@ pub fn active_scan(
             Ok(skstack::SkRxD::Erxudp(event)) => {
                 tracing::debug!("{:?}", event);
             }
+            Ok(skstack::SkRxD::NeighborTable(table)) => {
+                tracing::debug!("{:?}", table);
+            }
             Err(e) if e.kind() == io::ErrorKind::TimedOut => continue, // タイムアウトエラーは無視する
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => tracing::warn!("{}", e), // 解析できない受信データ
             Err(e) => return Err(e).context("read failed!"),
         }
     }
@@ -17,6 +17,8 @@ pub enum Error {
     Fail(u8),
     #[error("PANAセッションが切断された")]
     PanaSessionDisconnected,
+    #[error("接続を中止した")]
+    Cancelled,
 }
 
 #[derive(PartialEq, Eq)]
@@ -26,43 +28,90 @@ pub struct Credentials {
     pub password: Password,
 }
 
+/// 前後の空白を取り除いて固定長の ASCII 文字列にする
+/// 制約を満たさなければどの制約を満たさないかを返す
+fn parse_fixed_ascii<const N: usize>(
+    s: &str,
+    name: &str,
+    is_valid: fn(&u8) -> bool,
+    constraint: &str,
+) -> Result<[u8; N], String> {
+    let trimmed = s.trim();
+    let length = trimmed.chars().count();
+    if length != N {
+        return Err(format!("{name}は{N}文字固定長です (got {length} chars)"));
+    }
+    if let Some((i, c)) = trimmed
+        .char_indices()
+        .find(|(_, c)| !c.is_ascii() || !is_valid(&(*c as u8)))
+    {
+        return Err(format!(
+            "{name}は{constraint}です ({:?} at {})",
+            c,
+            trimmed[..i].chars().count() + 1
+        ));
+    }
+    let mut bytes = [0u8; N];
+    bytes.copy_from_slice(trimmed.as_bytes());
+    Ok(bytes)
+}
+
 #[derive(PartialEq, Eq)]
-/// ID
-pub struct Id([char; 32]);
+/// ID(16進数32文字)
+pub struct Id([u8; 32]);
 impl std::str::FromStr for Id {
     type Err = String;
     #[inline]
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        s.chars()
-            .collect::<Vec<char>>()
-            .try_into()
-            .map(|a| Self(a))
-            .map_err(|_| "IDは32文字固定長です".to_string())
+        parse_fixed_ascii(s, "ID", u8::is_ascii_hexdigit, "16進数").map(Self)
     }
 }
 impl std::fmt::Display for Id {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.0.iter().collect::<String>())
+        // ASCII文字しか入っていない
+        write!(f, "{}", String::from_utf8_lossy(&self.0))
     }
 }
 
 #[derive(PartialEq, Eq)]
-/// パスワード
-pub struct Password([char; 12]);
+/// パスワード(英数字12文字)
+pub struct Password([u8; 12]);
 impl std::str::FromStr for Password {
     type Err = String;
     #[inline]
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        s.chars()
-            .collect::<Vec<char>>()
-            .try_into()
-            .map(|a| Self(a))
-            .map_err(|_| "PASSWORDは12文字固定長です".to_string())
+        parse_fixed_ascii(s, "PASSWORD", u8::is_ascii_alphanumeric, "英数字").map(Self)
     }
 }
 impl std::fmt::Display for Password {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.0.iter().collect::<String>())
+        // ASCII文字しか入っていない
+        write!(f, "{}", String::from_utf8_lossy(&self.0))
+    }
+}
+
+/// PANAセッションが終わったことを知らせるイベント番号なら, その内容を返す
+///
+/// | EVENT | 内容                                             |
+/// |-------|--------------------------------------------------|
+/// | 0x24  | PANA による接続過程でエラーが発生した            |
+/// | 0x26  | 接続相手からセッション終了要求を受信した         |
+/// | 0x27  | PANA セッションの終了に成功した                  |
+/// | 0x28  | セッションの終了要求に対する応答がなくタイムアウトした |
+/// | 0x29  | セッションのライフタイムが経過して期限切れになった |
+///
+/// 0x26 の後は SKSTACK が終了要求に応答してセッションを閉じるので,
+/// 以降の送信は失敗する。どれを受信しても接続し直すこと。
+pub fn session_end_event(code: u8) -> Option<&'static str> {
+    match code {
+        0x24 => Some("PANA による接続過程でエラーが発生した（接続が完了しなかった）"),
+        0x26 => Some("接続相手からセッション終了要求を受信した"),
+        0x27 => Some("PANA セッションの終了に成功した"),
+        0x28 => {
+            Some("PANA セッションの終了要求に対する応答がなくタイムアウトした（セッションは終了）")
+        }
+        0x29 => Some("セッションのライフタイムが経過して期限切れになった"),
+        _ => None,
     }
 }
 
@@ -74,38 +123,63 @@ pub fn connect(
     sender: &Ipv6Addr,
     channel: u8,
     pan_id: u16,
+) -> std::result::Result<(), Error> {
+    connect_cancellable(
+        reader,
+        writer,
+        credentials,
+        sender,
+        channel,
+        pan_id,
+        &|| false,
+    )
+}
+
+/// スマートメーターと接続する
+/// コマンド毎と受信待ち(タイムアウト)毎に is_cancelled を調べて, 中止するなら Error::Cancelled を返す
+pub fn connect_cancellable(
+    reader: &mut io::BufReader<dyn io::Read>,
+    writer: &mut dyn io::Write,
+    credentials: &Credentials,
+    sender: &Ipv6Addr,
+    channel: u8,
+    pan_id: u16,
+    is_cancelled: &dyn Fn() -> bool,
 ) -> std::result::Result<(), Error> {
     let sender_address = sender.segments().map(|n| format!("{:04X}", n)).join(":");
 
     let connect_sequence = [
-        "SKRESET\r\n".to_owned(),                           // リセット
-        "SKSREG SFE 0\r\n".to_owned(),                      // コマンドのエコーバックを無効にする。
-        format!("SKSETPWD C {}\r\n", credentials.password), // パスワードを登録する。
-        format!("SKSETRBID {}\r\n", credentials.id),        // IDを登録する。
-        format!("SKSREG S2 {:02X}\r\n", channel),           // 自端末の論理チャンネル番号を設定する
-        format!("SKSREG S3 {:04X}\r\n", pan_id),            // 自端末のPAN IDを設定する
-        format!("SKJOIN {}\r\n", sender_address),           // PANA認証開始
+        "SKRESET".to_owned(),                           // リセット
+        "SKSREG SFE 0".to_owned(),                      // コマンドのエコーバックを無効にする。
+        format!("SKSETPWD C {}", credentials.password), // パスワードを登録する。
+        format!("SKSETRBID {}", credentials.id),        // IDを登録する。
+        format!("SKSREG S2 {:02X}", channel),           // 自端末の論理チャンネル番号を設定する
+        format!("SKSREG S3 {:04X}", pan_id),            // 自端末のPAN IDを設定する
+        format!("SKJOIN {}", sender_address),           // PANA認証開始
     ];
 
     // コマンド発行
     for command in connect_sequence.iter() {
-        skstack::send(writer, command.as_bytes())?;
+        if is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        skstack::send_line(writer, command)?;
         thread::sleep(Duration::from_millis(1));
-        if let skstack::SkRxD::Fail(code) = skstack::receive(reader)? {
+        if let skstack::SkRxD::Fail(code) = skstack::receive_skip_unparsed(reader)? {
             return Err(Error::Fail(code));
         }
     }
 
     // PANA認証開始後のイベントを処理する
-    loop {
-        match skstack::receive(reader) {
+    for received in skstack::responses(reader) {
+        match received {
             Ok(skstack::SkRxD::Void) => {}
             // OK
             Ok(skstack::SkRxD::Ok) => {}
             // FAIL ER
             Ok(skstack::SkRxD::Fail(code)) => return Err(Error::Fail(code)),
-            // EVENT 0x24 = PANA接続失敗
-            Ok(skstack::SkRxD::Event(event)) if event.code == 0x24 => {
+            // EVENT 0x24 = PANA接続失敗 など(session_end_event の表)
+            Ok(skstack::SkRxD::Event(event)) if session_end_event(event.code).is_some() => {
                 return Err(Error::PanaSessionDisconnected);
             }
             // EVENT 0x25 = PANA接続完了
@@ -116,10 +190,162 @@ pub fn connect(
             Ok(skstack::SkRxD::Epandesc(_)) => {}
             // ERXUDP
             Ok(skstack::SkRxD::Erxudp(_)) => {}
+            // ENEIGHBOR
+            Ok(skstack::SkRxD::NeighborTable(_)) => {}
             //
-            Err(e) if e.kind() == io::ErrorKind::TimedOut => continue, // タイムアウトエラーは無視する
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                // タイムアウトエラーは無視する
+                if is_cancelled() {
+                    return Err(Error::Cancelled);
+                }
+            }
+            // 解析できない受信データ
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => tracing::warn!("{}", e),
             //
             Err(e) => return Err(Error::Io(e)),
         }
     }
+    // PANA認証の結果を受信する前に読み込むものが無くなった
+    Err(Error::Io(io::ErrorKind::UnexpectedEof.into()))
+}
+
+#[cfg(test)]
+fn connect_script(join_result: &str) -> Vec<(&'static str, String)> {
+    vec![
+        ("SKRESET", "OK\r\n".to_string()),
+        ("SKSREG SFE 0", "SKSREG SFE 0\r\nOK\r\n".to_string()),
+        ("SKSETPWD C ", "OK\r\n".to_string()),
+        ("SKSETRBID ", "OK\r\n".to_string()),
+        ("SKSREG S2 ", "OK\r\n".to_string()),
+        ("SKSREG S3 ", "OK\r\n".to_string()),
+        (
+            "SKJOIN ",
+            format!(
+                "OK\r\nEVENT 21 FE80:0000:0000:0000:1234:5678:9ABC:DEF0 00\r\n{}",
+                join_result
+            ),
+        ),
+    ]
+}
+
+#[test]
+fn test1() {
+    use crate::skstack::mock_transport::MockTransport;
+    use std::str::FromStr;
+
+    let credentials = Credentials {
+        id: Id::from_str("0123456789ABCDEF0123456789ABCDEF").unwrap(),
+        password: Password::from_str("PASSWORD1234").unwrap(),
+    };
+    let sender: Ipv6Addr = "FE80:0000:0000:0000:1234:5678:9ABC:DEF0".parse().unwrap();
+
+    // EVENT 25 = PANA接続完了
+    let script = connect_script("EVENT 25 FE80:0000:0000:0000:1234:5678:9ABC:DEF0\r\n");
+    let mut writer = MockTransport::new(&script);
+    let mut reader = io::BufReader::new(writer.clone());
+    assert!(
+        connect(
+            &mut reader,
+            &mut writer,
+            &credentials,
+            &sender,
+            0x21,
+            0x1234
+        )
+        .is_ok()
+    );
+    assert_eq!(
+        writer.sent(),
+        vec![
+            "SKRESET",
+            "SKSREG SFE 0",
+            "SKSETPWD C PASSWORD1234",
+            "SKSETRBID 0123456789ABCDEF0123456789ABCDEF",
+            "SKSREG S2 21",
+            "SKSREG S3 1234",
+            "SKJOIN FE80:0000:0000:0000:1234:5678:9ABC:DEF0",
+        ]
+    );
+
+    // EVENT 24 = PANA接続失敗
+    let script = connect_script("EVENT 24 FE80:0000:0000:0000:1234:5678:9ABC:DEF0\r\n");
+    let mut writer = MockTransport::new(&script);
+    let mut reader = io::BufReader::new(writer.clone());
+    assert!(matches!(
+        connect(
+            &mut reader,
+            &mut writer,
+            &credentials,
+            &sender,
+            0x21,
+            0x1234
+        ),
+        Err(Error::PanaSessionDisconnected)
+    ));
+
+    // 最初のコマンドを送った後に中止する
+    let script = connect_script("EVENT 25 FE80:0000:0000:0000:1234:5678:9ABC:DEF0\r\n");
+    let mut writer = MockTransport::new(&script);
+    let mut reader = io::BufReader::new(writer.clone());
+    let probe = writer.clone();
+    assert!(matches!(
+        connect_cancellable(
+            &mut reader,
+            &mut writer,
+            &credentials,
+            &sender,
+            0x21,
+            0x1234,
+            &|| !probe.sent().is_empty()
+        ),
+        Err(Error::Cancelled)
+    ));
+    assert_eq!(probe.sent(), vec!["SKRESET"]);
+}
+
+#[test]
+fn test2() {
+    use std::str::FromStr;
+    // 前後の空白と改行は取り除く
+    let id = Id::from_str(" 0123456789ABCDEF0123456789abcdef\r\n").unwrap();
+    assert_eq!(id.to_string(), "0123456789ABCDEF0123456789abcdef");
+    let password = Password::from_str("PASSWORD1234\n").unwrap();
+    assert_eq!(password.to_string(), "PASSWORD1234");
+    // 文字数が違う
+    assert_eq!(
+        Id::from_str("0123456789ABCDEF").err().unwrap(),
+        "IDは32文字固定長です (got 16 chars)"
+    );
+    assert_eq!(
+        Password::from_str("PASSWORD12345").err().unwrap(),
+        "PASSWORDは12文字固定長です (got 13 chars)"
+    );
+    // 使えない文字
+    assert_eq!(
+        Id::from_str("0123456789ABCDEF0123456789ABCDEG")
+            .err()
+            .unwrap(),
+        "IDは16進数です ('G' at 32)"
+    );
+    assert_eq!(
+        Password::from_str("PASSWORD-234").err().unwrap(),
+        "PASSWORDは英数字です ('-' at 9)"
+    );
+    assert_eq!(
+        Password::from_str("PASSWORDあ234").err().unwrap(),
+        "PASSWORDは英数字です ('あ' at 9)"
+    );
+}
+
+#[test]
+fn test3() {
+    // セッションの終わりを知らせるイベント番号の表
+    let ended = (0..=u8::MAX)
+        .filter(|code| session_end_event(*code).is_some())
+        .collect::<Vec<u8>>();
+    assert_eq!(ended, vec![0x24, 0x26, 0x27, 0x28, 0x29]);
+    // 接続完了, UDP送信結果, ARIB108 の送信制限はセッションを終わらせない
+    for code in [0x21, 0x25, 0x32, 0x33] {
+        assert_eq!(session_end_event(code), None);
+    }
 }
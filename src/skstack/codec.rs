@@ -0,0 +1,105 @@
+// SKSTACK 非同期受信
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use crate::skstack::{RECEIVE_MAX_BYTES, SkRxD, send_receive::parse_lines};
+use std::io;
+use tokio::io::AsyncRead;
+use tokio_util::bytes::{Buf, BytesMut};
+use tokio_util::codec::{Decoder, FramedRead};
+
+/// 受信したバイト列を SkRxD に区切るコーデック
+/// 解析できない応答は受信を続けられるように Item の Err で返す
+/// (skstack::receive と同じく io::ErrorKind::InvalidData)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SkRxDCodec;
+
+impl Decoder for SkRxDCodec {
+    type Item = io::Result<SkRxD>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // 改行まで受信した行を1行ずつ連結して解析する
+        let mut end = 0;
+        let mut lines = 0;
+        while let Some(pos) = src[end..].iter().position(|b| *b == b'\n') {
+            end += pos + 1;
+            lines += 1;
+            let text = match std::str::from_utf8(&src[..end]) {
+                Ok(text) => text,
+                Err(e) => {
+                    src.advance(end);
+                    return Ok(Some(Err(io::Error::new(io::ErrorKind::InvalidData, e))));
+                }
+            };
+            if let Some(result) = parse_lines(text, lines) {
+                tracing::trace!(target:"<-Rx","{}", text.escape_debug());
+                src.advance(end);
+                return Ok(Some(result));
+            }
+        }
+        // 改行がないまま最大バイト数を超えた
+        if src.len() >= RECEIVE_MAX_BYTES {
+            let discarded = src.len();
+            src.clear();
+            return Ok(Some(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("incomplete response discarded ({} bytes)", discarded),
+            ))));
+        }
+        // つづけて受信する
+        Ok(None)
+    }
+}
+
+/// 受信した SkRxD を Stream で返す
+pub fn framed_reader<R: AsyncRead>(reader: R) -> FramedRead<R, SkRxDCodec> {
+    FramedRead::new(reader, SkRxDCodec)
+}
+
+#[cfg(test)]
+fn decode_all(input: &[u8]) -> Vec<io::Result<SkRxD>> {
+    let mut codec = SkRxDCodec;
+    let mut buf = BytesMut::from(input);
+    let mut items = Vec::new();
+    while let Some(item) = codec.decode(&mut buf).unwrap() {
+        items.push(item);
+    }
+    items
+}
+
+#[test]
+fn test1() {
+    // 途中まで受信したら続きを待つ
+    let mut codec = SkRxDCodec;
+    let mut buf = BytesMut::from(&b"OK\r\nEVENT 21 FE80:0000:0000:0000"[..]);
+    assert_eq!(codec.decode(&mut buf).unwrap().unwrap().unwrap(), SkRxD::Ok);
+    assert!(codec.decode(&mut buf).unwrap().is_none());
+    buf.extend_from_slice(b":1234:5678:9ABC:DEF0 00\r\n");
+    assert!(matches!(
+        codec.decode(&mut buf).unwrap().unwrap().unwrap(),
+        SkRxD::Event(crate::skstack::Event {
+            code: 0x21,
+            param: Some(0),
+            ..
+        })
+    ));
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test2() {
+    // 解析できない行を受信しても、続きを受信できる
+    let items = decode_all(b"SKVER\r\nOK\r\n");
+    assert_eq!(items.len(), 2);
+    assert_eq!(
+        items[0].as_ref().unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+    assert_eq!(items[1].as_ref().unwrap(), &SkRxD::Ok);
+    // 複数行の応答
+    let epandesc = "EPANDESC\r\n  Channel:3B\r\n  Channel Page:09\r\n  Pan ID:ABCD\r\n  Addr:12345678ABCDABCD\r\n  LQI:84\r\n  PairID:1234ABCD\r\n";
+    let items = decode_all(epandesc.as_bytes());
+    assert_eq!(items.len(), 1);
+    assert!(matches!(items[0], Ok(SkRxD::Epandesc(_))));
+}
@@ -0,0 +1,92 @@
+// テスト用のシリアル通信
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::rc::Rc;
+
+/// 送信されたコマンドに台本どおりの応答を返すメモリ上のシリアル通信
+/// 読み込み用と書き込み用に clone して使う
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    inner: Rc<RefCell<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// (期待するコマンドの先頭部分, 応答)の台本
+    script: VecDeque<(String, String)>,
+    /// 送信されたコマンド
+    sent: Vec<String>,
+    /// 読み込まれるのを待っている応答
+    rx: VecDeque<u8>,
+}
+
+impl MockTransport {
+    /// 台本を作る
+    pub fn new<C: AsRef<str>, R: AsRef<str>>(script: &[(C, R)]) -> Self {
+        let inner = Inner {
+            script: script
+                .iter()
+                .map(|(command, response)| {
+                    (command.as_ref().to_string(), response.as_ref().to_string())
+                })
+                .collect(),
+            ..Default::default()
+        };
+        Self {
+            inner: Rc::new(RefCell::new(inner)),
+        }
+    }
+
+    /// 送信されたコマンド
+    pub fn sent(&self) -> Vec<String> {
+        self.inner.borrow().sent.clone()
+    }
+}
+
+impl io::Write for MockTransport {
+    /// 1回の書き込みを1つのコマンドとして台本の応答を返す
+    /// (skstack::send は1回の write_all でコマンドを書き込む)
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut inner = self.inner.borrow_mut();
+        let command =
+            String::from_utf8_lossy(buf.strip_suffix(b"\r\n").unwrap_or(buf)).into_owned();
+        let Some((expected, response)) = inner.script.pop_front() else {
+            panic!(r#"unexpected command "{}""#, command.escape_debug());
+        };
+        assert!(
+            command.starts_with(&expected),
+            r#"expected "{}" but "{}""#,
+            expected,
+            command.escape_debug()
+        );
+        inner.sent.push(command);
+        inner.rx.extend(response.as_bytes());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Read for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut inner = self.inner.borrow_mut();
+        if inner.rx.is_empty() {
+            // 台本が尽きたら終わる(タイムアウトにすると受信側が待ち続ける)
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "script exhausted",
+            ));
+        }
+        let n = buf.len().min(inner.rx.len());
+        for (dst, src) in buf.iter_mut().zip(inner.rx.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+}
@@ -3,14 +3,17 @@
 // SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
 //
 use crate::skstack::{self, SkRxD};
+use nom::Parser;
 use nom::branch::alt;
-use nom::bytes::complete::{tag, take_while_m_n};
-use nom::character::complete::{crlf, hex_digit1, space0, space1};
+use nom::bytes::complete::{tag, take_till1, take_while_m_n};
+use nom::character::complete::{hex_digit1, line_ending, not_line_ending, space0, space1};
 use nom::combinator::{map, map_res, opt};
 use nom::multi::{many0, separated_list1};
-use nom::{Parser, bytes};
 use std::net::Ipv6Addr;
 
+// 16進数は大文字でも小文字でもよい(小文字でエコーバックするファームウェアもある)
+// 行末は \r\n でも \n でもよい(CRを落とすUSBシリアル変換器もある)
+
 // 8ビット16進数(任意桁)
 fn u8_hex_digit(input: &str) -> nom::IResult<&str, u8> {
     map_res(hex_digit1, |hexd| u8::from_str_radix(hexd, 16)).parse(input)
@@ -36,13 +39,13 @@ fn u64_hex_digit(input: &str) -> nom::IResult<&str, u64> {
 
 // FAIL ERxx\r\n
 fn rx_fail(input: &str) -> nom::IResult<&str, SkRxD> {
-    let parser = (tag("FAIL ER"), u8_hex_digit2, crlf);
+    let parser = (tag("FAIL ER"), u8_hex_digit2, line_ending);
     map(parser, |(_tag, code, _crlf)| SkRxD::Fail(code)).parse(input)
 }
 
 // OK\r\n
 fn rx_ok(input: &str) -> nom::IResult<&str, SkRxD> {
-    map((tag("OK"), crlf), |_| SkRxD::Ok).parse(input)
+    map((tag("OK"), line_ending), |_| SkRxD::Ok).parse(input)
 }
 
 // Ipv6アドレス(FE80:0000:0000:0000:0000:0000:0000:0000)
@@ -60,7 +63,7 @@ fn rx_event(s: &str) -> nom::IResult<&str, SkRxD> {
     let (s, sender_address) = ipv6addr.parse(s)?;
     let (s, _) = space0.parse(s)?;
     let (s, param) = opt(map(u8_hex_digit, |n| n)).parse(s)?;
-    let (s, _) = crlf.parse(s)?;
+    let (s, _) = line_ending.parse(s)?;
     Ok((
         s,
         SkRxD::Event(skstack::Event {
@@ -91,16 +94,40 @@ fn rx_erxudp(s: &str) -> nom::IResult<&str, SkRxD> {
     // 送信元のMAC層アドレス
     let (s, senderlla) = u64_hex_digit.parse(s)?;
     let (s, _) = space1.parse(s)?;
-    // 暗号化あり/なし
-    let (s, secured) = map(u8_hex_digit, |n| n).parse(s)?;
-    let (s, _) = space1.parse(s)?;
+    // 2.4GHz/サブGHz両対応のドングルは LQI, 暗号化あり/なし, Side の順に返す
+    let (s, extended) = opt((
+        u8_hex_digit2,
+        space1,
+        u8_hex_digit,
+        space1,
+        u8_hex_digit,
+        space1,
+    ))
+    .parse(s)?;
+    let (s, lqi, secured) = match extended {
+        Some((lqi, _, secured, _, _side, _)) => (s, Some(lqi), secured),
+        None => {
+            // 暗号化あり/なし
+            let (s, secured) = map(u8_hex_digit, |n| n).parse(s)?;
+            let (s, _) = space1.parse(s)?;
+            (s, None, secured)
+        }
+    };
     // 受信したデータの長さ
     let (s, datalen) = map(u16_hex_digit, |n| n).parse(s)?;
     let (s, _) = space1.parse(s)?;
     // 受信データ(テキスト)
-    let (s, data) = many0(u8_hex_digit2).parse(s)?;
+    let (rest, data) = many0(u8_hex_digit2).parse(s)?;
     //
-    let (s, _) = crlf.parse(s)?;
+    let (rest, _) = line_ending.parse(rest)?;
+    // 受信データの長さが一致しなければ解析失敗(途中で途切れた行など)
+    if data.len() != datalen as usize {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            s,
+            nom::error::ErrorKind::LengthValue,
+        )));
+    }
+    let s = rest;
 
     //
     let erxudp = skstack::Erxudp {
@@ -109,6 +136,7 @@ fn rx_erxudp(s: &str) -> nom::IResult<&str, SkRxD> {
         sender_port,
         destination_port: destination_port,
         senderlla: senderlla,
+        lqi,
         secured: secured,
         datalen,
         data: data,
@@ -117,55 +145,118 @@ fn rx_erxudp(s: &str) -> nom::IResult<&str, SkRxD> {
     Ok((s, SkRxD::Erxudp(erxudp)))
 }
 
+// EPANDESCの項目行(  Key:Value\r\n)
+// 字下げは空白でもタブでもよい
+fn epandesc_field(s: &str) -> nom::IResult<&str, (&str, &str)> {
+    let (s, _) = space1.parse(s)?;
+    let (s, key) = take_till1(|c| c == ':' || c == '\r' || c == '\n').parse(s)?;
+    let (s, _) = tag(":").parse(s)?;
+    let (s, value) = not_line_ending.parse(s)?;
+    let (s, _) = line_ending.parse(s)?;
+    Ok((s, (key.trim(), value.trim())))
+}
+
 // EPANDESC
+// 項目は名前で見分けるので順番は問わない(知らない項目は無視する)
+// Channel Page, Side は無いこともある
 fn rx_epandesc(s: &str) -> nom::IResult<&str, SkRxD> {
-    // 1行目
-    let (s, _) = (tag("EPANDESC"), crlf).parse(s)?;
-    // 2行目
-    let (s, _) = bytes::streaming::tag("  ").parse(s)?;
-    let (s, channel) = map((tag("Channel:"), u64_hex_digit, crlf), |(_, n, _)| n as u8).parse(s)?;
-    // 3行目
-    let (s, _) = bytes::streaming::tag("  ").parse(s)?;
-    let (s, channel_page) = map((tag("Channel Page:"), u64_hex_digit, crlf), |(_, n, _)| {
-        n as u8
-    })
-    .parse(s)?;
-    // 4行目
-    let (s, _) = bytes::streaming::tag("  ").parse(s)?;
-    let (s, pan_id) = map((tag("Pan ID:"), u64_hex_digit, crlf), |(_, n, _)| n as u16).parse(s)?;
-    // 5行目
-    let (s, _) = bytes::streaming::tag("  ").parse(s)?;
-    let (s, (_, mac_address, _)) = (tag("Addr:"), u64_hex_digit, crlf).parse(s)?;
-    // 6行目
-    let (s, _) = bytes::streaming::tag("  ").parse(s)?;
-    let (s, lqi) = map((tag("LQI:"), u64_hex_digit, crlf), |(_, n, _)| n as u8).parse(s)?;
-    // 7行目
-    let (s, _) = bytes::streaming::tag("  ").parse(s)?;
-    let (s, pair_id) = map((tag("PairID:"), u64_hex_digit, crlf), |(_, n, _)| n as u32).parse(s)?;
+    let (mut s, _) = (tag("EPANDESC"), line_ending).parse(s)?;
+    let mut channel = None;
+    let mut channel_page = None;
+    let mut pan_id = None;
+    let mut addr = None;
+    let mut lqi = None;
+    let mut pair_id = None;
+    let mut side = None;
+    while let Ok((rest, (key, value))) = epandesc_field(s) {
+        let result = match key {
+            "Channel" => u8::from_str_radix(value, 16).map(|n| channel = Some(n)),
+            "Channel Page" => u8::from_str_radix(value, 16).map(|n| channel_page = Some(n)),
+            "Pan ID" => u16::from_str_radix(value, 16).map(|n| pan_id = Some(n)),
+            "Addr" => u64::from_str_radix(value, 16).map(|n| addr = Some(n)),
+            "LQI" => u8::from_str_radix(value, 16).map(|n| lqi = Some(n)),
+            "PairID" => u32::from_str_radix(value, 16).map(|n| pair_id = Some(n)),
+            "Side" => u8::from_str_radix(value, 16).map(|n| side = Some(n)),
+            _ => Ok(()),
+        };
+        if result.is_err() {
+            return Err(nom::Err::Error(nom::error::Error::new(
+                s,
+                nom::error::ErrorKind::HexDigit,
+            )));
+        }
+        s = rest;
+    }
 
-    //
-    let epandesc = skstack::Epandesc {
-        channel: channel,
-        channel_page: channel_page,
-        pan_id: pan_id,
-        addr: mac_address,
-        lqi: lqi,
-        pair_id: pair_id,
-    };
+    match (channel, pan_id, addr, lqi, pair_id) {
+        (Some(channel), Some(pan_id), Some(addr), Some(lqi), Some(pair_id)) => {
+            let epandesc = skstack::Epandesc {
+                channel,
+                channel_page: channel_page.unwrap_or_default(),
+                pan_id,
+                addr,
+                lqi,
+                pair_id,
+                side,
+            };
+            Ok((s, SkRxD::Epandesc(epandesc)))
+        }
+        // 続きの行を待つ
+        _ if s.is_empty() => Err(nom::Err::Incomplete(nom::Needed::new(2))),
+        _ => Err(nom::Err::Error(nom::error::Error::new(
+            s,
+            nom::error::ErrorKind::Tag,
+        ))),
+    }
+}
 
-    Ok((s, SkRxD::Epandesc(epandesc)))
+// ENEIGHBORの項目行(IPADDR ADDR64 ADDR16\r\n)
+fn neighbor_entry(s: &str) -> nom::IResult<&str, skstack::Neighbor> {
+    let (s, address) = ipv6addr.parse(s)?;
+    let (s, _) = space1.parse(s)?;
+    let (s, addr64) = u64_hex_digit.parse(s)?;
+    let (s, _) = space1.parse(s)?;
+    let (s, addr16) = u16_hex_digit.parse(s)?;
+    let (s, _) = (space0, line_ending).parse(s)?;
+    Ok((
+        s,
+        skstack::Neighbor {
+            address,
+            addr64,
+            addr16,
+        },
+    ))
+}
+
+// ENEIGHBOR
+// 近隣キャッシュの項目行が続いて OK で終わる(OKまでを1つの応答にする)
+fn rx_eneighbor(s: &str) -> nom::IResult<&str, SkRxD> {
+    let (s, _) = (tag("ENEIGHBOR"), line_ending).parse(s)?;
+    let (s, neighbors) = many0(neighbor_entry).parse(s)?;
+    match rx_ok(s) {
+        Ok((s, _)) => Ok((s, SkRxD::NeighborTable(neighbors))),
+        // 続きの行を待つ
+        _ if s.is_empty() => Err(nom::Err::Incomplete(nom::Needed::new(2))),
+        _ => Err(nom::Err::Error(nom::error::Error::new(
+            s,
+            nom::error::ErrorKind::Tag,
+        ))),
+    }
 }
 
 /// 解析する
 pub fn parse_rxd(input: &str) -> nom::IResult<&str, SkRxD> {
     alt((
         // 以下のどれか
-        map((space0, crlf), |_| SkRxD::Void), // 空行
-        rx_ok,                                // OK
-        rx_fail,                              // FAIL
-        rx_event,                             // EVENT
-        rx_epandesc,                          // EPANDESC
-        rx_erxudp,                            // ERXUDP
+        map((space0, line_ending), |_| SkRxD::Void), // 空行
+        rx_ok,                                       // OK
+        rx_fail,                                     // FAIL
+        rx_event,                                    // EVENT
+        rx_epandesc,                                 // EPANDESC
+        rx_erxudp,                                   // ERXUDP
+        rx_eneighbor,                                // ENEIGHBOR
+        // EPANDESCを解析し終えた後に届いた項目行
+        map(epandesc_field, |_| SkRxD::Void),
     ))
     .parse(input)
 }
@@ -253,6 +344,7 @@ fn test3() {
                 sender_port: 0x02CC,
                 destination_port: 0x02CC,
                 senderlla: senderlla,
+                lqi: None,
                 secured: 1,
                 datalen: datalen,
                 data: vec!(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15),
@@ -284,6 +376,7 @@ fn test4() {
                 addr: 0x1234_5678_ABCD_ABCD,
                 lqi: 132,
                 pair_id: 0x1234_ABCD,
+                side: None,
             })
         ),
     );
@@ -313,7 +406,260 @@ fn test4() {
                 addr: 0x1234_5678_ABCD_ABCD,
                 lqi: 132,
                 pair_id: 0x1234_ABCD,
+                side: None,
             }),
         )
     );
 }
+
+#[test]
+fn test5() {
+    // 受信データの長さが足りない
+    let erxudp = "ERXUDP FE80:0000:0000:0000:1234:5678:9ABC:DEF0 FE80:0000:0000:0000:1234:5678:9ABC:DEF0 0E1A 0E1A 123456789ABCDEF0 1 0012 1081000102880105FF017201E704\r\n";
+    assert_eq!(
+        parse_rxd(erxudp).unwrap_err(),
+        nom::Err::Failure(nom::error::Error::new(
+            "1081000102880105FF017201E704\r\n",
+            nom::error::ErrorKind::LengthValue
+        ))
+    );
+}
+
+#[test]
+fn test6() {
+    // 項目の順番が違う, タブで字下げ, 知らない項目がある
+    let epandesc = vec![
+        "EPANDESC\r\n",
+        "\tAddr:12345678ABCDABCD\r\n",
+        "\tPan ID:ABCD\r\n",
+        "\tChannel:3B\r\n",
+        "\tExtra:0\r\n",
+        "\tPairID:1234ABCD\r\n",
+        "\tChannel Page:09\r\n",
+        "\tLQI:84\r\n",
+    ];
+    let expected = SkRxD::Epandesc(skstack::Epandesc {
+        channel: 59,
+        channel_page: 9,
+        pan_id: 0xABCD,
+        addr: 0x1234_5678_ABCD_ABCD,
+        lqi: 132,
+        pair_id: 0x1234_ABCD,
+        side: None,
+    });
+    assert_eq!(parse_rxd(&epandesc.concat()).unwrap(), ("", expected));
+    let incomplete = nom::Err::Incomplete(nom::Needed::new(2));
+    for n in 1..epandesc.len() {
+        assert_eq!(
+            parse_rxd(&epandesc.split_at(n).0.concat()).unwrap_err(),
+            incomplete
+        );
+    }
+    // 値が16進数でない
+    assert!(parse_rxd("EPANDESC\r\n  Channel:XY\r\n").is_err());
+}
+
+#[test]
+fn test7() {
+    // Channel Page が無い
+    let epandesc = [
+        "EPANDESC\r\n",
+        "  Channel:21\r\n",
+        "  Pan ID:0001\r\n",
+        "  Addr:0123456789ABCDEF\r\n",
+        "  LQI:30\r\n",
+        "  PairID:00000001\r\n",
+    ];
+    assert_eq!(
+        parse_rxd(&epandesc.concat()).unwrap(),
+        (
+            "",
+            SkRxD::Epandesc(skstack::Epandesc {
+                channel: 0x21,
+                channel_page: 0,
+                pan_id: 0x0001,
+                addr: 0x0123_4567_89AB_CDEF,
+                lqi: 0x30,
+                pair_id: 1,
+                side: None,
+            })
+        )
+    );
+    // 項目がそろう前に別の応答が来た
+    assert!(parse_rxd("EPANDESC\r\n  Channel:21\r\nOK\r\n").is_err());
+    // 解析し終えた後に届いた項目行は読み飛ばす
+    assert_eq!(parse_rxd("  Side:0\r\n").unwrap(), ("", SkRxD::Void));
+}
+
+#[test]
+fn test8() {
+    // 小文字の16進数
+    let sender = "fe80:0000:0000:0000:1034:5678:9abc:def0";
+    assert_eq!(
+        parse_rxd(&format!("EVENT 21 {} 02\r\n", sender)).unwrap(),
+        (
+            "",
+            SkRxD::Event(skstack::Event {
+                code: 0x21,
+                sender: sender.parse().unwrap(),
+                param: Some(2),
+            })
+        )
+    );
+    assert_eq!(parse_rxd("FAIL ER0a\r\n").unwrap(), ("", SkRxD::Fail(10)));
+    assert_eq!(u8_hex_digit("ff").unwrap(), ("", 0xff));
+    assert_eq!(u8_hex_digit2("ab").unwrap(), ("", 0xab));
+    assert_eq!(u16_hex_digit("0e1a").unwrap(), ("", 0x0e1a));
+    assert_eq!(
+        u64_hex_digit("123456789abcdef0").unwrap(),
+        ("", 0x1234_5678_9abc_def0)
+    );
+    let erxudp = format!(
+        "ERXUDP {0} {0} 0e1a 0e1a 123456789abcdef0 1 0004 1081abcd\r\n",
+        sender
+    );
+    match parse_rxd(&erxudp).unwrap() {
+        ("", SkRxD::Erxudp(a)) => {
+            assert_eq!(a.destination_port, 0x0e1a);
+            assert_eq!(a.senderlla, 0x1234_5678_9abc_def0);
+            assert_eq!(a.data, vec![0x10, 0x81, 0xab, 0xcd]);
+        }
+        other => panic!("{:?}", other),
+    }
+    let epandesc = "EPANDESC\r\n  Channel:3b\r\n  Channel Page:09\r\n  Pan ID:abcd\r\n  Addr:12345678abcdabcd\r\n  LQI:8a\r\n  PairID:1234abcd\r\n";
+    assert_eq!(
+        parse_rxd(epandesc).unwrap(),
+        (
+            "",
+            SkRxD::Epandesc(skstack::Epandesc {
+                channel: 0x3b,
+                channel_page: 9,
+                pan_id: 0xabcd,
+                addr: 0x1234_5678_abcd_abcd,
+                lqi: 0x8a,
+                pair_id: 0x1234_abcd,
+                side: None,
+            })
+        )
+    );
+}
+
+#[test]
+fn test9() {
+    // LFだけの行末
+    assert_eq!(parse_rxd("OK\n").unwrap(), ("", SkRxD::Ok));
+    assert_eq!(parse_rxd("\n").unwrap(), ("", SkRxD::Void));
+    assert_eq!(parse_rxd("FAIL ER10\n").unwrap(), ("", SkRxD::Fail(16)));
+    let sender = "FE80:0000:0000:0000:1034:5678:9ABC:DEF0";
+    assert_eq!(
+        parse_rxd(&format!("EVENT 21 {} 02\nOK\r\n", sender)).unwrap(),
+        (
+            "OK\r\n",
+            SkRxD::Event(skstack::Event {
+                code: 0x21,
+                sender: sender.parse().unwrap(),
+                param: Some(2),
+            })
+        )
+    );
+    let epandesc = "EPANDESC\n  Channel:3B\n  Channel Page:09\n  Pan ID:ABCD\n  Addr:12345678ABCDABCD\n  LQI:84\n  PairID:1234ABCD\n";
+    assert!(matches!(
+        parse_rxd(epandesc).unwrap(),
+        ("", SkRxD::Epandesc(_))
+    ));
+}
+
+#[test]
+fn test10() {
+    // Side がある
+    let epandesc = [
+        "EPANDESC\r\n",
+        "  Channel:21\r\n",
+        "  Channel Page:09\r\n",
+        "  Pan ID:0001\r\n",
+        "  Addr:0123456789ABCDEF\r\n",
+        "  LQI:30\r\n",
+        "  Side:0\r\n",
+        "  PairID:00000001\r\n",
+    ];
+    assert_eq!(
+        parse_rxd(&epandesc.concat()).unwrap(),
+        (
+            "",
+            SkRxD::Epandesc(skstack::Epandesc {
+                channel: 0x21,
+                channel_page: 9,
+                pan_id: 0x0001,
+                addr: 0x0123_4567_89AB_CDEF,
+                lqi: 0x30,
+                pair_id: 1,
+                side: Some(0),
+            })
+        )
+    );
+}
+
+#[test]
+fn test11() {
+    // 2.4GHz/サブGHz両対応のドングルは LQI と Side を付ける
+    let sender = "FE80:0000:0000:0000:1234:5678:9ABC:DEF0";
+    let erxudp = format!(
+        "ERXUDP {0} {0} 0E1A 0E1A 123456789ABCDEF0 A4 1 0 0004 1081ABCD\r\n",
+        sender
+    );
+    let ("", SkRxD::Erxudp(a)) = parse_rxd(&erxudp).unwrap() else {
+        panic!("ERXUDP is expected")
+    };
+    assert_eq!(a.lqi, Some(0xa4));
+    assert_eq!(a.secured, 1);
+    assert_eq!(a.data, vec![0x10, 0x81, 0xab, 0xcd]);
+    // 付けないドングル
+    let erxudp = format!(
+        "ERXUDP {0} {0} 0E1A 0E1A 123456789ABCDEF0 1 0004 1081ABCD\r\n",
+        sender
+    );
+    let ("", SkRxD::Erxudp(a)) = parse_rxd(&erxudp).unwrap() else {
+        panic!("ERXUDP is expected")
+    };
+    assert_eq!(a.lqi, None);
+    assert_eq!(a.secured, 1);
+}
+
+#[test]
+fn test12() {
+    // 近隣キャッシュ
+    let eneighbor = [
+        "ENEIGHBOR\r\n",
+        "FE80:0000:0000:0000:021D:1290:0003:C890 001D129000030C89 FFFE\r\n",
+        "FE80:0000:0000:0000:1034:5678:9ABC:DEF0 123456789ABCDEF0 0001\r\n",
+        "OK\r\n",
+    ];
+    assert_eq!(
+        parse_rxd(&eneighbor.concat()).unwrap(),
+        (
+            "",
+            SkRxD::NeighborTable(vec![
+                skstack::Neighbor {
+                    address: "FE80::21D:1290:3:C890".parse().unwrap(),
+                    addr64: 0x001D_1290_0003_0C89,
+                    addr16: 0xfffe,
+                },
+                skstack::Neighbor {
+                    address: "FE80::1034:5678:9ABC:DEF0".parse().unwrap(),
+                    addr64: 0x1234_5678_9ABC_DEF0,
+                    addr16: 0x0001,
+                },
+            ])
+        )
+    );
+    // OK が届くまで続きの行を待つ
+    assert!(matches!(
+        parse_rxd(&eneighbor[..3].concat()),
+        Err(nom::Err::Incomplete(_))
+    ));
+    // 空の近隣キャッシュ
+    assert_eq!(
+        parse_rxd("ENEIGHBOR\r\nOK\r\n").unwrap(),
+        ("", SkRxD::NeighborTable(vec![]))
+    );
+}
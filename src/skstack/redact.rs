@@ -0,0 +1,81 @@
+// ログに出すコマンドの秘密情報をマスクする
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+
+/// マスクする秘密情報(コマンド, 秘密情報の文字数)
+/// 秘密情報を送るコマンドが増えたらここに追加する
+pub const SENSITIVE_COMMANDS: [(&str, usize); 2] = [
+    // Bルート認証パスワード
+    ("SKSETPWD C ", 12),
+    // Bルート認証ID
+    ("SKSETRBID ", 32),
+];
+
+/// マスクに使う文字
+const MASK_CHAR: char = '#';
+
+/// 秘密情報をマスクする
+/// 秘密情報が文字列の末尾で途切れていても、途切れた所までをマスクする
+pub fn mask_credentials(buf: &mut String) {
+    for (prefix, length) in SENSITIVE_COMMANDS {
+        mask_after(buf, prefix, length);
+    }
+}
+
+/// prefix 以降の length 文字をマスクする
+fn mask_after(buf: &mut String, prefix: &str, length: usize) {
+    let mut from = 0;
+    while let Some(pos) = buf[from..].find(prefix) {
+        let start = from + pos + prefix.len();
+        // 文字単位で数えて最大length文字
+        let (end, count) = buf[start..]
+            .char_indices()
+            .take(length)
+            .fold((start, 0), |(_, n), (i, c)| {
+                (start + i + c.len_utf8(), n + 1)
+            });
+        buf.replace_range(start..end, &MASK_CHAR.to_string().repeat(count));
+        from = start + count;
+    }
+}
+
+#[test]
+fn test1() {
+    // 文字列の末尾にパスワードがある
+    let mut s = "Tx-> SKSETPWD C PASSWORD1234\r\n".to_string();
+    mask_credentials(&mut s);
+    assert_eq!(s, "Tx-> SKSETPWD C ############\r\n");
+    // 改行がない
+    let mut s = "SKSETPWD C PASSWORD1234".to_string();
+    mask_credentials(&mut s);
+    assert_eq!(s, "SKSETPWD C ############");
+    // パスワードが途中で途切れている
+    let mut s = "SKSETPWD C PASS".to_string();
+    mask_credentials(&mut s);
+    assert_eq!(s, "SKSETPWD C ####");
+    // パスワードがない
+    let mut s = "SKSETPWD C ".to_string();
+    mask_credentials(&mut s);
+    assert_eq!(s, "SKSETPWD C ");
+    // パスワードの後ろに続く文字列はそのまま
+    let mut s = "SKSETPWD C PASSWORD1234 SKSETPWD C ABCDEFGHIJKLMN".to_string();
+    mask_credentials(&mut s);
+    assert_eq!(s, "SKSETPWD C ############ SKSETPWD C ############MN");
+    // 該当なし
+    let mut s = "SKVER\r\n".to_string();
+    mask_credentials(&mut s);
+    assert_eq!(s, "SKVER\r\n");
+}
+
+#[test]
+fn test2() {
+    // Bルート認証ID
+    let mut s = "Tx-> SKSETRBID 0123456789ABCDEF0123456789ABCDEF\\r\\n".to_string();
+    mask_credentials(&mut s);
+    assert_eq!(s, "Tx-> SKSETRBID ################################\\r\\n");
+    // 途中で途切れている
+    let mut s = "SKSETRBID 0123".to_string();
+    mask_credentials(&mut s);
+    assert_eq!(s, "SKSETRBID ####");
+}
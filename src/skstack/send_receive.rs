@@ -3,19 +3,129 @@
 // SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
 //
 use crate::{
-    echonetlite::EchonetliteFrame,
-    skstack::{SkRxD, parser},
+    echonetlite::{EchonetliteEdata, EchonetliteFrame, Eoj, Esv, superclass},
+    skstack::{ECHONETLITE_PORT, Erxudp, Neighbor, SkRxD, parser, redact},
 };
+use chrono::NaiveDateTime;
 use std::io::{self, BufRead, BufReader};
 use std::net::Ipv6Addr;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// 送信するコマンドをログに出す
+fn trace_command(command: &[u8]) {
+    // journaldに送る前にマスクする
+    let mut line = command.escape_ascii().to_string();
+    redact::mask_credentials(&mut line);
+    tracing::trace!(target:"Tx->","{}",line);
+}
+
+/// 一時的な書き込みエラー(WouldBlock, Interrupted)が続いたときに再試行する回数
+pub const SEND_MAX_RETRY: u32 = 5;
+
+/// 一時的な書き込みエラーから再試行するまでの待ち時間
+const SEND_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/// USBシリアル変換器が負荷の高いときに返す一時的な書き込みエラーか
+fn is_transient_write_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+    )
+}
 
 /// コマンドを送信する
+/// 一部しか書き込めなければ残りを書き込む
+/// 一時的な書き込みエラーは SEND_MAX_RETRY 回まで続けて再試行する
 pub fn send(w: &mut dyn io::Write, command: &[u8]) -> io::Result<()> {
-    tracing::trace!(target:"Tx->","{}",command.escape_ascii());
-    w.write_all(command)
+    trace_command(command);
+    let mut written = 0;
+    let mut retry = 0;
+    while written < command.len() {
+        match w.write(&command[written..]) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                written += n;
+                retry = 0;
+            }
+            Err(e) if is_transient_write_error(&e) && retry < SEND_MAX_RETRY => {
+                retry += 1;
+                tracing::debug!("serial write retry {retry}/{SEND_MAX_RETRY}, reason: {e}");
+                std::thread::sleep(SEND_RETRY_DELAY);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// 1行のコマンドに CRLF を付けて送信する
+/// コマンド中に CR, LF, 非ASCII文字があれば送信せずに io::ErrorKind::InvalidInput を返す
+/// SKSENDTO のようにバイナリを含むコマンドは send() を使う
+pub fn send_line(w: &mut dyn io::Write, command: &str) -> io::Result<()> {
+    if let Some(c) = command
+        .chars()
+        .find(|c| matches!(c, '\r' | '\n') || !c.is_ascii())
+    {
+        // パスワードを含むかもしれないのでコマンドは出さない
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("command contains {:?}", c),
+        ));
+    }
+    send(w, format!("{command}\r\n").as_bytes())
+}
+
+/// コマンドを送信する(非同期)
+/// 一時的な書き込みエラーは send() と同じく再試行する
+pub async fn send_async<W: AsyncWrite + Unpin + ?Sized>(
+    w: &mut W,
+    command: &[u8],
+) -> io::Result<()> {
+    trace_command(command);
+    let mut written = 0;
+    let mut retry = 0;
+    while written < command.len() {
+        match w.write(&command[written..]).await {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => {
+                written += n;
+                retry = 0;
+            }
+            Err(e) if is_transient_write_error(&e) && retry < SEND_MAX_RETRY => {
+                retry += 1;
+                tracing::debug!("serial write retry {retry}/{SEND_MAX_RETRY}, reason: {e}");
+                tokio::time::sleep(SEND_RETRY_DELAY).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    w.flush().await
+}
+
+/// 1つの応答として連結する最大行数(最も長いEPANDESCで7行)
+pub const RECEIVE_MAX_LINES: usize = 16;
+
+/// 1つの応答として連結する最大バイト数
+pub const RECEIVE_MAX_BYTES: usize = 4096;
+
+/// 解析できなかった受信データ
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("unparsed response \"{}\"", .0.escape_debug())]
+pub struct UnparsedResponse(pub String);
+
+impl UnparsedResponse {
+    /// io::Error から取り出す
+    pub fn from_io_error(e: &io::Error) -> Option<&Self> {
+        e.get_ref().and_then(|inner| inner.downcast_ref::<Self>())
+    }
 }
 
 /// 結果を受信する
+/// 解析できないまま最大行数か最大バイト数を超えたら io::ErrorKind::InvalidData を返す
+/// 解析できない行を受信したら UnparsedResponse を包んだ io::ErrorKind::InvalidData を返す
+/// (呼び出し側は次の受信で同期を取り直せる)
 pub fn receive(r: &mut BufReader<dyn io::Read>) -> io::Result<SkRxD> {
     let mut linebuf = Vec::<String>::new();
     loop {
@@ -23,16 +133,185 @@ pub fn receive(r: &mut BufReader<dyn io::Read>) -> io::Result<SkRxD> {
         let _ = r.read_line(&mut line)?;
         tracing::trace!(target:"<-Rx","{}", line.escape_debug());
         linebuf.push(line);
-        match parser::parse_rxd(linebuf.concat().as_ref()) {
-            Ok((_s, r)) => return Ok(r),
-            Err(nom::Err::Incomplete(_)) => continue, // つづけて次行を読み込む
-            Err(e) => tracing::trace!(target:"parser","{:?}", e),
+        let text = linebuf.concat();
+        if let Some(result) = parse_lines(&text, linebuf.len()) {
+            return result;
+        }
+        // つづけて次行を読み込む
+    }
+}
+
+/// 連結した受信行(lines行)を解析する
+/// 続きの行が必要なら None を返す
+pub(crate) fn parse_lines(text: &str, lines: usize) -> Option<io::Result<SkRxD>> {
+    match parser::parse_rxd(text) {
+        Ok((_s, r)) => Some(Ok(r)),
+        Err(nom::Err::Incomplete(_))
+            if lines >= RECEIVE_MAX_LINES || text.len() >= RECEIVE_MAX_BYTES =>
+        {
+            Some(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "incomplete response discarded ({} lines, {} bytes)",
+                    lines,
+                    text.len()
+                ),
+            )))
+        }
+        Err(nom::Err::Incomplete(_)) => None,
+        Err(nom::Err::Failure(e)) if e.code == nom::error::ErrorKind::LengthValue => {
+            Some(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    r#"ERXUDP data length does not match datalen "{}""#,
+                    text.escape_debug()
+                ),
+            )))
+        }
+        Err(e) => {
+            tracing::trace!(target:"parser","{:?}", e);
+            Some(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                UnparsedResponse(text.to_string()),
+            )))
+        }
+    }
+}
+
+/// 受信し続けるイテレーター
+/// タイムアウトは io::ErrorKind::TimedOut, 解析できない受信データは io::ErrorKind::InvalidData
+/// のエラーとして返して受信を続ける
+/// それ以外のエラーを返すか, 読み込むものが無くなったら終わる
+pub struct Responses<'a> {
+    reader: &'a mut BufReader<dyn io::Read>,
+    finished: bool,
+}
+
+impl Iterator for Responses<'_> {
+    type Item = io::Result<SkRxD>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        // 読み込むものが無い
+        if let Ok([]) = self.reader.fill_buf() {
+            self.finished = true;
+            return None;
+        }
+        let result = receive(self.reader);
+        if let Err(e) = &result
+            && !matches!(
+                e.kind(),
+                io::ErrorKind::TimedOut | io::ErrorKind::InvalidData
+            )
+        {
+            self.finished = true;
         }
-        linebuf.clear();
+        Some(result)
     }
 }
 
+/// 受信し続ける
+pub fn responses(reader: &mut BufReader<dyn io::Read>) -> Responses<'_> {
+    Responses {
+        reader,
+        finished: false,
+    }
+}
+
+/// コマンドの結果を受信する
+/// コマンドのエコーバックなど解析できない行は読み飛ばす
+pub fn receive_skip_unparsed(r: &mut BufReader<dyn io::Read>) -> io::Result<SkRxD> {
+    loop {
+        match receive(r) {
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => tracing::debug!("{}", e),
+            result => return result,
+        }
+    }
+}
+
+/// SKVERの応答を待つ時間
+pub const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// SKVERコマンドを送ってSKSTACKのバージョン("EVER 1.2.10" の "1.2.10")を得る
+/// 応答がなければ io::ErrorKind::TimedOut, FAIL応答なら io::ErrorKind::Other を返す
+pub fn version(
+    reader: &mut BufReader<dyn io::Read>,
+    writer: &mut dyn io::Write,
+) -> io::Result<String> {
+    send_line(writer, "SKVER")?;
+    let mut version: Option<String> = None;
+    let deadline = Instant::now() + VERSION_TIMEOUT;
+    for received in responses(reader) {
+        match received {
+            Ok(SkRxD::Ok) => {
+                return version.ok_or(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "EVER is not received",
+                ));
+            }
+            Ok(SkRxD::Fail(code)) => {
+                return Err(io::Error::other(format!("SKVER FAIL ER{code:02X}")));
+            }
+            Ok(_) => {}
+            // EVER行はパーサーが解析しないので解析できなかった行から取り出す
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                if let Some(ever) = UnparsedResponse::from_io_error(&e)
+                    .and_then(|a| a.0.trim_end().strip_prefix("EVER "))
+                {
+                    version = Some(ever.trim().to_string());
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+            Err(e) => return Err(e),
+        }
+        if Instant::now() >= deadline {
+            break;
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::TimedOut, "SKVER no response"))
+}
+
+/// SKTABLE E の応答を待つ時間
+pub const NEIGHBOR_TABLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// SKTABLE E コマンドを送って近隣キャッシュを得る
+/// スマートメーターとの通信が途絶えたときに, まだ到達できるとみなしているかを調べられる
+/// 応答がなければ io::ErrorKind::TimedOut, FAIL応答なら io::ErrorKind::Other を返す
+pub fn neighbor_table(
+    reader: &mut BufReader<dyn io::Read>,
+    writer: &mut dyn io::Write,
+) -> io::Result<Vec<Neighbor>> {
+    send_line(writer, "SKTABLE E")?;
+    let deadline = Instant::now() + NEIGHBOR_TABLE_TIMEOUT;
+    for received in responses(reader) {
+        match received {
+            Ok(SkRxD::NeighborTable(neighbors)) => return Ok(neighbors),
+            Ok(SkRxD::Fail(code)) => {
+                return Err(io::Error::other(format!("SKTABLE FAIL ER{code:02X}")));
+            }
+            Ok(_) => {}
+            // コマンドのエコーバックなど解析できない行は読み飛ばす
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => tracing::debug!("{}", e),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+            Err(e) => return Err(e),
+        }
+        if Instant::now() >= deadline {
+            break;
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "SKTABLE no response",
+    ))
+}
+
+/// SKSENDTOで送信できる最大データ長(0x04D0)
+pub const SKSENDTO_MAX_DATALEN: usize = 1232;
+
 /// EchonetliteフレームからSKSENDTOコマンドを作る
+/// 送信できる最大データ長を超えるフレームはエラーになる
 pub fn command_from_echonetliteframe(
     sender: &Ipv6Addr,
     frame: &EchonetliteFrame,
@@ -42,12 +321,503 @@ pub fn command_from_echonetliteframe(
         .with_big_endian()
         .with_fixed_int_encoding();
     let payload = bincode::encode_to_vec(frame, config)?;
+    if payload.len() > SKSENDTO_MAX_DATALEN {
+        return Err(bincode::error::EncodeError::OtherString(format!(
+            "Echonetlite frame is too long to send ({} bytes > {} bytes)",
+            payload.len(),
+            SKSENDTO_MAX_DATALEN
+        )));
+    }
     let sksendto = format!(
         "SKSENDTO 1 {} {:04X} 1 {:04X} ",
         sender_address,
-        0x0e1a,
+        ECHONETLITE_PORT,
         payload.len(),
     );
     let command = [sksendto.as_bytes(), &payload].concat();
     Ok(command)
 }
+
+/// スマートメーターから読み出したプロパティ値
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropertyValue {
+    pub epc: u8,
+    pub edt: Vec<u8>,
+}
+
+impl PropertyValue {
+    /// EDATAとして参照する
+    pub fn as_edata(&self) -> EchonetliteEdata<'_> {
+        EchonetliteEdata {
+            epc: self.epc,
+            pdc: self.edt.len() as u8,
+            edt: &self.edt,
+        }
+    }
+}
+
+/// Echonetliteメッセージを受信する
+/// それ以外の受信やタイムアウトなら None
+fn receive_echonetlite(reader: &mut BufReader<dyn io::Read>) -> io::Result<Option<Erxudp>> {
+    match receive(reader) {
+        Ok(SkRxD::Erxudp(erxudp)) if erxudp.destination_port == ECHONETLITE_PORT => {
+            Ok(Some(erxudp))
+        }
+        Ok(r) => {
+            tracing::trace!("{:?}", r);
+            Ok(None)
+        }
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => Ok(None), // タイムアウトエラーは無視する
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            tracing::warn!("{}", e);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// プロパティ値読み出し要求の応答を待つ時間
+pub const REQUEST_PROPERTY_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// スマートメーターにプロパティ値読み出し要求(Get)を送って応答を待つ
+/// 応答のEchonetliteフレームから epc のプロパティ値を取り出して返す
+/// 応答がなければ io::ErrorKind::TimedOut, Get_SNA応答なら io::ErrorKind::Unsupported
+pub fn request_property(
+    reader: &mut BufReader<dyn io::Read>,
+    writer: &mut dyn io::Write,
+    sender: &Ipv6Addr,
+    epc: u8,
+) -> io::Result<PropertyValue> {
+    request_properties(reader, writer, sender, &[epc])?
+        .into_iter()
+        .next()
+        .ok_or(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("EPC:0x{:02X} Get_SNA(property is not available)", epc),
+        ))
+}
+
+/// スマートメーターに複数のEPCをまとめたプロパティ値読み出し要求(Get)を送って応答を待つ
+/// 応答のあったプロパティ値を要求した順に返す
+/// Get_SNA応答で読み出せなかったプロパティ値は含まない
+/// 一部しか応答がないまま時間切れになった場合は応答のあった分だけ返す
+pub fn request_properties(
+    reader: &mut BufReader<dyn io::Read>,
+    writer: &mut dyn io::Write,
+    sender: &Ipv6Addr,
+    epcs: &[u8],
+) -> io::Result<Vec<PropertyValue>> {
+    let frame = EchonetliteFrame {
+        ehd: 0x1081, // 0x1081 = echonet lite
+        tid: 1,      // tid
+        seoj: Eoj::HOME_CONTROLLER,
+        deoj: Eoj::SMART_METER,
+        esv: Esv::Get.into(), // get要求
+        opc: epcs.len() as u8,
+        edata: epcs
+            .iter()
+            .map(|epc| EchonetliteEdata {
+                epc: *epc,
+                ..Default::default()
+            })
+            .collect(),
+    };
+    let command = command_from_echonetliteframe(sender, &frame)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    send(writer, &command)?;
+
+    let config = bincode::config::standard()
+        .with_big_endian()
+        .with_fixed_int_encoding();
+    // 要求したEPCの応答(Get_SNAで読み出せなかったものは None)
+    let mut answers: Vec<Option<Option<PropertyValue>>> = vec![None; epcs.len()];
+    let deadline = Instant::now() + REQUEST_PROPERTY_TIMEOUT;
+    while Instant::now() < deadline && answers.iter().any(Option::is_none) {
+        let Some(erxudp) = receive_echonetlite(reader)? else {
+            continue;
+        };
+        let (frame, _len): (EchonetliteFrame, usize) =
+            bincode::borrow_decode_from_slice(&erxudp.data, config)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let esv = Esv::try_from(frame.esv);
+        if !matches!(esv, Ok(Esv::GetRes | Esv::GetSna)) {
+            // 別の要求に対する応答や通知
+            tracing::trace!("{}", frame);
+            continue;
+        }
+        for edata in frame.edata.iter() {
+            let Some(idx) = epcs.iter().position(|epc| *epc == edata.epc) else {
+                continue;
+            };
+            answers[idx] = match esv {
+                // Get_SNA応答では読み出せなかったプロパティのPDCが0になる
+                Ok(Esv::GetSna) if edata.pdc == 0 => {
+                    tracing::warn!("EPC:0x{:02X} Get_SNA(property is not available)", edata.epc);
+                    Some(None)
+                }
+                _ => Some(Some(PropertyValue {
+                    epc: edata.epc,
+                    edt: edata.edt.to_vec(),
+                })),
+            };
+        }
+    }
+    if answers.iter().all(Option::is_none) {
+        return Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("EPC:{:02X?} no response", epcs),
+        ));
+    }
+    Ok(answers.into_iter().flatten().flatten().collect())
+}
+
+/// スマートメーターにプロパティ値書き込み要求(SetC)を送って応答を待つ
+/// Set_res応答なら Ok
+/// 応答がなければ io::ErrorKind::TimedOut, Set_SNA応答なら io::ErrorKind::PermissionDenied
+pub fn set_properties(
+    reader: &mut BufReader<dyn io::Read>,
+    writer: &mut dyn io::Write,
+    sender: &Ipv6Addr,
+    values: &[PropertyValue],
+) -> io::Result<()> {
+    let epcs = values.iter().map(|v| v.epc).collect::<Vec<u8>>();
+    let frame = EchonetliteFrame {
+        ehd: 0x1081, // 0x1081 = echonet lite
+        tid: 1,      // tid
+        seoj: Eoj::HOME_CONTROLLER,
+        deoj: Eoj::SMART_METER,
+        esv: Esv::SetC.into(), // 書き込み要求(応答要)
+        opc: values.len() as u8,
+        edata: values.iter().map(PropertyValue::as_edata).collect(),
+    };
+    let command = command_from_echonetliteframe(sender, &frame)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    send(writer, &command)?;
+
+    let config = bincode::config::standard()
+        .with_big_endian()
+        .with_fixed_int_encoding();
+    let deadline = Instant::now() + REQUEST_PROPERTY_TIMEOUT;
+    while Instant::now() < deadline {
+        let Some(erxudp) = receive_echonetlite(reader)? else {
+            continue;
+        };
+        let (frame, _len): (EchonetliteFrame, usize) =
+            bincode::borrow_decode_from_slice(&erxudp.data, config)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if !frame.edata.iter().any(|edata| epcs.contains(&edata.epc)) {
+            // 別の要求に対する応答や通知
+            tracing::trace!("{}", frame);
+            continue;
+        }
+        match Esv::try_from(frame.esv) {
+            Ok(Esv::SetRes) => return Ok(()),
+            // Set_SNA応答では書き込めなかったプロパティのPDCが0でない
+            Ok(Esv::SetCSna) => {
+                let rejected = frame
+                    .edata
+                    .iter()
+                    .filter(|edata| edata.pdc != 0)
+                    .map(|edata| edata.epc)
+                    .collect::<Vec<u8>>();
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("EPC:{:02X?} Set_SNA(property is not writable)", rejected),
+                ));
+            }
+            _ => tracing::trace!("{}", frame),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("EPC:{:02X?} no response", epcs),
+    ))
+}
+
+/// スマートメーターの時計(0x98 現在年月日設定, 0x97 現在時刻設定)を書き込む
+/// 書き込めなければ set_properties と同じエラーを返す
+pub fn set_meter_clock(
+    reader: &mut BufReader<dyn io::Read>,
+    writer: &mut dyn io::Write,
+    sender: &Ipv6Addr,
+    now: &NaiveDateTime,
+) -> io::Result<()> {
+    let values = [
+        PropertyValue {
+            epc: superclass::CurrentDateSetting::EPC,
+            edt: superclass::CurrentDateSetting(now.date()).edt().to_vec(),
+        },
+        PropertyValue {
+            epc: superclass::CurrentTimeSetting::EPC,
+            edt: superclass::CurrentTimeSetting(now.time()).edt().to_vec(),
+        },
+    ];
+    set_properties(reader, writer, sender, &values)
+}
+
+#[test]
+fn test1() {
+    let sender: Ipv6Addr = "FE80:0000:0000:0000:1234:5678:9ABC:DEF0".parse().unwrap();
+    // 積算電力量単位(0xe1)のGet_Res応答
+    let rxd = format!(
+        "OK\r\nERXUDP {0} {0} 0E1A 0E1A 123456789ABCDEF0 1 000F 1081000102880105FF017201E10101\r\n",
+        "FE80:0000:0000:0000:1234:5678:9ABC:DEF0"
+    );
+    let mut reader = BufReader::new(io::Cursor::new(rxd));
+    let mut writer = Vec::<u8>::new();
+
+    let value = request_property(&mut reader, &mut writer, &sender, 0xe1).unwrap();
+    assert_eq!(
+        value,
+        PropertyValue {
+            epc: 0xe1,
+            edt: vec![0x01]
+        }
+    );
+    assert!(writer.starts_with(b"SKSENDTO 1 FE80:0000:0000:0000:1234:5678:9ABC:DEF0 0E1A 1 000E "));
+}
+
+#[test]
+fn test2() {
+    let sender: Ipv6Addr = "FE80:0000:0000:0000:1234:5678:9ABC:DEF0".parse().unwrap();
+    // 0xe1, 0xd3, 0xd7 のGet_SNA応答(0xd3は読み出し不可)
+    let rxd = format!(
+        "ERXUDP {0} {0} 0E1A 0E1A 123456789ABCDEF0 1 0014 1081000102880105FF015203E10101D300D70106\r\n",
+        "FE80:0000:0000:0000:1234:5678:9ABC:DEF0"
+    );
+    let mut reader = BufReader::new(io::Cursor::new(rxd));
+    let mut writer = Vec::<u8>::new();
+
+    let values =
+        request_properties(&mut reader, &mut writer, &sender, &[0xe1, 0xd3, 0xd7]).unwrap();
+    assert_eq!(
+        values,
+        vec![
+            PropertyValue {
+                epc: 0xe1,
+                edt: vec![0x01]
+            },
+            PropertyValue {
+                epc: 0xd7,
+                edt: vec![0x06]
+            },
+        ]
+    );
+}
+
+#[test]
+fn test3() {
+    // 途中で途切れたEPANDESCは最大行数で打ち切られる
+    let rxd = "EPANDESC\r\n  Channel:21\r\n  Channel Page:09\r\n";
+    let mut reader = BufReader::new(io::Cursor::new(rxd));
+    let e = receive(&mut reader).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test4() {
+    // 解析できない行はその内容を返す
+    let mut reader = BufReader::new(io::Cursor::new("SKVER\r\nOK\r\n"));
+    let e = receive(&mut reader).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(
+        UnparsedResponse::from_io_error(&e),
+        Some(&UnparsedResponse("SKVER\r\n".to_string()))
+    );
+    // 続けて受信すると同期が取り直される
+    assert_eq!(receive(&mut reader).unwrap(), SkRxD::Ok);
+    // エコーバックを読み飛ばす
+    let mut reader = BufReader::new(io::Cursor::new("SKRESET\r\nOK\r\n"));
+    assert_eq!(receive_skip_unparsed(&mut reader).unwrap(), SkRxD::Ok);
+}
+
+#[test]
+fn test5() {
+    // 受信データの長さがdatalenと一致しない
+    let rxd = "ERXUDP FE80:0000:0000:0000:1234:5678:9ABC:DEF0 FE80:0000:0000:0000:1234:5678:9ABC:DEF0 0E1A 0E1A 123456789ABCDEF0 1 0012 1081000102880105FF017201E704\r\n";
+    let mut reader = BufReader::new(io::Cursor::new(rxd));
+    let e = receive(&mut reader).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert!(e.to_string().contains("datalen"));
+}
+
+#[test]
+fn test6() {
+    let sender: Ipv6Addr = "FE80:0000:0000:0000:1234:5678:9ABC:DEF0".parse().unwrap();
+    let edt = [0u8; 240];
+    let frame = |n: usize| EchonetliteFrame {
+        ehd: 0x1081,
+        tid: 1,
+        seoj: Eoj::HOME_CONTROLLER,
+        deoj: Eoj::SMART_METER,
+        esv: 0x61,
+        opc: n as u8,
+        edata: vec![
+            EchonetliteEdata {
+                epc: 0xe5,
+                pdc: edt.len() as u8,
+                edt: &edt,
+            };
+            n
+        ],
+    };
+    // 12 + 242 * 5 = 1222 bytes は送信できる
+    let command = command_from_echonetliteframe(&sender, &frame(5)).unwrap();
+    assert!(
+        command.starts_with(b"SKSENDTO 1 FE80:0000:0000:0000:1234:5678:9ABC:DEF0 0E1A 1 04C6 ")
+    );
+    // 12 + 242 * 6 = 1464 bytes は長すぎる
+    assert!(matches!(
+        command_from_echonetliteframe(&sender, &frame(6)),
+        Err(bincode::error::EncodeError::OtherString(_))
+    ));
+}
+
+#[test]
+fn test7() {
+    // 解析できない行があっても続けて, 読み終えたら終わる
+    let rxd = "OK\r\nSKVER\r\nEVENT 21 FE80:0000:0000:0000:1234:5678:9ABC:DEF0 02\r\n";
+    let mut reader = BufReader::new(io::Cursor::new(rxd));
+    let received = responses(&mut reader).collect::<Vec<io::Result<SkRxD>>>();
+    assert_eq!(received.len(), 3);
+    assert_eq!(received[0].as_ref().unwrap(), &SkRxD::Ok);
+    assert_eq!(
+        received[1].as_ref().unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+    assert!(matches!(received[2], Ok(SkRxD::Event(_))));
+}
+
+#[test]
+fn test8() {
+    use chrono::NaiveDate;
+    let sender: Ipv6Addr = "FE80:0000:0000:0000:1234:5678:9ABC:DEF0".parse().unwrap();
+    let now = NaiveDate::from_ymd_opt(2025, 7, 13)
+        .and_then(|a| a.and_hms_opt(12, 34, 56))
+        .unwrap();
+    // Set_res応答
+    let rxd = format!(
+        "ERXUDP {0} {0} 0E1A 0E1A 123456789ABCDEF0 1 0010 1081000102880105FF01710298009700\r\n",
+        "FE80:0000:0000:0000:1234:5678:9ABC:DEF0"
+    );
+    let mut reader = BufReader::new(io::Cursor::new(rxd));
+    let mut writer = Vec::<u8>::new();
+    set_meter_clock(&mut reader, &mut writer, &sender, &now).unwrap();
+    let header = b"SKSENDTO 1 FE80:0000:0000:0000:1234:5678:9ABC:DEF0 0E1A 1 0016 ";
+    assert!(writer.starts_with(header));
+    assert_eq!(
+        &writer[header.len()..],
+        &[
+            0x10, 0x81, 0x00, 0x01, 0x05, 0xff, 0x01, 0x02, 0x88, 0x01, 0x61, 0x02, 0x98, 0x04,
+            0x07, 0xe9, 0x07, 0x0d, 0x97, 0x02, 0x0c, 0x22
+        ]
+    );
+    // Set_SNA応答(書き込めなかったプロパティはPDCが0でない)
+    let rxd = format!(
+        "ERXUDP {0} {0} 0E1A 0E1A 123456789ABCDEF0 1 0016 1081000102880105FF015102980407E9070D97020C22\r\n",
+        "FE80:0000:0000:0000:1234:5678:9ABC:DEF0"
+    );
+    let mut reader = BufReader::new(io::Cursor::new(rxd));
+    let mut writer = Vec::<u8>::new();
+    let e = set_meter_clock(&mut reader, &mut writer, &sender, &now).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    assert!(e.to_string().contains("[98, 97]"));
+}
+
+#[test]
+fn test9() {
+    // エコーバック, EVER, OK の順に届く
+    let mut reader = BufReader::new(io::Cursor::new("SKVER\r\nEVER 1.2.10\r\nOK\r\n"));
+    let mut writer = Vec::<u8>::new();
+    assert_eq!(version(&mut reader, &mut writer).unwrap(), "1.2.10");
+    assert_eq!(writer, b"SKVER\r\n");
+    // FAIL応答
+    let mut reader = BufReader::new(io::Cursor::new("FAIL ER04\r\n"));
+    let mut writer = Vec::<u8>::new();
+    assert!(version(&mut reader, &mut writer).is_err());
+    // 応答がない
+    let mut reader = BufReader::new(io::Cursor::new(""));
+    let mut writer = Vec::<u8>::new();
+    let e = version(&mut reader, &mut writer).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+}
+
+#[test]
+fn test10() {
+    // エコーバック, ENEIGHBOR, OK の順に届く
+    let rxd = "SKTABLE E\r\nENEIGHBOR\r\nFE80:0000:0000:0000:1034:5678:9ABC:DEF0 123456789ABCDEF0 FFFF\r\nOK\r\n";
+    let mut reader = BufReader::new(io::Cursor::new(rxd));
+    let mut writer = Vec::<u8>::new();
+    let neighbors = neighbor_table(&mut reader, &mut writer).unwrap();
+    assert_eq!(writer, b"SKTABLE E\r\n");
+    assert_eq!(
+        neighbors,
+        vec![Neighbor {
+            address: "FE80::1034:5678:9ABC:DEF0".parse().unwrap(),
+            addr64: 0x1234_5678_9ABC_DEF0,
+            addr16: 0xffff,
+        }]
+    );
+    // FAIL応答
+    let mut reader = BufReader::new(io::Cursor::new("FAIL ER04\r\n"));
+    let mut writer = Vec::<u8>::new();
+    assert!(neighbor_table(&mut reader, &mut writer).is_err());
+}
+
+#[test]
+fn test11() {
+    let mut writer = Vec::<u8>::new();
+    send_line(&mut writer, "SKSREG S16 1C20").unwrap();
+    assert_eq!(writer, b"SKSREG S16 1C20\r\n");
+    // 改行や非ASCII文字を含むコマンドは送らない
+    let mut writer = Vec::<u8>::new();
+    for command in ["SKVER\r\n", "SKSETPWD C A\nSKRESET", "SKSETRBID 〇"] {
+        let e = send_line(&mut writer, command).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    }
+    assert!(writer.is_empty());
+}
+
+#[test]
+fn test12() {
+    // 一時的な書き込みエラーと一部だけの書き込みを繰り返す書き込み先
+    struct Flaky {
+        written: Vec<u8>,
+        errors: Vec<io::ErrorKind>,
+    }
+    impl io::Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if let Some(kind) = self.errors.pop() {
+                return Err(kind.into());
+            }
+            // 1度に3バイトまで
+            let n = buf.len().min(3);
+            self.written.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    let mut w = Flaky {
+        written: Vec::new(),
+        errors: vec![io::ErrorKind::Interrupted, io::ErrorKind::WouldBlock],
+    };
+    send(&mut w, b"SKVER\r\n").unwrap();
+    assert_eq!(w.written, b"SKVER\r\n");
+    // 一時的な書き込みエラーが続けば諦める
+    let mut w = Flaky {
+        written: Vec::new(),
+        errors: vec![io::ErrorKind::WouldBlock; SEND_MAX_RETRY as usize + 1],
+    };
+    let e = send(&mut w, b"SKVER\r\n").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::WouldBlock);
+    assert!(w.written.is_empty());
+    // 一時的でない書き込みエラーはすぐに返す
+    let mut w = Flaky {
+        written: Vec::new(),
+        errors: vec![io::ErrorKind::BrokenPipe],
+    };
+    let e = send(&mut w, b"SKVER\r\n").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+}
@@ -0,0 +1,70 @@
+// シリアルポート
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use serialport::{DataBits, SerialPort, StopBits};
+use std::time::Duration;
+
+/// 対応するボーレート
+pub const SUPPORTED_BAUD_RATES: [u32; 7] = [2400, 4800, 9600, 19200, 38400, 57600, 115200];
+
+/// ボーレートの既定値
+pub const DEFAULT_BAUD_RATE: u32 = 115200;
+
+/// 読み込みタイムアウトの既定値(ミリ秒)
+pub const DEFAULT_TIMEOUT_MS: u64 = 1000;
+
+/// ボーレートを解析する
+pub fn parse_baud_rate(s: &str) -> Result<u32, String> {
+    let baud = s.parse::<u32>().map_err(|e| e.to_string())?;
+    if SUPPORTED_BAUD_RATES.contains(&baud) {
+        Ok(baud)
+    } else {
+        Err(format!(
+            "unsupported baud rate {}, must be one of {:?}",
+            baud, SUPPORTED_BAUD_RATES
+        ))
+    }
+}
+
+/// シリアルポートの設定
+fn serial_builder(port_name: &str, baud: u32, timeout: Duration) -> serialport::SerialPortBuilder {
+    tracing::info!(
+        "open serial port \"{}\" ({} baud, timeout {:?})",
+        port_name,
+        baud,
+        timeout
+    );
+    serialport::new(port_name, baud)
+        .stop_bits(StopBits::One)
+        .data_bits(DataBits::Eight)
+        .timeout(timeout)
+}
+
+/// シリアルポートを開く
+pub fn open_serial(
+    port_name: &str,
+    baud: u32,
+    timeout: Duration,
+) -> Result<Box<dyn SerialPort>, serialport::Error> {
+    serial_builder(port_name, baud, timeout).open()
+}
+
+/// シリアルポートを開く
+/// 接続後に tokio_serial::SerialStream へ変換して非同期で読み書きできる
+#[cfg(unix)]
+pub fn open_serial_native(
+    port_name: &str,
+    baud: u32,
+    timeout: Duration,
+) -> Result<serialport::TTYPort, serialport::Error> {
+    serial_builder(port_name, baud, timeout).open_native()
+}
+
+#[test]
+fn test1() {
+    assert_eq!(parse_baud_rate("115200"), Ok(115200));
+    assert_eq!(parse_baud_rate("9600"), Ok(9600));
+    assert!(parse_baud_rate("12345").is_err());
+    assert!(parse_baud_rate("fast").is_err());
+}
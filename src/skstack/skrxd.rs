@@ -19,6 +19,8 @@ pub struct Epandesc {
     pub addr: u64,
     pub lqi: u8,
     pub pair_id: u32,
+    /// 2.4GHz/サブGHz両対応のドングルが返す Side(無ければ None)
+    pub side: Option<u8>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -28,17 +30,27 @@ pub struct Erxudp {
     pub sender_port: u16,      // 送信元UDPポート番号
     pub destination_port: u16, // 送信先UDPポート番号
     pub senderlla: u64,        // 送信元のMAC層アドレス
+    pub lqi: Option<u8>,       // 受信電波の品質(2.4GHz/サブGHz両対応のドングルだけが返す)
     pub secured: u8,           // 1:暗号化あり, 0:暗号化なし
     pub datalen: u16,          // 受信データ長
     pub data: Vec<u8>,         // 受信データ
 }
 
+/// 近隣キャッシュ(SKTABLE E)の項目
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Neighbor {
+    pub address: Ipv6Addr, // IPv6アドレス
+    pub addr64: u64,       // MAC層アドレス
+    pub addr16: u16,       // ショートアドレス
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SkRxD {
-    Event(Event),       // イベント受信
-    Epandesc(Epandesc), // EPANDESC受信
-    Erxudp(Erxudp),     // ERXUDP受信
-    Fail(u8),           // 失敗
-    Ok,                 // 成功
-    Void,               // 空行
+    Event(Event),                 // イベント受信
+    Epandesc(Epandesc),           // EPANDESC受信
+    Erxudp(Erxudp),               // ERXUDP受信
+    NeighborTable(Vec<Neighbor>), // ENEIGHBOR受信(近隣キャッシュ)
+    Fail(u8),                     // 失敗
+    Ok,                           // 成功
+    Void,                         // 空行
 }
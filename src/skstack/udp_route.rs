@@ -0,0 +1,116 @@
+// ERXUDPの送信先UDPポート番号ごとの扱い
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+
+/// UDPポート番号 0E1A = 3610 は Echonetliteメッセージ
+pub const ECHONETLITE_PORT: u16 = 0x0e1a;
+
+/// UDPポート番号 02CC = 716 は PANAメッセージ(RFC5191)
+pub const PANA_PORT: u16 = 0x02cc;
+
+/// 受信したUDPデーターの扱い
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UdpRoute {
+    /// Echonetliteフレームとして解読する
+    EchonetLite,
+    /// PANAメッセージ(SKSTACKが処理するので読み捨てる)
+    Pana,
+    /// データー部をそのまま指定のUDPアドレスに転送する
+    Forward(SocketAddr),
+}
+
+/// 送信先UDPポート番号と扱いの対応表
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UdpRoutes(BTreeMap<u16, UdpRoute>);
+
+impl Default for UdpRoutes {
+    fn default() -> Self {
+        Self(BTreeMap::from([
+            (ECHONETLITE_PORT, UdpRoute::EchonetLite),
+            (PANA_PORT, UdpRoute::Pana),
+        ]))
+    }
+}
+
+impl UdpRoutes {
+    /// 対応を追加する(既にあれば置き換える)
+    pub fn insert(&mut self, port: u16, route: UdpRoute) -> Option<UdpRoute> {
+        self.0.insert(port, route)
+    }
+
+    /// 送信先UDPポート番号の扱い(未知のポート番号なら None)
+    pub fn get(&self, port: u16) -> Option<&UdpRoute> {
+        self.0.get(&port)
+    }
+
+    /// "ポート番号=転送先" をカンマで区切った転送設定を追加する
+    /// ポート番号は10進数か 0x で始まる16進数
+    /// 例 "0x1234=127.0.0.1:5000,4661=[::1]:5001"
+    pub fn with_forwards(mut self, s: &str) -> Result<Self, String> {
+        for item in s.split(',').map(str::trim).filter(|a| !a.is_empty()) {
+            let (port, addr) = item
+                .split_once('=')
+                .ok_or(format!(r#""{item}" must be "port=address:port""#))?;
+            let port = port.trim();
+            let port = match port.strip_prefix("0x").or(port.strip_prefix("0X")) {
+                Some(hex) => u16::from_str_radix(hex, 16),
+                None => port.parse::<u16>(),
+            }
+            .map_err(|e| format!(r#"port "{port}" {e}"#))?;
+            if port == ECHONETLITE_PORT || port == PANA_PORT {
+                return Err(format!("port {port:#06x} cannot be forwarded"));
+            }
+            let addr = addr
+                .trim()
+                .parse::<SocketAddr>()
+                .map_err(|e| format!(r#"address "{addr}" {e}"#))?;
+            self.insert(port, UdpRoute::Forward(addr));
+        }
+        Ok(self)
+    }
+}
+
+#[test]
+fn test1() {
+    let routes = UdpRoutes::default();
+    assert_eq!(routes.get(0x0e1a), Some(&UdpRoute::EchonetLite));
+    assert_eq!(routes.get(0x02cc), Some(&UdpRoute::Pana));
+    assert_eq!(routes.get(0x1234), None);
+}
+
+#[test]
+fn test2() {
+    let routes = UdpRoutes::default()
+        .with_forwards("0x1234=127.0.0.1:5000, 4661=[::1]:5001,")
+        .unwrap();
+    assert_eq!(
+        routes.get(0x1234),
+        Some(&UdpRoute::Forward("127.0.0.1:5000".parse().unwrap()))
+    );
+    assert_eq!(
+        routes.get(4661),
+        Some(&UdpRoute::Forward("[::1]:5001".parse().unwrap()))
+    );
+    assert_eq!(routes.get(0x0e1a), Some(&UdpRoute::EchonetLite));
+    // 書式の誤り
+    assert!(UdpRoutes::default().with_forwards("0x1234").is_err());
+    assert!(
+        UdpRoutes::default()
+            .with_forwards("x=127.0.0.1:5000")
+            .is_err()
+    );
+    assert!(
+        UdpRoutes::default()
+            .with_forwards("4660=localhost")
+            .is_err()
+    );
+    // Echonetlite と PANA は転送できない
+    assert!(
+        UdpRoutes::default()
+            .with_forwards("0x0e1a=127.0.0.1:5000")
+            .is_err()
+    );
+}
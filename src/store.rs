@@ -0,0 +1,990 @@
+// 測定値の蓄積先(PostgreSQL / SQLite)
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use crate::connection_settings::{ConnectionSettings, active_meters};
+use crate::units::{Ampere, KiloWattHour, Watt};
+use chrono::{DateTime, Utc};
+use futures_util::stream::BoxStream;
+use futures_util::{StreamExt, TryStreamExt};
+use rust_decimal::Decimal;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use sqlx::{Postgres, QueryBuilder, Sqlite, Transaction, postgres::PgPool};
+use std::future::Future;
+use std::str::FromStr;
+
+/// 測定値を蓄積する
+/// meter_id は計測したスマートメーターの設定情報(settings.id)
+pub trait MeasurementStore {
+    /// 瞬時電力を蓄積する
+    fn insert_instant_power(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        watt: &Watt,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send;
+
+    /// 瞬時電流を蓄積する
+    fn insert_current(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        r: &Ampere,
+        t: &Option<Ampere>,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send;
+
+    /// 積算電力量を蓄積する
+    fn insert_cumulative(
+        &mut self,
+        meter_id: i64,
+        values: &[(DateTime<Utc>, KiloWattHour)],
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send;
+
+    /// 積算電力量(逆方向)を蓄積する
+    fn insert_reverse_cumulative(
+        &mut self,
+        meter_id: i64,
+        values: &[(DateTime<Utc>, KiloWattHour)],
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send;
+
+    /// 受信電波の品質(LQI)を蓄積する
+    fn insert_link_quality(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        lqi: u8,
+    ) -> impl Future<Output = Result<(), sqlx::Error>> + Send;
+}
+
+/// 積算電力量を1回の INSERT で蓄積する最大行数
+/// 1行あたり3つのバインドパラメーターを使うので
+/// PostgreSQL(65535個) と SQLite(古い版では999個) の上限を超えないようにまとめる
+const INSERT_BATCH_ROWS: usize = 300;
+
+impl MeasurementStore for Transaction<'_, Postgres> {
+    async fn insert_instant_power(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        watt: &Watt,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"INSERT INTO instant_epower ( meter_id, recorded_at, watt ) VALUES ( $1, $2, $3 )"#,
+            meter_id,
+            *recorded_at,
+            watt.0
+        )
+        .execute(&mut **self)
+        .await?;
+        Ok(())
+    }
+
+    async fn insert_current(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        r: &Ampere,
+        t: &Option<Ampere>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"INSERT INTO instant_current ( meter_id, recorded_at, r, t ) VALUES ( $1, $2, $3, $4 )"#,
+            meter_id,
+            *recorded_at,
+            r.0,
+            t.map(|t| t.0)
+        )
+        .execute(&mut **self)
+        .await?;
+        Ok(())
+    }
+
+    async fn insert_cumulative(
+        &mut self,
+        meter_id: i64,
+        values: &[(DateTime<Utc>, KiloWattHour)],
+    ) -> Result<(), sqlx::Error> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        for chunk in values.chunks(INSERT_BATCH_ROWS) {
+            let mut query_builder = QueryBuilder::new(
+                r#"INSERT INTO cumlative_amount_epower (meter_id, recorded_at, kwh)"#,
+            );
+            query_builder.push_values(chunk, |mut b, value| {
+                b.push_bind(meter_id)
+                    .push_bind(value.0)
+                    .push_bind(value.1.0);
+            });
+            query_builder.build().execute(&mut **self).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_reverse_cumulative(
+        &mut self,
+        meter_id: i64,
+        values: &[(DateTime<Utc>, KiloWattHour)],
+    ) -> Result<(), sqlx::Error> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        for chunk in values.chunks(INSERT_BATCH_ROWS) {
+            let mut query_builder = QueryBuilder::new(
+                r#"INSERT INTO reverse_cumlative_amount_epower (meter_id, recorded_at, kwh)"#,
+            );
+            query_builder.push_values(chunk, |mut b, value| {
+                b.push_bind(meter_id)
+                    .push_bind(value.0)
+                    .push_bind(value.1.0);
+            });
+            query_builder.build().execute(&mut **self).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_link_quality(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        lqi: u8,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"INSERT INTO link_quality ( meter_id, recorded_at, lqi ) VALUES ( $1, $2, $3 )"#,
+            meter_id,
+            *recorded_at,
+            lqi as i16
+        )
+        .execute(&mut **self)
+        .await?;
+        Ok(())
+    }
+}
+
+// SQLiteは NUMERIC 型を持たないので Decimal は文字列で蓄積する
+impl MeasurementStore for Transaction<'_, Sqlite> {
+    async fn insert_instant_power(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        watt: &Watt,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"INSERT INTO instant_epower ( meter_id, recorded_at, watt ) VALUES ( $1, $2, $3 )"#,
+        )
+        .bind(meter_id)
+        .bind(*recorded_at)
+        .bind(watt.to_string())
+        .execute(&mut **self)
+        .await?;
+        Ok(())
+    }
+
+    async fn insert_current(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        r: &Ampere,
+        t: &Option<Ampere>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(concat!(
+            r#"INSERT INTO instant_current ( meter_id, recorded_at, r, t )"#,
+            r#" VALUES ( $1, $2, $3, $4 )"#,
+        ))
+        .bind(meter_id)
+        .bind(*recorded_at)
+        .bind(r.to_string())
+        .bind(t.map(|t| t.to_string()))
+        .execute(&mut **self)
+        .await?;
+        Ok(())
+    }
+
+    async fn insert_cumulative(
+        &mut self,
+        meter_id: i64,
+        values: &[(DateTime<Utc>, KiloWattHour)],
+    ) -> Result<(), sqlx::Error> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        for chunk in values.chunks(INSERT_BATCH_ROWS) {
+            let mut query_builder = QueryBuilder::new(
+                r#"INSERT INTO cumlative_amount_epower (meter_id, recorded_at, kwh)"#,
+            );
+            query_builder.push_values(chunk, |mut b, value| {
+                b.push_bind(meter_id)
+                    .push_bind(value.0)
+                    .push_bind(value.1.to_string());
+            });
+            query_builder.build().execute(&mut **self).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_reverse_cumulative(
+        &mut self,
+        meter_id: i64,
+        values: &[(DateTime<Utc>, KiloWattHour)],
+    ) -> Result<(), sqlx::Error> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        for chunk in values.chunks(INSERT_BATCH_ROWS) {
+            let mut query_builder = QueryBuilder::new(
+                r#"INSERT INTO reverse_cumlative_amount_epower (meter_id, recorded_at, kwh)"#,
+            );
+            query_builder.push_values(chunk, |mut b, value| {
+                b.push_bind(meter_id)
+                    .push_bind(value.0)
+                    .push_bind(value.1.to_string());
+            });
+            query_builder.build().execute(&mut **self).await?;
+        }
+        Ok(())
+    }
+
+    async fn insert_link_quality(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        lqi: u8,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"INSERT INTO link_quality ( meter_id, recorded_at, lqi ) VALUES ( $1, $2, $3 )"#,
+        )
+        .bind(meter_id)
+        .bind(*recorded_at)
+        .bind(lqi)
+        .execute(&mut **self)
+        .await?;
+        Ok(())
+    }
+}
+
+/// 瞬時電流を1回の INSERT で蓄積する最大行数
+/// 1行あたり4つのバインドパラメーターを使う
+const INSERT_BATCH_ROWS_CURRENT: usize = INSERT_BATCH_ROWS * 3 / 4;
+
+/// 瞬時電力と瞬時電流を溜めておいて, まとめて蓄積する
+/// 蓄積する前に終了(電源断など)すると溜めておいた測定値は失われる
+#[derive(Debug, Default)]
+pub struct InstantBuffer {
+    /// (meter_id, 計測時刻, 瞬時電力)
+    instant_power: Vec<(i64, DateTime<Utc>, Watt)>,
+    /// (meter_id, 計測時刻, R相電流, T相電流)
+    current: Vec<(i64, DateTime<Utc>, Ampere, Option<Ampere>)>,
+}
+
+impl InstantBuffer {
+    /// 溜めている行数
+    pub fn len(&self) -> usize {
+        self.instant_power.len() + self.current.len()
+    }
+
+    /// 溜めていないか
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 溜めている測定値を1つのトランザクションで蓄積して空にする
+    /// 蓄積できなければ溜めたままにする(次の機会に蓄積する)
+    /// 蓄積した行数を返す
+    pub async fn flush(&mut self, db: &Database) -> Result<usize, sqlx::Error> {
+        if self.is_empty() {
+            return Ok(0);
+        }
+        match db.begin().await? {
+            DatabaseTransaction::Postgres(mut tx) => {
+                for chunk in self.instant_power.chunks(INSERT_BATCH_ROWS) {
+                    let mut query_builder = QueryBuilder::<Postgres>::new(
+                        r#"INSERT INTO instant_epower (meter_id, recorded_at, watt)"#,
+                    );
+                    query_builder.push_values(chunk, |mut b, value| {
+                        b.push_bind(value.0).push_bind(value.1).push_bind(value.2.0);
+                    });
+                    query_builder.build().execute(&mut *tx).await?;
+                }
+                for chunk in self.current.chunks(INSERT_BATCH_ROWS_CURRENT) {
+                    let mut query_builder = QueryBuilder::<Postgres>::new(
+                        r#"INSERT INTO instant_current (meter_id, recorded_at, r, t)"#,
+                    );
+                    query_builder.push_values(chunk, |mut b, value| {
+                        b.push_bind(value.0)
+                            .push_bind(value.1)
+                            .push_bind(value.2.0)
+                            .push_bind(value.3.map(|t| t.0));
+                    });
+                    query_builder.build().execute(&mut *tx).await?;
+                }
+                tx.commit().await?;
+            }
+            DatabaseTransaction::Sqlite(mut tx) => {
+                for chunk in self.instant_power.chunks(INSERT_BATCH_ROWS) {
+                    let mut query_builder = QueryBuilder::<Sqlite>::new(
+                        r#"INSERT INTO instant_epower (meter_id, recorded_at, watt)"#,
+                    );
+                    query_builder.push_values(chunk, |mut b, value| {
+                        b.push_bind(value.0)
+                            .push_bind(value.1)
+                            .push_bind(value.2.to_string());
+                    });
+                    query_builder.build().execute(&mut *tx).await?;
+                }
+                for chunk in self.current.chunks(INSERT_BATCH_ROWS_CURRENT) {
+                    let mut query_builder = QueryBuilder::<Sqlite>::new(
+                        r#"INSERT INTO instant_current (meter_id, recorded_at, r, t)"#,
+                    );
+                    query_builder.push_values(chunk, |mut b, value| {
+                        b.push_bind(value.0)
+                            .push_bind(value.1)
+                            .push_bind(value.2.to_string())
+                            .push_bind(value.3.map(|t| t.to_string()));
+                    });
+                    query_builder.build().execute(&mut *tx).await?;
+                }
+                tx.commit().await?;
+            }
+        }
+        let n = self.len();
+        self.instant_power.clear();
+        self.current.clear();
+        Ok(n)
+    }
+}
+
+/// 瞬時電力と瞬時電流は InstantBuffer に溜めて, それ以外はすぐに store に蓄積する
+pub struct Buffered<'a, S> {
+    pub store: &'a mut S,
+    pub buffer: &'a mut InstantBuffer,
+}
+
+impl<S: MeasurementStore + Send> MeasurementStore for Buffered<'_, S> {
+    async fn insert_instant_power(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        watt: &Watt,
+    ) -> Result<(), sqlx::Error> {
+        self.buffer
+            .instant_power
+            .push((meter_id, *recorded_at, *watt));
+        Ok(())
+    }
+
+    async fn insert_current(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        r: &Ampere,
+        t: &Option<Ampere>,
+    ) -> Result<(), sqlx::Error> {
+        self.buffer.current.push((meter_id, *recorded_at, *r, *t));
+        Ok(())
+    }
+
+    async fn insert_cumulative(
+        &mut self,
+        meter_id: i64,
+        values: &[(DateTime<Utc>, KiloWattHour)],
+    ) -> Result<(), sqlx::Error> {
+        self.store.insert_cumulative(meter_id, values).await
+    }
+
+    async fn insert_reverse_cumulative(
+        &mut self,
+        meter_id: i64,
+        values: &[(DateTime<Utc>, KiloWattHour)],
+    ) -> Result<(), sqlx::Error> {
+        self.store.insert_reverse_cumulative(meter_id, values).await
+    }
+
+    async fn insert_link_quality(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        lqi: u8,
+    ) -> Result<(), sqlx::Error> {
+        self.store
+            .insert_link_quality(meter_id, recorded_at, lqi)
+            .await
+    }
+}
+
+/// データーベース
+#[derive(Clone, Debug)]
+pub enum Database {
+    Postgres(PgPool),
+    Sqlite(SqlitePool),
+}
+
+/// SQLite のデーターベースURLか("sqlite:" で始まる)
+pub fn is_sqlite_url(database_url: &str) -> bool {
+    database_url.starts_with("sqlite:")
+}
+
+impl Database {
+    /// データーベースに接続する
+    /// "sqlite:" で始まるURLならSQLite、そうでなければPostgreSQL
+    /// PostgreSQL のテーブルは作らないので先に sqlx migrate run を実行しておくこと
+    pub async fn connect(database_url: &str) -> Result<Self, sqlx::Error> {
+        if is_sqlite_url(database_url) {
+            let options = SqliteConnectOptions::from_str(database_url)?.create_if_missing(true);
+            let pool = SqlitePool::connect_with(options).await?;
+            // SQLiteはテーブルをここで作る
+            sqlx::migrate!("./migrations_sqlite").run(&pool).await?;
+            Ok(Self::Sqlite(pool))
+        } else {
+            Ok(Self::Postgres(PgPool::connect(database_url).await?))
+        }
+    }
+
+    /// トランザクションを開始する
+    pub async fn begin(&self) -> Result<DatabaseTransaction<'static>, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => Ok(DatabaseTransaction::Postgres(pool.begin().await?)),
+            Self::Sqlite(pool) => Ok(DatabaseTransaction::Sqlite(pool.begin().await?)),
+        }
+    }
+
+    /// 接続を閉じる
+    pub async fn close(&self) {
+        match self {
+            Self::Postgres(pool) => pool.close().await,
+            Self::Sqlite(pool) => pool.close().await,
+        }
+    }
+
+    /// 設定情報をデーターベースから得る(最も新しいもの)
+    /// 新しさは connection_settings::recency と同じ(使い始めた時刻, id の順)
+    /// 設定情報が無ければ sqlx::Error::RowNotFound を返す
+    pub async fn read_settings(&self) -> Result<ConnectionSettings, sqlx::Error> {
+        let note: Option<sqlx::types::Json<ConnectionSettings>> = match self {
+            Self::Postgres(pool) => {
+                sqlx::query_scalar!(
+                    r#"SELECT note as "note: sqlx::types::Json<ConnectionSettings>" FROM settings ORDER BY (note->>'ActivatedAt')::TIMESTAMPTZ DESC NULLS LAST, id DESC LIMIT 1"#
+                )
+                .fetch_optional(pool)
+                .await?
+            }
+            Self::Sqlite(pool) => {
+                sqlx::query_scalar(
+                    "SELECT note FROM settings ORDER BY julianday(json_extract(note, '$.ActivatedAt')) DESC NULLS LAST, id DESC LIMIT 1",
+                )
+                .fetch_optional(pool)
+                .await?
+            }
+        };
+        note.map(|note| note.0).ok_or(sqlx::Error::RowNotFound)
+    }
+
+    /// すべての設定情報(settings.id, 接続情報)をデーターベースから得る
+    /// id の順に返す
+    pub async fn read_all_settings(&self) -> Result<Vec<(i64, ConnectionSettings)>, sqlx::Error> {
+        let rows: Vec<(i64, sqlx::types::Json<ConnectionSettings>)> = match self {
+            Self::Postgres(pool) => {
+                sqlx::query!(
+                    r#"SELECT id, note as "note: sqlx::types::Json<ConnectionSettings>" FROM settings ORDER BY id"#
+                )
+                .fetch_all(pool)
+                .await?
+                .into_iter()
+                .map(|rec| (rec.id, rec.note))
+                .collect()
+            }
+            Self::Sqlite(pool) => {
+                sqlx::query_as("SELECT id, note FROM settings ORDER BY id")
+                    .fetch_all(pool)
+                    .await?
+            }
+        };
+        Ok(rows.into_iter().map(|(id, note)| (id, note.0)).collect())
+    }
+
+    /// 指定した id の設定情報をデーターベースから得る
+    /// 設定情報が無ければ sqlx::Error::RowNotFound を返す
+    pub async fn read_settings_by_id(&self, id: i64) -> Result<ConnectionSettings, sqlx::Error> {
+        self.read_all_settings()
+            .await?
+            .into_iter()
+            .find_map(|(a, settings)| (a == id).then_some(settings))
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    /// 使用するスマートメーターの設定情報(settings.id, 接続情報)をデーターベースから得る
+    /// 新しい順に返す(connection_settings::active_meters)
+    pub async fn read_active_settings(
+        &self,
+    ) -> Result<Vec<(i64, ConnectionSettings)>, sqlx::Error> {
+        Ok(active_meters(self.read_all_settings().await?))
+    }
+
+    /// 最も新しい使用中のスマートメーターの settings.id をデーターベースから得る
+    /// 設定情報が無ければ sqlx::Error::RowNotFound を返す
+    pub async fn read_newest_meter_id(&self) -> Result<i64, sqlx::Error> {
+        self.read_active_settings()
+            .await?
+            .first()
+            .map(|(id, _)| *id)
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
+    /// 設定情報を使い始めた時刻を今にして最も新しい設定情報にする
+    /// 設定情報が無ければ sqlx::Error::RowNotFound を返す
+    pub async fn activate_settings(&self, id: i64) -> Result<ConnectionSettings, sqlx::Error> {
+        let mut settings = self.read_settings_by_id(id).await?;
+        settings.ActivatedAt = Some(Utc::now());
+        match self {
+            Self::Postgres(pool) => {
+                sqlx::query!(
+                    "UPDATE settings SET note = $1 WHERE id = $2",
+                    sqlx::types::Json(&settings) as _,
+                    id
+                )
+                .execute(pool)
+                .await?;
+            }
+            Self::Sqlite(pool) => {
+                sqlx::query("UPDATE settings SET note = $1 WHERE id = $2")
+                    .bind(sqlx::types::Json(&settings))
+                    .bind(id)
+                    .execute(pool)
+                    .await?;
+            }
+        }
+        Ok(settings)
+    }
+
+    /// 設定情報をデーターベースに蓄積する
+    pub async fn insert_settings(&self, settings: &ConnectionSettings) -> Result<i64, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => {
+                let rec = sqlx::query!(
+                    "INSERT INTO settings ( note ) VALUES ( $1 ) RETURNING id",
+                    sqlx::types::Json(settings) as _
+                )
+                .fetch_one(pool)
+                .await?;
+                Ok(rec.id)
+            }
+            Self::Sqlite(pool) => {
+                sqlx::query_scalar("INSERT INTO settings ( note ) VALUES ( $1 ) RETURNING id")
+                    .bind(sqlx::types::Json(settings))
+                    .fetch_one(pool)
+                    .await
+            }
+        }
+    }
+}
+
+/// 測定値を読み出す範囲
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadRange {
+    /// 最新から offset 件飛ばして遡った limit 件
+    Latest { limit: i64, offset: i64 },
+    /// 期間内のすべて(両端を含む)
+    Between(DateTime<Utc>, DateTime<Utc>),
+}
+
+impl ReadRange {
+    /// 期間を指定した場合はその期間のすべて、そうでなければ最新の limit 件
+    pub fn new(limit: i64, range: Option<(DateTime<Utc>, DateTime<Utc>)>) -> Self {
+        match range {
+            Some((from, to)) => Self::Between(from, to),
+            None => Self::Latest { limit, offset: 0 },
+        }
+    }
+}
+
+/// 測定値の1行(計測時刻, 値)
+pub type Reading = (DateTime<Utc>, Decimal);
+
+/// 瞬時電流の1行(計測時刻, R相電流, T相電流)
+pub type CurrentReading = (DateTime<Utc>, Decimal, Option<Decimal>);
+
+// 測定値の読み出しは PostgreSQL のみ
+// meter_id を指定しなければすべてのスマートメーター
+// どれも時刻の古い順に1行ずつ返す
+
+/// 瞬時電力をデーターベースから得る
+pub fn read_instant_power(
+    pool: &PgPool,
+    meter_id: Option<i64>,
+    range: ReadRange,
+) -> BoxStream<'_, Result<Reading, sqlx::Error>> {
+    match range {
+        ReadRange::Between(from, to) => sqlx::query!(
+            "SELECT recorded_at, watt FROM instant_epower WHERE recorded_at BETWEEN $1 AND $2 AND ($3::BIGINT IS NULL OR meter_id = $3) ORDER BY recorded_at, id",
+            from,
+            to,
+            meter_id
+        )
+        .fetch(pool)
+        .map_ok(|a| (a.recorded_at, a.watt))
+        .boxed(),
+        ReadRange::Latest { limit, offset } => sqlx::query!(
+            r#"SELECT recorded_at AS "recorded_at!", watt AS "watt!" FROM (SELECT id, recorded_at, watt FROM instant_epower WHERE ($3::BIGINT IS NULL OR meter_id = $3) ORDER BY recorded_at DESC, id DESC LIMIT $1 OFFSET $2) AS latest ORDER BY recorded_at, id"#,
+            limit,
+            offset,
+            meter_id
+        )
+        .fetch(pool)
+        .map_ok(|a| (a.recorded_at, a.watt))
+        .boxed(),
+    }
+}
+
+/// 瞬時電流をデーターベースから得る
+pub fn read_current(
+    pool: &PgPool,
+    meter_id: Option<i64>,
+    range: ReadRange,
+) -> BoxStream<'_, Result<CurrentReading, sqlx::Error>> {
+    match range {
+        ReadRange::Between(from, to) => sqlx::query!(
+            "SELECT recorded_at, r, t FROM instant_current WHERE recorded_at BETWEEN $1 AND $2 AND ($3::BIGINT IS NULL OR meter_id = $3) ORDER BY recorded_at, id",
+            from,
+            to,
+            meter_id
+        )
+        .fetch(pool)
+        .map_ok(|a| (a.recorded_at, a.r, a.t))
+        .boxed(),
+        ReadRange::Latest { limit, offset } => sqlx::query!(
+            r#"SELECT recorded_at AS "recorded_at!", r AS "r!", t FROM (SELECT id, recorded_at, r, t FROM instant_current WHERE ($3::BIGINT IS NULL OR meter_id = $3) ORDER BY recorded_at DESC, id DESC LIMIT $1 OFFSET $2) AS latest ORDER BY recorded_at, id"#,
+            limit,
+            offset,
+            meter_id
+        )
+        .fetch(pool)
+        .map_ok(|a| (a.recorded_at, a.r, a.t))
+        .boxed(),
+    }
+}
+
+/// 積算電力量(正方向計測値)をデーターベースから得る
+pub fn read_cumulative(
+    pool: &PgPool,
+    meter_id: Option<i64>,
+    range: ReadRange,
+) -> BoxStream<'_, Result<Reading, sqlx::Error>> {
+    match range {
+        ReadRange::Between(from, to) => sqlx::query!(
+            "SELECT recorded_at, kwh FROM cumlative_amount_epower WHERE recorded_at BETWEEN $1 AND $2 AND ($3::BIGINT IS NULL OR meter_id = $3) ORDER BY recorded_at, id",
+            from,
+            to,
+            meter_id
+        )
+        .fetch(pool)
+        .map_ok(|a| (a.recorded_at, a.kwh))
+        .boxed(),
+        ReadRange::Latest { limit, offset } => sqlx::query!(
+            r#"SELECT recorded_at AS "recorded_at!", kwh AS "kwh!" FROM (SELECT id, recorded_at, kwh FROM cumlative_amount_epower WHERE ($3::BIGINT IS NULL OR meter_id = $3) ORDER BY recorded_at DESC, id DESC LIMIT $1 OFFSET $2) AS latest ORDER BY recorded_at, id"#,
+            limit,
+            offset,
+            meter_id
+        )
+        .fetch(pool)
+        .map_ok(|a| (a.recorded_at, a.kwh))
+        .boxed(),
+    }
+}
+
+/// 積算電力量(逆方向計測値)をデーターベースから得る
+pub fn read_reverse_cumulative(
+    pool: &PgPool,
+    meter_id: Option<i64>,
+    range: ReadRange,
+) -> BoxStream<'_, Result<Reading, sqlx::Error>> {
+    match range {
+        ReadRange::Between(from, to) => sqlx::query!(
+            "SELECT recorded_at, kwh FROM reverse_cumlative_amount_epower WHERE recorded_at BETWEEN $1 AND $2 AND ($3::BIGINT IS NULL OR meter_id = $3) ORDER BY recorded_at, id",
+            from,
+            to,
+            meter_id
+        )
+        .fetch(pool)
+        .map_ok(|a| (a.recorded_at, a.kwh))
+        .boxed(),
+        ReadRange::Latest { limit, offset } => sqlx::query!(
+            r#"SELECT recorded_at AS "recorded_at!", kwh AS "kwh!" FROM (SELECT id, recorded_at, kwh FROM reverse_cumlative_amount_epower WHERE ($3::BIGINT IS NULL OR meter_id = $3) ORDER BY recorded_at DESC, id DESC LIMIT $1 OFFSET $2) AS latest ORDER BY recorded_at, id"#,
+            limit,
+            offset,
+            meter_id
+        )
+        .fetch(pool)
+        .map_ok(|a| (a.recorded_at, a.kwh))
+        .boxed(),
+    }
+}
+
+/// トランザクション
+pub enum DatabaseTransaction<'c> {
+    Postgres(Transaction<'c, Postgres>),
+    Sqlite(Transaction<'c, Sqlite>),
+}
+
+impl DatabaseTransaction<'_> {
+    /// コミットする
+    pub async fn commit(self) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Postgres(tx) => tx.commit().await,
+            Self::Sqlite(tx) => tx.commit().await,
+        }
+    }
+}
+
+impl MeasurementStore for DatabaseTransaction<'_> {
+    async fn insert_instant_power(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        watt: &Watt,
+    ) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Postgres(tx) => tx.insert_instant_power(meter_id, recorded_at, watt).await,
+            Self::Sqlite(tx) => tx.insert_instant_power(meter_id, recorded_at, watt).await,
+        }
+    }
+
+    async fn insert_current(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        r: &Ampere,
+        t: &Option<Ampere>,
+    ) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Postgres(tx) => tx.insert_current(meter_id, recorded_at, r, t).await,
+            Self::Sqlite(tx) => tx.insert_current(meter_id, recorded_at, r, t).await,
+        }
+    }
+
+    async fn insert_cumulative(
+        &mut self,
+        meter_id: i64,
+        values: &[(DateTime<Utc>, KiloWattHour)],
+    ) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Postgres(tx) => tx.insert_cumulative(meter_id, values).await,
+            Self::Sqlite(tx) => tx.insert_cumulative(meter_id, values).await,
+        }
+    }
+
+    async fn insert_reverse_cumulative(
+        &mut self,
+        meter_id: i64,
+        values: &[(DateTime<Utc>, KiloWattHour)],
+    ) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Postgres(tx) => tx.insert_reverse_cumulative(meter_id, values).await,
+            Self::Sqlite(tx) => tx.insert_reverse_cumulative(meter_id, values).await,
+        }
+    }
+
+    async fn insert_link_quality(
+        &mut self,
+        meter_id: i64,
+        recorded_at: &DateTime<Utc>,
+        lqi: u8,
+    ) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Postgres(tx) => tx.insert_link_quality(meter_id, recorded_at, lqi).await,
+            Self::Sqlite(tx) => tx.insert_link_quality(meter_id, recorded_at, lqi).await,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test1() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    let recorded_at = DateTime::parse_from_rfc3339("2025-07-13T12:00:00+09:00")
+        .unwrap()
+        .to_utc();
+    let mut tx = db.begin().await.unwrap();
+    tx.insert_instant_power(1, &recorded_at, &Watt(Decimal::new(123, 0)))
+        .await
+        .unwrap();
+    tx.insert_current(1, &recorded_at, &Ampere(Decimal::new(15, 1)), &None)
+        .await
+        .unwrap();
+    tx.insert_cumulative(1, &[(recorded_at, KiloWattHour(Decimal::new(1234567, 2)))])
+        .await
+        .unwrap();
+    tx.insert_reverse_cumulative(2, &[(recorded_at, KiloWattHour(Decimal::new(4321, 1)))])
+        .await
+        .unwrap();
+    tx.insert_link_quality(1, &recorded_at, 0xa4).await.unwrap();
+    tx.commit().await.unwrap();
+
+    let Database::Sqlite(pool) = &db else {
+        unreachable!()
+    };
+    let watt: String = sqlx::query_scalar("SELECT watt FROM instant_epower")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    assert_eq!(watt, "123");
+    let (r, t): (String, Option<String>) = sqlx::query_as("SELECT r, t FROM instant_current")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    assert_eq!((r.as_str(), t), ("1.5", None));
+    let kwh: String = sqlx::query_scalar("SELECT kwh FROM cumlative_amount_epower")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    assert_eq!(kwh, "12345.67");
+    let kwh: String = sqlx::query_scalar("SELECT kwh FROM reverse_cumlative_amount_epower")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    assert_eq!(kwh, "432.1");
+    let meter_id: i64 = sqlx::query_scalar("SELECT meter_id FROM reverse_cumlative_amount_epower")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    assert_eq!(meter_id, 2);
+    let lqi: i64 = sqlx::query_scalar("SELECT lqi FROM link_quality")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    assert_eq!(lqi, 0xa4);
+}
+
+#[tokio::test]
+async fn test2() {
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    let settings = |mac: &str| -> ConnectionSettings {
+        serde_json::from_str(&format!(
+            r#"{{"RouteBId":"0123456789ABCDEF0123456789ABCDEF","RouteBPassword":"PASSWORD1234","Channel":59,"MacAddress":"{mac}","PanId":43981,"Unit":"0.1 kwh","Coefficient":1}}"#
+        ))
+        .unwrap()
+    };
+    assert!(matches!(
+        db.read_settings().await,
+        Err(sqlx::Error::RowNotFound)
+    ));
+    let id1 = db.insert_settings(&settings("1")).await.unwrap();
+    let id2 = db.insert_settings(&settings("2")).await.unwrap();
+    // 最も新しいもの
+    assert_eq!(db.read_settings().await.unwrap().MacAddress, "2");
+    // 以前の設定情報を使う
+    let activated = db.activate_settings(id1).await.unwrap();
+    assert!(activated.ActivatedAt.is_some());
+    assert_eq!(db.read_settings().await.unwrap().MacAddress, "1");
+    // 使い始めた時刻の新しいもの
+    db.activate_settings(id2).await.unwrap();
+    assert_eq!(db.read_settings().await.unwrap().MacAddress, "2");
+    db.activate_settings(id1).await.unwrap();
+    assert_eq!(db.read_settings().await.unwrap().MacAddress, "1");
+    let active = db.read_active_settings().await.unwrap();
+    assert_eq!(active.iter().map(|(id, _)| *id).collect::<Vec<_>>(), [id1]);
+    let all = db.read_all_settings().await.unwrap();
+    assert_eq!(
+        all.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+        [id1, id2]
+    );
+    // id を指定する
+    assert_eq!(db.read_settings_by_id(id2).await.unwrap().MacAddress, "2");
+    // 無い設定情報
+    assert!(matches!(
+        db.activate_settings(id2 + 1).await,
+        Err(sqlx::Error::RowNotFound)
+    ));
+    assert!(matches!(
+        db.read_settings_by_id(id2 + 1).await,
+        Err(sqlx::Error::RowNotFound)
+    ));
+}
+
+#[tokio::test]
+async fn test3() {
+    // 1回の INSERT にまとめる行数を超える長い積算電力量履歴
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    let start = DateTime::parse_from_rfc3339("2025-07-01T00:00:00+09:00")
+        .unwrap()
+        .to_utc();
+    let n = INSERT_BATCH_ROWS * 2 + 1;
+    let values = (0..n)
+        .map(|i| {
+            (
+                start + chrono::TimeDelta::minutes(30 * i as i64),
+                KiloWattHour(Decimal::new(i as i64, 1)),
+            )
+        })
+        .collect::<Vec<(DateTime<Utc>, KiloWattHour)>>();
+    let mut tx = db.begin().await.unwrap();
+    tx.insert_cumulative(1, &values).await.unwrap();
+    tx.insert_reverse_cumulative(1, &values).await.unwrap();
+    tx.commit().await.unwrap();
+
+    let Database::Sqlite(pool) = &db else {
+        unreachable!()
+    };
+    for table in ["cumlative_amount_epower", "reverse_cumlative_amount_epower"] {
+        let (count, last): (i64, String) = sqlx::query_as(&format!(
+            "SELECT COUNT(*), (SELECT kwh FROM {table} ORDER BY recorded_at DESC LIMIT 1) FROM {table}"
+        ))
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert_eq!(count, n as i64);
+        assert_eq!(last, Decimal::new(n as i64 - 1, 1).to_string());
+    }
+}
+
+#[tokio::test]
+async fn test4() {
+    // 瞬時値は溜めておいて, まとめて蓄積する
+    let db = Database::connect("sqlite::memory:").await.unwrap();
+    let start = DateTime::parse_from_rfc3339("2025-07-13T12:00:00+09:00")
+        .unwrap()
+        .to_utc();
+    let mut buffer = InstantBuffer::default();
+    let n = INSERT_BATCH_ROWS + 1;
+    let mut tx = db.begin().await.unwrap();
+    for i in 0..n {
+        let recorded_at = start + chrono::TimeDelta::minutes(i as i64);
+        let mut store = Buffered {
+            store: &mut tx,
+            buffer: &mut buffer,
+        };
+        store
+            .insert_instant_power(1, &recorded_at, &Watt(Decimal::new(i as i64, 0)))
+            .await
+            .unwrap();
+        store
+            .insert_current(1, &recorded_at, &Ampere(Decimal::new(15, 1)), &None)
+            .await
+            .unwrap();
+    }
+    // 積算電力量はすぐに蓄積する
+    Buffered {
+        store: &mut tx,
+        buffer: &mut buffer,
+    }
+    .insert_cumulative(1, &[(start, KiloWattHour(Decimal::new(1234567, 2)))])
+    .await
+    .unwrap();
+    tx.commit().await.unwrap();
+    assert_eq!(buffer.len(), n * 2);
+
+    let Database::Sqlite(pool) = &db else {
+        unreachable!()
+    };
+    let count = |table: &'static str| async move {
+        sqlx::query_scalar::<_, i64>(&format!("SELECT COUNT(*) FROM {table}"))
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    };
+    assert_eq!(count("instant_epower").await, 0);
+    assert_eq!(count("cumlative_amount_epower").await, 1);
+
+    assert_eq!(buffer.flush(&db).await.unwrap(), n * 2);
+    assert!(buffer.is_empty());
+    assert_eq!(count("instant_epower").await, n as i64);
+    assert_eq!(count("instant_current").await, n as i64);
+    // 空なら何もしない
+    assert_eq!(buffer.flush(&db).await.unwrap(), 0);
+}
@@ -0,0 +1,34 @@
+// タイムゾーン
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use chrono_tz::Tz;
+use std::env;
+use std::str::FromStr;
+
+/// タイムゾーンを指定する環境変数名
+pub const TIMEZONE_ENV: &str = "TIMEZONE";
+
+/// タイムゾーンの既定値
+pub const DEFAULT_TIMEZONE: Tz = chrono_tz::Asia::Tokyo;
+
+/// タイムゾーン名(例 "Asia/Tokyo", "UTC")を解析する
+pub fn parse_timezone(s: &str) -> Result<Tz, String> {
+    Tz::from_str(s).map_err(|e| format!("invalid timezone \"{}\": {}", s, e))
+}
+
+/// 環境変数からタイムゾーンを得る
+/// 設定しなければ既定値(Asia/Tokyo)
+pub fn timezone_from_env() -> Result<Tz, String> {
+    match env::var(TIMEZONE_ENV) {
+        Ok(s) => parse_timezone(&s),
+        Err(_) => Ok(DEFAULT_TIMEZONE),
+    }
+}
+
+#[test]
+fn test1() {
+    assert_eq!(parse_timezone("Asia/Tokyo"), Ok(chrono_tz::Asia::Tokyo));
+    assert_eq!(parse_timezone("UTC"), Ok(chrono_tz::UTC));
+    assert!(parse_timezone("Mars/Olympus_Mons").is_err());
+}
@@ -0,0 +1,85 @@
+// 測定値の単位
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// 電力(W)
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug, Default)]
+#[serde(transparent)]
+pub struct Watt(pub Decimal);
+
+impl Watt {
+    /// 逆潮流(太陽光発電などで系統へ送り出している)なら true
+    /// 瞬時電力は符号付きで, プラスは買電, マイナスは逆潮流
+    pub fn is_export(&self) -> bool {
+        self.0.is_sign_negative() && !self.0.is_zero()
+    }
+
+    /// 向きを除いた大きさ(W)
+    /// (棒グラフの長さなどに使う, 収まらなければ u64::MAX)
+    pub fn magnitude(&self) -> u64 {
+        self.0.abs().to_u64().unwrap_or(u64::MAX)
+    }
+}
+
+/// 電流(A)
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug, Default)]
+#[serde(transparent)]
+pub struct Ampere(pub Decimal);
+
+/// 電力量(kWh)
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug, Default)]
+#[serde(transparent)]
+pub struct KiloWattHour(pub Decimal);
+
+// 表示は中の Decimal と同じ(幅などの書式指定もそのまま効く)
+
+impl fmt::Display for Watt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for Ampere {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for KiloWattHour {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[test]
+fn test1() {
+    // JSONでの表現は Decimal と同じ
+    let watt = Watt(Decimal::new(-120, 0));
+    assert_eq!(serde_json::to_string(&watt).unwrap(), r#""-120""#);
+    assert_eq!(
+        serde_json::from_str::<Ampere>("1.5").unwrap(),
+        Ampere(Decimal::new(15, 1))
+    );
+    assert_eq!(
+        format!("{:>6}", KiloWattHour(Decimal::new(123, 1))),
+        "  12.3"
+    );
+}
+
+#[test]
+fn test2() {
+    // マイナスは逆潮流
+    let export = Watt(Decimal::from(-500));
+    assert!(export.is_export());
+    assert_eq!(export.magnitude(), 500);
+    let import = Watt(Decimal::from(1234));
+    assert!(!import.is_export());
+    assert_eq!(import.magnitude(), 1234);
+    assert!(!Watt(Decimal::new(0, 0)).is_export());
+    assert!(!Watt(-Decimal::new(0, 0)).is_export());
+}
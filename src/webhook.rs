@@ -0,0 +1,217 @@
+// 出来事を Webhook で知らせる
+// SPDX-License-Identifier: MPL-2.0
+// SPDX-FileCopyrightText: 2025 Akihiro Yamamoto <github.com/ak1211>
+//
+use crate::alert::PowerAlertEvent;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// HTTP要求のタイムアウト
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 知らせる出来事の種類
+pub const EVENT_KINDS: [&str; 3] = ["session", "fault", "power_alert"];
+
+/// 知らせる出来事
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookEvent {
+    /// PANAセッションが切断された(再接続する)
+    SessionDisconnected,
+    /// 異常発生状態(0x88)を受信した
+    FaultStatus { meter_id: i64, fault: bool },
+    /// 瞬時電力がしきい値を越えた
+    PowerAlert {
+        meter_id: i64,
+        event: PowerAlertEvent,
+    },
+}
+
+impl WebhookEvent {
+    /// 出来事の種類(EVENT_KINDS のどれか)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WebhookEvent::SessionDisconnected => "session",
+            WebhookEvent::FaultStatus { .. } => "fault",
+            WebhookEvent::PowerAlert { .. } => "power_alert",
+        }
+    }
+
+    /// 人が読む文
+    pub fn message(&self) -> String {
+        match self {
+            WebhookEvent::SessionDisconnected => {
+                "PANA session disconnected, reconnecting".to_string()
+            }
+            WebhookEvent::FaultStatus { meter_id, fault } => format!(
+                "smart meter {meter_id} fault status: {}",
+                if *fault { "fault" } else { "no fault" }
+            ),
+            WebhookEvent::PowerAlert { meter_id, event } => format!(
+                "smart meter {meter_id} instant power {} W: {} -> {}",
+                event.watt, event.previous, event.level
+            ),
+        }
+    }
+
+    /// POST する JSON
+    /// Slack(text) と Discord(content) の Incoming Webhook にそのまま送れるようにする
+    pub fn payload(&self, at: &DateTime<Utc>) -> serde_json::Value {
+        let message = self.message();
+        let mut payload = json!({
+            "event": self.kind(),
+            "at": at.to_rfc3339(),
+            "text": message,
+            "content": message,
+        });
+        match self {
+            WebhookEvent::SessionDisconnected => {}
+            WebhookEvent::FaultStatus { meter_id, fault } => {
+                payload["meter_id"] = json!(meter_id);
+                payload["fault"] = json!(fault);
+            }
+            WebhookEvent::PowerAlert { meter_id, event } => {
+                payload["meter_id"] = json!(meter_id);
+                payload["level"] = json!(event.level);
+                payload["previous"] = json!(event.previous);
+                payload["watt"] = json!(event.watt);
+            }
+        }
+        payload
+    }
+}
+
+/// 送信先の設定
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// POST する URL
+    pub url: String,
+    /// 知らせる出来事の種類
+    pub events: BTreeSet<String>,
+}
+
+/// 知らせる出来事の種類をカンマで区切った設定を解析する
+/// 例 "session,power_alert"
+pub fn parse_events(s: &str) -> Result<BTreeSet<String>, String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+        .map(|a| {
+            if EVENT_KINDS.contains(&a) {
+                Ok(a.to_string())
+            } else {
+                Err(format!(
+                    r#"unknown event "{a}", must be one of {}"#,
+                    EVENT_KINDS.join(", ")
+                ))
+            }
+        })
+        .collect()
+}
+
+/// 送信タスクへの送信口と知らせる出来事の種類
+static NOTIFIER: OnceLock<(mpsc::UnboundedSender<WebhookEvent>, BTreeSet<String>)> =
+    OnceLock::new();
+
+/// 通知が有効か
+pub fn is_enabled() -> bool {
+    NOTIFIER.get().is_some()
+}
+
+/// 出来事を送信タスクに渡す
+/// 通知が有効でないか, 知らせる種類でなければ何もしない
+pub fn notify(event: WebhookEvent) {
+    if let Some((tx, events)) = NOTIFIER.get()
+        && events.contains(event.kind())
+        && tx.send(event).is_err()
+    {
+        tracing::warn!("webhook notifier is not running");
+    }
+}
+
+/// 送信タスクを開始する
+/// 送信に失敗してもログに出すだけで再送しない
+pub fn start(config: WebhookConfig) -> reqwest::Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+    let (tx, mut rx) = mpsc::unbounded_channel::<WebhookEvent>();
+    if NOTIFIER.set((tx, config.events.clone())).is_err() {
+        tracing::warn!("webhook notifier is already running");
+        return Ok(());
+    }
+    tracing::info!(
+        "webhook notifier started, events: {}",
+        config.events.iter().cloned().collect::<Vec<_>>().join(",")
+    );
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            let payload = event.payload(&Utc::now());
+            let sent = client
+                .post(&config.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(payload.to_string())
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+            match sent {
+                Ok(_) => tracing::debug!("webhook {} sent", event.kind()),
+                Err(e) => tracing::warn!("webhook {} failed, reason: {e}", event.kind()),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[test]
+fn test1() {
+    use crate::alert::PowerLevel;
+    use crate::units::Watt;
+    use rust_decimal::Decimal;
+    let at = DateTime::parse_from_rfc3339("2025-07-13T12:00:00Z")
+        .unwrap()
+        .to_utc();
+    let event = WebhookEvent::PowerAlert {
+        meter_id: 1,
+        event: PowerAlertEvent {
+            level: PowerLevel::High,
+            previous: PowerLevel::Normal,
+            watt: Watt(Decimal::from(3000)),
+        },
+    };
+    assert_eq!(
+        event.payload(&at),
+        json!({
+            "event": "power_alert",
+            "at": "2025-07-13T12:00:00+00:00",
+            "text": "smart meter 1 instant power 3000 W: normal -> high",
+            "content": "smart meter 1 instant power 3000 W: normal -> high",
+            "meter_id": 1,
+            "level": "high",
+            "previous": "normal",
+            "watt": "3000",
+        })
+    );
+    let event = WebhookEvent::FaultStatus {
+        meter_id: 2,
+        fault: true,
+    };
+    assert_eq!(event.payload(&at)["fault"], json!(true));
+    assert_eq!(
+        WebhookEvent::SessionDisconnected.payload(&at)["event"],
+        "session"
+    );
+}
+
+#[test]
+fn test2() {
+    assert_eq!(
+        parse_events(" session, power_alert,").unwrap(),
+        BTreeSet::from(["session".to_string(), "power_alert".to_string()])
+    );
+    assert!(parse_events("session,disk_full").is_err());
+    assert_eq!(parse_events("").unwrap(), BTreeSet::new());
+}